@@ -47,6 +47,7 @@ where
             pub use std::process;
             pub use std::os::raw;
             pub use std::ffi::CString;
+            pub use std::sync::atomic::{{AtomicUsize, Ordering}};
         }}
     "#
     )
@@ -114,7 +115,8 @@ where
     Ok(())
 }
 
-/// Creates the gl_guard function for opengl error checking
+/// Creates the gl_guard function for opengl error checking, along with the
+///  runtime switch that selects what it does when an error is caught
 fn write_gl_guard<W>(dest: &mut W) -> io::Result<()>
 where
     W: io::Write,
@@ -122,9 +124,45 @@ where
     writeln!(
         dest,
         r#"
+        /// What the error guard does after a GL call that raised an error
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum ErrorBehavior {{
+            /// Print the error, the debug log and a backtrace, then exit
+            Abort = 0,
+            /// Print the error and the debug log, then keep running
+            LogAndContinue = 1,
+            /// Clear the error flag silently
+            Ignore = 2,
+        }}
+
+        static ERROR_BEHAVIOR: __gl_imports::AtomicUsize = __gl_imports::AtomicUsize::new(ErrorBehavior::Abort as usize);
+
+        /// Selects what happens when a GL call raises an error
+        #[allow(dead_code)]
+        pub fn set_error_behavior(behavior: ErrorBehavior) {{
+            ERROR_BEHAVIOR.store(behavior as usize, __gl_imports::Ordering::Relaxed);
+        }}
+
+        /// Returns the currently selected error behavior
+        #[allow(dead_code)]
+        pub fn error_behavior() -> ErrorBehavior {{
+            match ERROR_BEHAVIOR.load(__gl_imports::Ordering::Relaxed) {{
+                0 => ErrorBehavior::Abort,
+                1 => ErrorBehavior::LogAndContinue,
+                _ => ErrorBehavior::Ignore,
+            }}
+        }}
+
+        // Only called from debug builds, see `write_fns`
+        #[allow(dead_code)]
         unsafe fn gl_guard(fn_name: &str, params: &str) {{
             let err = __gl_imports::mem::transmute::<_, extern "system" fn() -> u32> (storage::GetError.f)();
             if err != self::NO_ERROR {{
+                let behavior = error_behavior();
+                if behavior == ErrorBehavior::Ignore {{
+                    return;
+                }}
+
                 // Show generic info about the error
                 println!("[OpenGL] error @ gl{{}}({{}})", fn_name, params);
                 loop {{
@@ -179,6 +217,10 @@ where
                     }}
                 }}
 
+                if behavior == ErrorBehavior::LogAndContinue {{
+                    return;
+                }}
+
                 let mut bt = String::new();
                 let mut i = 0;
                 backtrace::trace(|frame| {{
@@ -225,7 +267,8 @@ where
 /// Creates the functions corresponding to the GL commands.
 ///
 /// The function calls the corresponding function pointer stored in the `storage` module created
-///  by `write_ptrs`.
+///  by `write_ptrs`. In debug builds it then checks for errors through `gl_guard`, which costs a
+///  glGetError round trip per call. The guard is compiled out of release builds.
 fn write_fns<W>(registry: &Registry, dest: &mut W) -> io::Result<()>
 where
     W: io::Write,
@@ -269,7 +312,7 @@ where
             typed_params = typed_params.join(", "),
             return_suffix = cmd.proto.ty,
             idents = idents.join(", "),
-            guard = if cmd.proto.ident != "GetError" { format!("gl_guard(\"{}\", {});", cmd.proto.ident, param_values) } else { String::from("") }
+            guard = if cmd.proto.ident != "GetError" { format!("#[cfg(debug_assertions)] {{ gl_guard(\"{}\", {}); }}", cmd.proto.ident, param_values) } else { String::from("") }
         ));
     }
 
@@ -434,7 +477,14 @@ fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let mut file = File::create(&Path::new(&out_dir).join("bindings.rs")).unwrap();
 
-    Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, [])
+    let extensions = [
+        // Core only since 4.6, but widely available as an extension
+        "GL_EXT_texture_filter_anisotropic",
+        // Compressed texture formats outside of core
+        "GL_EXT_texture_compression_s3tc",
+        "GL_KHR_texture_compression_astc_ldr",
+    ];
+    Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, extensions)
         .write_bindings(CustomGenerator, &mut file)
         .unwrap();
 }
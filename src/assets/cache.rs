@@ -0,0 +1,81 @@
+use super::{load, Image, Load, Model};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+//
+// Decoded assets keyed by their asset path. The first request for a path
+// loads and decodes it, later ones share the same data.
+//
+#[allow(dead_code)]
+pub struct AssetCache {
+    images: HashMap<PathBuf, Rc<Image>>,
+    models: HashMap<PathBuf, Rc<Model>>,
+}
+
+#[allow(dead_code)]
+impl AssetCache {
+    pub fn new() -> Self {
+        AssetCache {
+            images: HashMap::new(),
+            models: HashMap::new(),
+        }
+    }
+
+    pub fn get_image<P: AsRef<Path>>(&mut self, path: P) -> Result<Rc<Image>, String> {
+        let path = path.as_ref();
+        if let Some(img) = self.images.get(path) {
+            return Ok(img.clone());
+        }
+        let img = Rc::new(try!(Image::from_buf(try!(load(path)))));
+        self.images.insert(path.to_path_buf(), img.clone());
+        Ok(img)
+    }
+
+    pub fn get_model<P: AsRef<Path>>(&mut self, path: P) -> Result<Rc<Model>, String> {
+        let path = path.as_ref();
+        if let Some(model) = self.models.get(path) {
+            return Ok(model.clone());
+        }
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let model = Rc::new(try!(Model::from_buf_in(&mut try!(load(path)), base_dir)));
+        self.models.insert(path.to_path_buf(), model.clone());
+        Ok(model)
+    }
+
+    /// Drops the cached data of a path, so that the next request reloads it.
+    /// Handles given out earlier keep the old data alive
+    pub fn evict<P: AsRef<Path>>(&mut self, path: P) {
+        self.images.remove(path.as_ref());
+        self.models.remove(path.as_ref());
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.models.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assets::set_asset_roots;
+    use assets::tests::{png_bytes, quadrants, TempRoot};
+    use std::fs;
+
+    #[test]
+    fn image_is_decoded_once_and_shared() {
+        let png = png_bytes(&quadrants());
+        let root = TempRoot::new(&[("quadrants.png", &png)]);
+        set_asset_roots(&[&root.dir]);
+        let mut cache = AssetCache::new();
+        let first = cache.get_image("quadrants.png").unwrap();
+        // Any further decode would fail now
+        fs::remove_file(root.dir.join("quadrants.png")).unwrap();
+        let second = cache.get_image("quadrants.png").unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(second.dimensions(), (4, 4));
+        cache.evict("quadrants.png");
+        assert!(cache.get_image("quadrants.png").is_err());
+    }
+}
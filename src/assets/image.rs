@@ -1,14 +1,168 @@
 use super::Load;
 use image;
+use image::imageops;
+pub use image::DynamicImage as NativeImage;
 pub use image::RgbaImage as Image;
+pub use image::{FilterType, ImageFormat};
+use std::fs::File;
 use std::io::BufRead;
+use std::path::Path;
 
+/// Row order of decoded pixel data
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    /// First row is the bottom one, as glTexImage2D expects. Image files
+    /// store the top row first, so loading flips them vertically
+    BottomLeft,
+    /// First row is the top one, as stored in the file
+    TopLeft,
+}
+
+/// Loads with a bottom left origin, ready to be uploaded as a texture
 impl Load for Image {
+    fn from_buf<B: BufRead>(buf: B) -> Result<Self, String> {
+        from_buf_oriented(buf, Origin::BottomLeft)
+    }
+}
+
+/// Decodes to RGBA keeping the rows in the requested order, use
+/// `Origin::TopLeft` for data that isn't sampled as a GL texture
+pub fn from_buf_oriented<B: BufRead>(mut buf: B, origin: Origin) -> Result<Image, String> {
+    let mut data = Vec::new();
+    let _bytes_read = try!(buf.read_to_end(&mut data).map_err(|e| e.to_string()));
+    let img = try!(image::load_from_memory(&data).map_err(|e| e.to_string()));
+    Ok(oriented(img, origin).to_rgba())
+}
+
+fn oriented(img: NativeImage, origin: Origin) -> NativeImage {
+    match origin {
+        Origin::BottomLeft => img.flipv(),
+        Origin::TopLeft => img,
+    }
+}
+
+/// Loads keeping the source channel layout instead of expanding to RGBA
+impl Load for NativeImage {
     fn from_buf<B: BufRead>(mut buf: B) -> Result<Self, String> {
         let mut data = Vec::new();
         let _bytes_read = try!(buf.read_to_end(&mut data).map_err(|e| e.to_string()));
-        let mut img = try!(image::load_from_memory(&data).map_err(|e| e.to_string()));
-        img = img.flipv();
-        Ok(img.to_rgba())
+        let img = try!(image::load_from_memory(&data).map_err(|e| e.to_string()));
+        Ok(oriented(img, Origin::BottomLeft))
+    }
+}
+
+/// Decodes image data of a known format, skipping format detection
+#[allow(dead_code)]
+pub fn load_with_format<B: BufRead>(
+    mut buf: B,
+    format: ImageFormat,
+    origin: Origin,
+) -> Result<Image, String> {
+    let mut data = Vec::new();
+    let _bytes_read = try!(buf.read_to_end(&mut data).map_err(|e| e.to_string()));
+    let img = try!(
+        image::load_from_memory_with_format(&data, format).map_err(|e| e.to_string())
+    );
+    Ok(oriented(img, origin).to_rgba())
+}
+
+#[allow(dead_code)]
+pub fn resize(img: &Image, width: u32, height: u32, filter: FilterType) -> Image {
+    imageops::resize(img, width, height, filter)
+}
+
+/// Successively halved copies of `img` down to 1x1, starting with the
+/// image itself, e.g. for `Texture::from_mip_levels`
+#[allow(dead_code)]
+pub fn generate_mipmap_chain(img: &Image) -> Vec<Image> {
+    let mut levels = vec![img.clone()];
+    loop {
+        let next = {
+            let prev = &levels[levels.len() - 1];
+            let (w, h) = prev.dimensions();
+            if w == 1 && h == 1 {
+                break;
+            }
+            resize(prev, (w / 2).max(1), (h / 2).max(1), FilterType::Triangle)
+        };
+        levels.push(next);
+    }
+    levels
+}
+
+/// Writes `img` to a PNG file at `path` (not an asset path). `origin` tells
+/// the row order of `img`, files always store the top row first
+#[allow(dead_code)]
+pub fn save_png<P: AsRef<Path>>(img: &Image, path: P, origin: Origin) -> Result<(), String> {
+    let (width, height) = img.dimensions();
+    let file = try!(File::create(path).map_err(|e| e.to_string()));
+    let encoder = image::png::PNGEncoder::new(file);
+    let result = match origin {
+        Origin::BottomLeft => {
+            let flipped = imageops::flip_vertical(img);
+            encoder.encode(&flipped, width, height, image::ColorType::RGBA(8))
+        }
+        Origin::TopLeft => encoder.encode(img, width, height, image::ColorType::RGBA(8)),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assets::tests::{png_bytes, quadrants, TempRoot};
+    use std::fs;
+
+    #[test]
+    fn png_decodes_and_resizes() {
+        let png = png_bytes(&quadrants());
+        let img = from_buf_oriented(&png[..], Origin::TopLeft).unwrap();
+        assert_eq!(img.dimensions(), (4, 4));
+        let small = resize(&img, 2, 2, FilterType::Nearest);
+        assert_eq!(small.dimensions(), (2, 2));
+        assert_eq!(small.get_pixel(0, 0).data, [255, 0, 0, 255]);
+        assert_eq!(small.get_pixel(1, 1).data, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn origin_decides_where_top_left_pixel_lands() {
+        let png = png_bytes(&quadrants());
+        let red = [255, 0, 0, 255];
+        let top_left = from_buf_oriented(&png[..], Origin::TopLeft).unwrap();
+        assert_eq!(top_left.get_pixel(0, 0).data, red);
+        let bottom_left = from_buf_oriented(&png[..], Origin::BottomLeft).unwrap();
+        assert_eq!(bottom_left.get_pixel(0, 3).data, red);
+        assert_eq!(bottom_left.get_pixel(0, 0).data, [0, 0, 255, 255]);
+        // Plain loads are ready for texture upload
+        let loaded = Image::from_buf(&png[..]).unwrap();
+        assert_eq!(loaded.into_raw(), bottom_left.into_raw());
+    }
+
+    #[test]
+    fn bottom_left_frames_are_saved_top_row_first() {
+        let (red, green, blue, white) = (
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+        );
+        // Rows as read_color returns them, bottom row first
+        let pixels = [red, green, blue, white].concat();
+        let frame = Image::from_raw(2, 2, pixels).unwrap();
+        let root = TempRoot::new(&[]);
+        let path = root.dir.join("frame.png");
+        save_png(&frame, &path, Origin::BottomLeft).unwrap();
+        let saved = fs::read(&path).unwrap();
+        let img = from_buf_oriented(&saved[..], Origin::TopLeft).unwrap();
+        assert_eq!(img.get_pixel(0, 0).data, blue);
+        assert_eq!(img.get_pixel(1, 0).data, white);
+        assert_eq!(img.get_pixel(0, 1).data, red);
+        assert_eq!(img.get_pixel(1, 1).data, green);
+        // Top-left images are stored as they are
+        save_png(&frame, &path, Origin::TopLeft).unwrap();
+        let saved = fs::read(&path).unwrap();
+        let img = from_buf_oriented(&saved[..], Origin::TopLeft).unwrap();
+        assert_eq!(img.into_raw(), frame.into_raw());
     }
 }
@@ -0,0 +1,151 @@
+use super::Load;
+use std::io::BufRead;
+
+const KTX_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX_HEADER_SIZE: usize = 64;
+
+//
+// Block compressed texture data read from a KTX (version 1) container.
+// Only single 2D textures are supported, without array layers or cubemap
+// faces. The format is kept as the GL internal format the file declares.
+//
+#[allow(dead_code)]
+pub struct CompressedImage {
+    pub gl_internal_format: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Data of each mip level, base level first
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Load for CompressedImage {
+    fn from_buf<B: BufRead>(buf: B) -> Result<Self, String> {
+        let data = try!(Vec::<u8>::from_buf(buf));
+        parse_ktx(&data)
+    }
+}
+
+fn parse_ktx(data: &[u8]) -> Result<CompressedImage, String> {
+    if data.len() < KTX_HEADER_SIZE || data[..12] != KTX_IDENTIFIER {
+        return Err("Not a KTX file".to_string());
+    }
+    // Written as 0x04030201 in the byte order of the file
+    let big_endian = match &data[12..16] {
+        [0x01, 0x02, 0x03, 0x04] => false,
+        [0x04, 0x03, 0x02, 0x01] => true,
+        _ => return Err("Invalid KTX endianness marker".to_string()),
+    };
+    let read_u32 = |offset: usize| -> Result<u32, String> {
+        let b = try!(data
+            .get(offset..offset + 4)
+            .ok_or("Truncated KTX file".to_string()));
+        let bytes = [b[0], b[1], b[2], b[3]];
+        Ok(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let gl_type = try!(read_u32(16));
+    let gl_internal_format = try!(read_u32(28));
+    let width = try!(read_u32(36));
+    let height = try!(read_u32(40)).max(1);
+    let depth = try!(read_u32(44));
+    let array_elements = try!(read_u32(48));
+    let faces = try!(read_u32(52));
+    let mip_levels = try!(read_u32(56)).max(1);
+    let key_value_bytes = try!(read_u32(60)) as usize;
+    if gl_type != 0 {
+        return Err("KTX file holds uncompressed data".to_string());
+    }
+    if depth > 1 || array_elements > 0 || faces != 1 {
+        return Err("Only 2D KTX textures are supported".to_string());
+    }
+
+    let mut offset = KTX_HEADER_SIZE + key_value_bytes;
+    let mut levels = Vec::with_capacity(mip_levels as usize);
+    for _ in 0..mip_levels {
+        let size = try!(read_u32(offset)) as usize;
+        offset += 4;
+        let level = try!(data
+            .get(offset..offset + size)
+            .ok_or("Truncated KTX file".to_string()));
+        levels.push(level.to_vec());
+        // Levels are padded to 4 bytes
+        offset += (size + 3) & !3;
+    }
+    Ok(CompressedImage {
+        gl_internal_format,
+        width,
+        height,
+        levels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETC2_RGB8: u32 = 0x9274;
+
+    // 8x4 ETC2 texture with two mip levels, written in either byte order
+    fn ktx_file(big_endian: bool) -> Vec<u8> {
+        let mut data = KTX_IDENTIFIER.to_vec();
+        let push = |data: &mut Vec<u8>, v: u32| {
+            let bytes = if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            };
+            data.extend_from_slice(&bytes);
+        };
+        // Endianness, glType, glTypeSize, glFormat, glInternalFormat,
+        // glBaseInternalFormat, size, array elements, faces, mip levels
+        let header = [0x04030201, 0, 1, 0, ETC2_RGB8, 0x1907, 8, 4, 0, 0, 1, 2];
+        for &v in &header {
+            push(&mut data, v);
+        }
+        // 4 bytes of key/value data, skipped
+        push(&mut data, 4);
+        data.extend_from_slice(b"key\0");
+        push(&mut data, 16);
+        data.extend_from_slice(&[1; 16]);
+        push(&mut data, 8);
+        data.extend_from_slice(&[2; 8]);
+        data
+    }
+
+    #[test]
+    fn parses_header_and_levels() {
+        let image = parse_ktx(&ktx_file(false)).unwrap();
+        assert_eq!(image.gl_internal_format, ETC2_RGB8);
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(image.levels, vec![vec![1; 16], vec![2; 8]]);
+    }
+
+    #[test]
+    fn parses_swapped_endianness() {
+        let image = parse_ktx(&ktx_file(true)).unwrap();
+        assert_eq!(image.gl_internal_format, ETC2_RGB8);
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(image.levels.len(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_identifier() {
+        let mut data = ktx_file(false);
+        data[1] = b'X';
+        assert!(parse_ktx(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_levels() {
+        let mut data = ktx_file(false);
+        let len = data.len();
+        data.truncate(len - 1);
+        assert!(parse_ktx(&data).is_err());
+    }
+}
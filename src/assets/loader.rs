@@ -0,0 +1,93 @@
+use super::{load, Load};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+//
+// Background asset loading. Reading and decoding run on a thread of their
+// own, while the caller keeps polling the returned handle from the main loop.
+// Only CPU side work happens off the main thread, turning the result into
+// GPU resources (e.g. Texture::from_image) must still be done by the thread
+// owning the GL context once the handle yields it.
+//
+#[allow(dead_code)]
+pub struct LoadHandle<T> {
+    receiver: Receiver<Result<T, String>>,
+    done: bool,
+}
+
+#[allow(dead_code)]
+impl<T> LoadHandle<T> {
+    /// Polls for the decoded asset without blocking. Returns None while the
+    /// load is still running and the result exactly once when it is over
+    pub fn try_get(&mut self) -> Option<Result<T, String>> {
+        if self.done {
+            return None;
+        }
+        let result = match self.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("Loader thread died".to_string()),
+        };
+        self.done = true;
+        Some(result)
+    }
+
+    /// Blocks until the load is over
+    pub fn wait(mut self) -> Result<T, String> {
+        if self.done {
+            return Err("Result was already taken".to_string());
+        }
+        self.done = true;
+        match self.receiver.recv() {
+            Ok(result) => result,
+            Err(_) => Err("Loader thread died".to_string()),
+        }
+    }
+}
+
+/// Starts loading and decoding the asset at `path` on a worker thread
+#[allow(dead_code)]
+pub fn load_async<T, P>(path: P) -> LoadHandle<T>
+where
+    T: Load + Send + 'static,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().to_path_buf();
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let result = load(&path).and_then(T::from_buf);
+        // The handle may be gone already, nobody wants the result then
+        let _ = sender.send(result);
+    });
+    LoadHandle {
+        receiver,
+        done: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assets::tests::{png_bytes, quadrants, TempRoot};
+    use assets::{set_asset_roots, Image};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn decoded_image_becomes_available() {
+        let png = png_bytes(&quadrants());
+        let root = TempRoot::new(&[("quadrants.png", &png)]);
+        set_asset_roots(&[&root.dir]);
+        let mut handle = load_async::<Image, _>("quadrants.png");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let img = loop {
+            if let Some(result) = handle.try_get() {
+                break result.unwrap();
+            }
+            assert!(Instant::now() < deadline, "load did not finish in time");
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(img.dimensions(), (4, 4));
+        assert!(handle.try_get().is_none());
+    }
+}
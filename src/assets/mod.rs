@@ -1,10 +1,19 @@
+pub mod cache;
 pub mod image;
+pub mod ktx;
+pub mod loader;
 pub mod model;
 
 pub use self::image::*;
 pub use self::model::*;
-use std::path::Path;
 use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Environment variable overriding the default asset root. May hold several
+/// roots, separated like PATH entries
+pub const ASSET_DIR_VAR: &str = "CHARGE_ASSET_DIR";
 
 pub trait Load
 where
@@ -13,13 +22,78 @@ where
     fn from_buf<B: BufRead>(buf: B) -> Result<Self, String>;
 }
 
+/// Raw file contents
+impl Load for Vec<u8> {
+    fn from_buf<B: BufRead>(mut buf: B) -> Result<Self, String> {
+        let mut data = Vec::new();
+        let _bytes_read = try!(buf.read_to_end(&mut data).map_err(|e| e.to_string()));
+        Ok(data)
+    }
+}
+
+/// Text assets, like shader sources or level files. Fails on invalid UTF-8
+impl Load for String {
+    fn from_buf<B: BufRead>(buf: B) -> Result<Self, String> {
+        let data = try!(Vec::<u8>::from_buf(buf));
+        String::from_utf8(data).map_err(|e| e.to_string())
+    }
+}
+
+lazy_static! {
+    // Roots set through set_asset_roots, None to use the defaults. Shared
+    // with the loader threads
+    static ref ASSET_ROOTS: Mutex<Option<Vec<PathBuf>>> = Mutex::new(None);
+}
+
+/// Replaces the directories assets are searched in, in order of priority
+#[allow(dead_code)]
+pub fn set_asset_roots<P: AsRef<Path>>(roots: &[P]) {
+    let roots = roots.iter().map(|r| r.as_ref().to_path_buf()).collect();
+    *ASSET_ROOTS.lock().unwrap() = Some(roots);
+}
+
+/// The directories assets are searched in. Unless set explicitly, these are
+/// the roots listed in CHARGE_ASSET_DIR, or `assets` when it isn't set
+pub fn asset_roots() -> Vec<PathBuf> {
+    use std::env;
+
+    if let Some(roots) = ASSET_ROOTS.lock().unwrap().as_ref() {
+        return roots.clone();
+    }
+    match env::var_os(ASSET_DIR_VAR) {
+        Some(dirs) => env::split_paths(&dirs).collect(),
+        None => vec![PathBuf::from("assets")],
+    }
+}
+
+// Full path of an asset under the first root that has it
+#[cfg(not(target_os = "android"))]
+fn find<P: AsRef<Path>>(path: P) -> Result<PathBuf, String> {
+    let roots = asset_roots();
+    match roots.iter().map(|r| r.join(&path)).find(|p| p.exists()) {
+        Some(fullpath) => Ok(fullpath),
+        None => {
+            let searched = roots
+                .iter()
+                .map(|r| r.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(format!(
+                "Asset {} not found in [{}]",
+                path.as_ref().display(),
+                searched
+            ))
+        }
+    }
+}
+
 #[cfg(not(target_os = "android"))]
 pub fn load<P: AsRef<Path>>(path: P) -> Result<Box<BufRead>, String> {
     use std::fs::File;
     use std::io::BufReader;
 
-    let fullpath = Path::new("assets").join(&path);
-    let file = try!(File::open(&fullpath).map_err(|e| e.to_string()));
+    let fullpath = try!(find(&path));
+    let file = try!(File::open(&fullpath).map_err(|e| format!("{}: {}", fullpath.display(), e)));
     let reader = BufReader::new(file);
     Ok(Box::new(reader))
 }
@@ -33,3 +107,122 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Box<BufRead>, String> {
     let buf = try!(android_glue::load_asset(fullpath).or(Err(format!("Could not load asset {}", fullpath))));
     Ok(Box::new(Cursor::new(buf)))
 }
+
+/// Last modification time of an asset, when the platform can tell
+#[cfg(not(target_os = "android"))]
+pub fn modified<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    use std::fs;
+
+    let fullpath = match find(&path) {
+        Ok(fullpath) => fullpath,
+        Err(_) => return None,
+    };
+    fs::metadata(&fullpath).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(target_os = "android")]
+pub fn modified<P: AsRef<Path>>(_path: P) -> Option<SystemTime> {
+    // Packaged assets never change
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::MutexGuard;
+    use std::{env, process};
+
+    lazy_static! {
+        // The asset roots are global, tests that set them run one at a time
+        static ref ROOTS_IN_USE: Mutex<()> = Mutex::new(());
+    }
+
+    /// Scratch directory holding the given files, for tests that load
+    /// assets from disk. The default asset roots are restored on drop
+    pub struct TempRoot {
+        pub dir: PathBuf,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl TempRoot {
+        pub fn new(files: &[(&str, &[u8])]) -> TempRoot {
+            static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+            let lock = ROOTS_IN_USE.lock().unwrap_or_else(|e| e.into_inner());
+            let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+            let dir = env::temp_dir().join(format!("charge-assets-{}-{}", process::id(), id));
+            fs::create_dir_all(&dir).unwrap();
+            for &(name, data) in files {
+                fs::write(dir.join(name), data).unwrap();
+            }
+            TempRoot { dir, _lock: lock }
+        }
+    }
+
+    /// PNG file contents of `img`, top row first
+    pub fn png_bytes(img: &Image) -> Vec<u8> {
+        let (width, height) = img.dimensions();
+        let mut data = Vec::new();
+        ::image::png::PNGEncoder::new(&mut data)
+            .encode(img, width, height, ::image::ColorType::RGBA(8))
+            .unwrap();
+        data
+    }
+
+    /// 4x4 image with a solid color in each 2x2 quadrant, red in the top left
+    pub fn quadrants() -> Image {
+        Image::from_fn(4, 4, |x, y| match (x < 2, y < 2) {
+            (true, true) => ::image::Rgba([255, 0, 0, 255]),
+            (false, true) => ::image::Rgba([0, 255, 0, 255]),
+            (true, false) => ::image::Rgba([0, 0, 255, 255]),
+            (false, false) => ::image::Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    impl Drop for TempRoot {
+        fn drop(&mut self) {
+            *ASSET_ROOTS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn bytes_load_unchanged() {
+        let data: &[u8] = &[0, 159, 146, 150, 255];
+        assert_eq!(Vec::<u8>::from_buf(data).unwrap(), data);
+    }
+
+    #[test]
+    fn text_loads_as_utf8() {
+        let data = "héllo wörld".as_bytes();
+        assert_eq!(String::from_buf(data).unwrap(), "héllo wörld");
+    }
+
+    #[test]
+    fn invalid_utf8_text_fails() {
+        let data: &[u8] = &[b'o', b'k', 0xC3, 0x28];
+        assert!(String::from_buf(data).is_err());
+    }
+
+    #[test]
+    fn loads_from_set_root() {
+        let root = TempRoot::new(&[("hello.txt", b"hello")]);
+        set_asset_roots(&[&root.dir]);
+        assert_eq!(asset_roots(), vec![root.dir.clone()]);
+        let text = String::from_buf(load("hello.txt").unwrap()).unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn missing_asset_lists_searched_roots() {
+        let root = TempRoot::new(&[]);
+        let roots = [root.dir.join("first"), root.dir.join("second")];
+        set_asset_roots(&roots);
+        let err = load("missing.txt").err().unwrap();
+        assert!(err.contains("missing.txt"));
+        for r in &roots {
+            assert!(err.contains(&r.display().to_string()), "{}", err);
+        }
+    }
+}
@@ -1,30 +1,293 @@
+use super::{load, Load};
+use base64;
+use gltf;
 use math::*;
+use std::collections::{HashMap, VecDeque};
 use std::io::BufRead;
+use std::path::Path;
 use tobj;
 
+const DEGENERATE_EPSILON: f32 = 1.0e-8;
+
 pub struct Shape {
     pub name: String,
     pub positions: Vec<f32>,
     pub normals: Vec<f32>,
     pub texcoords: Vec<f32>,
+    /// Per vertex (x, y, z, w) tangents, w being the bitangent handedness
+    pub tangents: Vec<f32>,
     pub indices: Vec<u32>,
+    /// Per vertex indices of the 4 joints skinning it
+    pub joints: Vec<u16>,
+    /// Per vertex weights of the 4 joints skinning it
+    pub weights: Vec<f32>,
+    pub material: Option<ShapeMaterial>,
+}
+
+/// Surface description of a shape as given by its source file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeMaterial {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    /// Diffuse texture path, relative to the model's directory
+    pub diffuse_texture: Option<String>,
 }
 
 pub struct Model {
     pub shapes: Vec<Shape>,
 }
 
+pub struct LoadOptions {
+    /// Flip triangles whose winding disagrees with their neighbours
+    pub repair_winding: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            repair_winding: false,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Shape {
+    /// Number of triangles that would need flipping to make every connected
+    /// patch of the shape coherently oriented
+    pub fn check_winding(&self) -> usize {
+        self.winding_flips().iter().filter(|f| **f).count()
+    }
+
+    /// Flips inconsistently wound triangles, returning how many were flipped
+    pub fn repair_winding(&mut self) -> usize {
+        let flips = self.winding_flips();
+        let mut nflipped = 0;
+        for (tri, flip) in self.indices.chunks_mut(3).zip(flips) {
+            if flip && tri.len() == 3 {
+                tri.swap(1, 2);
+                nflipped += 1;
+            }
+        }
+        nflipped
+    }
+
+    //
+    // Walks the triangle adjacency graph and decides per triangle whether it
+    // must be flipped to agree with its neighbours. Two triangles sharing an
+    // edge are coherently oriented when they traverse that edge in opposite
+    // directions. Vertices are welded by position first, so that attribute
+    // seams don't break adjacency. Within each connected patch the majority
+    // orientation is kept.
+    //
+    fn winding_flips(&self) -> Vec<bool> {
+        // Weld vertices by their exact position
+        let mut welded = HashMap::new();
+        let vid: Vec<usize> = self
+            .positions
+            .chunks(3)
+            .map(|p| {
+                let key = (p[0].to_bits(), p[1].to_bits(), p[2].to_bits());
+                let next = welded.len();
+                *welded.entry(key).or_insert(next)
+            }).collect();
+
+        // Undirected edge -> (triangle, traversed in ascending order)
+        let tris: Vec<[usize; 3]> = self
+            .indices
+            .chunks(3)
+            .filter(|c| c.len() == 3)
+            .map(|c| [vid[c[0] as usize], vid[c[1] as usize], vid[c[2] as usize]])
+            .collect();
+        let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::new();
+        for (t, tri) in tris.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                if a != b {
+                    edges
+                        .entry((a.min(b), a.max(b)))
+                        .or_insert_with(Vec::new)
+                        .push((t, a < b));
+                }
+            }
+        }
+
+        let mut flips = vec![false; tris.len()];
+        let mut visited = vec![false; tris.len()];
+        let mut queue = VecDeque::new();
+        for seed in 0..tris.len() {
+            if visited[seed] {
+                continue;
+            }
+            visited[seed] = true;
+            queue.push_back(seed);
+            let mut patch = Vec::new();
+            while let Some(t) = queue.pop_front() {
+                patch.push(t);
+                let tri = tris[t];
+                for k in 0..3 {
+                    let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                    if a == b {
+                        continue;
+                    }
+                    let fwd = (a < b) != flips[t];
+                    for &(n, nfwd) in &edges[&(a.min(b), a.max(b))] {
+                        if visited[n] {
+                            continue;
+                        }
+                        visited[n] = true;
+                        // Neighbour must run the shared edge the other way
+                        flips[n] = nfwd == fwd;
+                        queue.push_back(n);
+                    }
+                }
+            }
+            // Keep whichever orientation most of the patch already has
+            let nflips = patch.iter().filter(|t| flips[**t]).count();
+            if nflips * 2 > patch.len() {
+                for t in patch {
+                    flips[t] = !flips[t];
+                }
+            }
+        }
+        flips
+    }
+}
+
+impl Shape {
+    #[allow(dead_code)]
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_positions(&self.positions)
+    }
+
+    // Appends the vertices and triangles of `other`, dropping the
+    // attributes only one of the two shapes has
+    fn append(&mut self, mut other: Shape) {
+        let base = (self.positions.len() / 3) as u32;
+        fn merge(a: &mut Vec<f32>, b: &mut Vec<f32>) {
+            if a.is_empty() || b.is_empty() {
+                a.clear();
+            } else {
+                a.append(b);
+            }
+        }
+        merge(&mut self.normals, &mut other.normals);
+        merge(&mut self.texcoords, &mut other.texcoords);
+        merge(&mut self.tangents, &mut other.tangents);
+        merge(&mut self.weights, &mut other.weights);
+        if self.joints.is_empty() || other.joints.is_empty() {
+            self.joints.clear();
+        } else {
+            self.joints.append(&mut other.joints);
+        }
+        self.positions.append(&mut other.positions);
+        self.indices.extend(other.indices.iter().map(|i| base + i));
+    }
+}
+
 impl Model {
+    /// Bounds of all shapes combined
+    #[allow(dead_code)]
+    pub fn aabb(&self) -> Aabb {
+        self.shapes
+            .iter()
+            .fold(Aabb::empty(), |aabb, s| aabb.union(&s.aabb()))
+    }
+
     pub fn from_buf<B: BufRead>(reader: &mut B) -> Result<Model, String> {
-        Self::load(reader)
+        Self::load(reader, &LoadOptions::default(), Path::new(""))
+    }
+
+    /// Parses an OBJ file, loading the material libraries it references
+    /// relative to `base_dir` (usually the asset directory of the file itself)
+    #[allow(dead_code)]
+    pub fn from_buf_in<B: BufRead>(reader: &mut B, base_dir: &Path) -> Result<Model, String> {
+        Self::load(reader, &LoadOptions::default(), base_dir)
     }
 
-    fn load<B: BufRead>(reader: &mut B) -> Result<Model, String> {
-        let mut m = try!(Self::load_obj(reader));
+    /// Collapses the shapes sharing a material into one, so that a model is
+    /// drawn with a single draw call per material. Shapes without a material
+    /// are merged together. A vertex attribute is kept only if every merged
+    /// shape has it
+    #[allow(dead_code)]
+    pub fn merge_by_material(self) -> Model {
+        let mut merged: Vec<Shape> = Vec::new();
+        for s in self.shapes {
+            let pos = merged.iter().position(|m| m.material == s.material);
+            match pos {
+                Some(i) => merged[i].append(s),
+                None => merged.push(s),
+            }
+        }
+        Model { shapes: merged }
+    }
+
+    /// Merges all shapes into a single planar position/normal/uv0 vertex
+    /// buffer, returning it along with its vertex count and the indices
+    /// rebased onto the merged vertices
+    pub fn flatten(self) -> (Vec<f32>, usize, Vec<u32>) {
+        let (mut vpos, mut vnrm, mut vuv0, mut indc) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut nvrt = 0;
+        for mut s in self.shapes {
+            // Shape indices are local, rebase them past the previous shapes
+            let base = nvrt as u32;
+            nvrt += s.positions.len() / 3;
+            vpos.append(&mut s.positions);
+            vnrm.append(&mut s.normals);
+            vuv0.append(&mut s.texcoords);
+            // Keep the planar streams aligned for shapes without uvs
+            vuv0.resize(nvrt * 2, 0.0);
+            indc.extend(s.indices.iter().map(|i| base + i));
+        }
+        let mut vdata = Vec::new();
+        vdata.append(&mut vpos);
+        vdata.append(&mut vnrm);
+        vdata.append(&mut vuv0);
+        (vdata, nvrt, indc)
+    }
+
+    #[allow(dead_code)]
+    pub fn from_buf_with_options<B: BufRead>(
+        reader: &mut B,
+        options: &LoadOptions,
+    ) -> Result<Model, String> {
+        Self::load(reader, options, Path::new(""))
+    }
+
+    /// Parses an OBJ file incrementally, yielding each shape as soon as it
+    /// has been read so its CPU side data can be uploaded and dropped early
+    #[allow(dead_code)]
+    pub fn stream<B: BufRead>(reader: B) -> ObjStream<B> {
+        ObjStream::new(reader)
+    }
+
+    fn load<B: BufRead>(
+        reader: &mut B,
+        options: &LoadOptions,
+        base_dir: &Path,
+    ) -> Result<Model, String> {
+        let mut m = try!(Self::load_obj(reader, base_dir));
         for shape in m.shapes.iter_mut() {
+            if options.repair_winding {
+                let nflipped = shape.repair_winding();
+                if nflipped > 0 {
+                    println!(
+                        "Flipped {} inconsistently wound triangles in shape {}",
+                        nflipped, shape.name
+                    );
+                }
+            }
             if shape.normals.len() == 0 {
                 shape.normals = Self::generate_normals(&mut shape.positions, &mut shape.indices);
             }
+            if shape.tangents.len() == 0 && shape.texcoords.len() != 0 {
+                shape.tangents = Self::generate_tangents(
+                    &shape.positions,
+                    &shape.normals,
+                    &shape.texcoords,
+                    &shape.indices,
+                );
+            }
         }
         Ok(m)
     }
@@ -39,7 +302,14 @@ impl Model {
                 .collect::<Vec<_>>();
             let e1 = v[1] - v[0];
             let e2 = v[2] - v[0];
-            let nm = e1.cross(&e2).normalize();
+            let cross = e1.cross(&e2);
+            let len = cross.norm();
+            // Degenerate triangles have no meaningful orientation and
+            // normalizing them would spread NaNs to every shared vertex
+            if len < DEGENERATE_EPSILON {
+                continue;
+            }
+            let nm = cross / len;
             tr.iter().for_each(|i| {
                 normals[*i..(*i + 3)]
                     .iter_mut()
@@ -49,29 +319,625 @@ impl Model {
         }
 
         for nm in normals.chunks_mut(3) {
-            let nnm = make_vec3(nm).normalize();
+            let acc = make_vec3(nm);
+            let len = acc.norm();
+            // Vertices only touched by degenerate triangles, or whose face
+            // normals cancel out, fall back to pointing up
+            let nnm = if len < DEGENERATE_EPSILON {
+                vec3(0.0, 1.0, 0.0)
+            } else {
+                acc / len
+            };
             nm.copy_from_slice(&[nnm.x, nnm.y, nnm.z]);
         }
         normals
     }
 
-    fn load_obj<B: BufRead>(reader: &mut B) -> Result<Model, String> {
+    // Per triangle tangents from the uv gradients, accumulated per vertex and
+    // made orthogonal to the vertex normal (Gram-Schmidt). The w component
+    // stores the handedness so that bitangent = cross(normal, tangent) * w.
+    fn generate_tangents(
+        positions: &[f32],
+        normals: &[f32],
+        texcoords: &[f32],
+        indices: &[u32],
+    ) -> Vec<f32> {
+        let nverts = positions.len() / 3;
+        let mut tan = vec![vec3(0.0, 0.0, 0.0); nverts];
+        let mut btan = vec![vec3(0.0, 0.0, 0.0); nverts];
+        for c in indices.chunks(3) {
+            if c.len() < 3 {
+                continue;
+            }
+            let i = [c[0] as usize, c[1] as usize, c[2] as usize];
+            let p = i
+                .iter()
+                .map(|i| make_vec3(&positions[(i * 3)..(i * 3 + 3)]))
+                .collect::<Vec<_>>();
+            let t = i
+                .iter()
+                .map(|i| make_vec2(&texcoords[(i * 2)..(i * 2 + 2)]))
+                .collect::<Vec<_>>();
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (d1, d2) = (t[1] - t[0], t[2] - t[0]);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            // Degenerate uv mapping, no well defined tangent space
+            if det.abs() < DEGENERATE_EPSILON {
+                continue;
+            }
+            let r = 1.0 / det;
+            let sdir = (e1 * d2.y - e2 * d1.y) * r;
+            let tdir = (e2 * d1.x - e1 * d2.x) * r;
+            for v in i.iter() {
+                tan[*v] += sdir;
+                btan[*v] += tdir;
+            }
+        }
+
+        let mut tangents = Vec::with_capacity(nverts * 4);
+        for v in 0..nverts {
+            let n = make_vec3(&normals[(v * 3)..(v * 3 + 3)]);
+            let t = tan[v] - n * n.dot(&tan[v]);
+            let len = t.norm();
+            let t = if len < DEGENERATE_EPSILON {
+                // Any direction perpendicular to the normal will do
+                let axis = if n.x.abs() < 0.9 {
+                    vec3(1.0, 0.0, 0.0)
+                } else {
+                    vec3(0.0, 1.0, 0.0)
+                };
+                normalize(&(axis - n * n.dot(&axis)))
+            } else {
+                t / len
+            };
+            let w = if n.cross(&t).dot(&btan[v]) < 0.0 { -1.0 } else { 1.0 };
+            tangents.extend_from_slice(&[t.x, t.y, t.z, w]);
+        }
+        tangents
+    }
+
+    fn load_obj<B: BufRead>(reader: &mut B, base_dir: &Path) -> Result<Model, String> {
         let obj = try!(
-            tobj::load_obj_buf(reader, |_| Err(tobj::LoadError::MaterialParseError))
+            tobj::load_obj_buf(reader, |p| Self::load_mtl(&base_dir.join(p)))
                 .map_err(|e| e.to_string())
         );
-        let (models, _) = obj;
+        let (models, materials) = obj;
         let mut model = Model { shapes: Vec::new() };
         for m in models {
+            let material = m.mesh.material_id.and_then(|i| materials.get(i)).map(|mat| {
+                ShapeMaterial {
+                    name: mat.name.clone(),
+                    diffuse: mat.diffuse,
+                    diffuse_texture: if mat.diffuse_texture.is_empty() {
+                        None
+                    } else {
+                        Some(mat.diffuse_texture.clone())
+                    },
+                }
+            });
             let shape = Shape {
                 name: m.name,
                 positions: m.mesh.positions,
                 normals: m.mesh.normals,
                 texcoords: m.mesh.texcoords,
+                tangents: Vec::new(),
                 indices: m.mesh.indices,
+                joints: Vec::new(),
+                weights: Vec::new(),
+                material,
             };
             model.shapes.push(shape);
         }
         Ok(model)
     }
+
+    // A missing material library only costs the material info,
+    // the geometry is still usable
+    fn load_mtl(path: &Path) -> tobj::MTLLoadResult {
+        match load(path) {
+            Ok(mut reader) => tobj::load_mtl_buf(&mut reader),
+            Err(e) => {
+                println!("Could not load material library {:?}: {}", path, e);
+                Ok((Vec::new(), HashMap::new()))
+            }
+        }
+    }
+}
+
+//
+// glTF 2.0 loading. Both the JSON (.gltf) and the binary container (.glb)
+// forms are accepted. Every triangle primitive reachable from the default
+// scene becomes a Shape, with node transforms baked into its vertices.
+// Joint indices and weights are read, while materials, skin hierarchies
+// and animations are ignored.
+//
+impl Load for Model {
+    fn from_buf<B: BufRead>(mut buf: B) -> Result<Self, String> {
+        Model::from_buf(&mut buf)
+    }
+}
+
+impl Model {
+    /// Parses a glTF/GLB file, resolving external buffer URIs relative
+    /// to the assets root
+    #[allow(dead_code)]
+    pub fn from_gltf_buf<B: BufRead>(reader: &mut B) -> Result<Model, String> {
+        Self::from_gltf_buf_in(reader, Path::new(""))
+    }
+
+    /// Parses a glTF/GLB file, resolving external buffer URIs relative to
+    /// `base_dir` (usually the asset directory of the file itself)
+    #[allow(dead_code)]
+    pub fn from_gltf_buf_in<B: BufRead>(reader: &mut B, base_dir: &Path) -> Result<Model, String> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data).map_err(|e| e.to_string()));
+        let gltf::Gltf { document, blob } =
+            try!(gltf::Gltf::from_slice(&data).map_err(|e| e.to_string()));
+
+        // Resolve buffer contents
+        let mut blob = blob;
+        let mut buffers = Vec::new();
+        for buffer in document.buffers() {
+            let mut contents = match buffer.source() {
+                gltf::buffer::Source::Bin => {
+                    try!(blob.take().ok_or("Missing GLB binary chunk".to_string()))
+                }
+                gltf::buffer::Source::Uri(uri) => try!(Self::load_gltf_uri(uri, base_dir)),
+            };
+            if contents.len() < buffer.length() {
+                return Err(format!("Buffer {} is truncated", buffer.index()));
+            }
+            // GLB binary chunks may carry trailing padding
+            contents.truncate(buffer.length());
+            buffers.push(contents);
+        }
+
+        // Walk the node hierarchy accumulating transforms
+        let mut model = Model { shapes: Vec::new() };
+        let identity = Mat4::identity();
+        let scene = document.default_scene().or(document.scenes().next());
+        let mut stack: Vec<(gltf::Node, Mat4)> = match scene {
+            Some(scene) => scene.nodes().map(|n| (n, identity)).collect(),
+            None => {
+                // Scene-less files are just a collection of meshes
+                for mesh in document.meshes() {
+                    try!(Self::load_gltf_mesh(&mut model, &mesh, &identity, &buffers));
+                }
+                Vec::new()
+            }
+        };
+        while let Some((node, parent)) = stack.pop() {
+            let world = parent * Mat4::from(node.transform().matrix());
+            if let Some(mesh) = node.mesh() {
+                try!(Self::load_gltf_mesh(&mut model, &mesh, &world, &buffers));
+            }
+            stack.extend(node.children().map(|c| (c, world)));
+        }
+        Ok(model)
+    }
+
+    fn load_gltf_uri(uri: &str, base_dir: &Path) -> Result<Vec<u8>, String> {
+        const DATA_PREFIX: &str = "data:";
+        if uri.starts_with(DATA_PREFIX) {
+            // Embedded base64 payload
+            let payload = match uri.find(";base64,") {
+                Some(i) => &uri[(i + 8)..],
+                None => return Err("Unsupported data URI encoding".to_string()),
+            };
+            return base64::decode(payload).map_err(|e| e.to_string());
+        }
+        let mut reader = try!(load(base_dir.join(uri)));
+        let mut contents = Vec::new();
+        try!(reader.read_to_end(&mut contents).map_err(|e| e.to_string()));
+        Ok(contents)
+    }
+
+    fn load_gltf_mesh(
+        model: &mut Model,
+        mesh: &gltf::Mesh,
+        world: &Mat4,
+        buffers: &[Vec<u8>],
+    ) -> Result<(), String> {
+        let nmm = mat4_to_mat3(&inverse_transpose(*world));
+        for (i, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                return Err(format!(
+                    "Unsupported primitive mode {:?} in mesh {}",
+                    primitive.mode(),
+                    mesh.index()
+                ));
+            }
+            let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
+            let read_positions = reader.read_positions();
+            let mut positions = Vec::new();
+            for p in try!(read_positions.ok_or("Primitive without positions".to_string())) {
+                let wp = world * vec4(p[0], p[1], p[2], 1.0);
+                positions.extend_from_slice(&[wp.x, wp.y, wp.z]);
+            }
+            let mut normals = Vec::new();
+            if let Some(iter) = reader.read_normals() {
+                for n in iter {
+                    let wn = normalize(&(nmm * vec3(n[0], n[1], n[2])));
+                    normals.extend_from_slice(&[wn.x, wn.y, wn.z]);
+                }
+            }
+            let mut texcoords = Vec::new();
+            if let Some(iter) = reader.read_tex_coords(0) {
+                for t in iter.into_f32() {
+                    // glTF puts the uv origin at the top left, images get
+                    // flipped on load so use the bottom left like OBJ
+                    texcoords.extend_from_slice(&[t[0], 1.0 - t[1]]);
+                }
+            }
+            let mut tangents = Vec::new();
+            if let Some(iter) = reader.read_tangents() {
+                for t in iter {
+                    let wt = normalize(&(mat4_to_mat3(world) * vec3(t[0], t[1], t[2])));
+                    // Flipping v above mirrors the bitangent
+                    tangents.extend_from_slice(&[wt.x, wt.y, wt.z, -t[3]]);
+                }
+            }
+            let mut joints = Vec::new();
+            let mut weights = Vec::new();
+            if let (Some(j), Some(w)) = (reader.read_joints(0), reader.read_weights(0)) {
+                for j in j.into_u16() {
+                    joints.extend_from_slice(&j);
+                }
+                for w in w.into_f32() {
+                    weights.extend_from_slice(&w);
+                }
+            }
+            let mut indices = match reader.read_indices() {
+                Some(iter) => iter.into_u32().collect(),
+                None => (0..(positions.len() / 3) as u32).collect::<Vec<_>>(),
+            };
+            // Mirroring transforms flip the winding
+            if determinant(world) < 0.0 {
+                for tri in indices.chunks_mut(3) {
+                    if tri.len() == 3 {
+                        tri.swap(1, 2);
+                    }
+                }
+            }
+            if normals.is_empty() {
+                normals = Self::generate_normals(&mut positions, &mut indices);
+            }
+            if tangents.is_empty() && !texcoords.is_empty() {
+                tangents = Self::generate_tangents(&positions, &normals, &texcoords, &indices);
+            }
+            model.shapes.push(Shape {
+                name: format!("{}.{}", mesh.name().unwrap_or("mesh"), i),
+                positions,
+                normals,
+                texcoords,
+                tangents,
+                indices,
+                joints,
+                weights,
+                material: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+//
+// Incremental OBJ parser. Only the shared vertex attribute pools are kept
+// for the whole file (faces may reference any previously declared vertex),
+// while the per-shape vertex/index data is handed out one shape at a time.
+// Materials are ignored.
+//
+pub struct ObjStream<B: BufRead> {
+    reader: B,
+    positions: Vec<[f32; 3]>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    current: ShapeBuilder,
+    done: bool,
+}
+
+struct ShapeBuilder {
+    shape: Shape,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    num_verts: usize,
+}
+
+impl ShapeBuilder {
+    fn new(name: String) -> Self {
+        ShapeBuilder {
+            shape: Shape {
+                name,
+                positions: Vec::new(),
+                normals: Vec::new(),
+                texcoords: Vec::new(),
+                tangents: Vec::new(),
+                indices: Vec::new(),
+                joints: Vec::new(),
+                weights: Vec::new(),
+                material: None,
+            },
+            vertex_map: HashMap::new(),
+            num_verts: 0,
+        }
+    }
+
+    fn finish(self) -> Shape {
+        let mut shape = self.shape;
+        // Attributes are only kept if every vertex of the shape has them
+        if shape.texcoords.len() != self.num_verts * 2 {
+            shape.texcoords.clear();
+        }
+        if shape.normals.len() != self.num_verts * 3 {
+            shape.normals.clear();
+        }
+        if shape.normals.len() == 0 {
+            shape.normals = Model::generate_normals(&mut shape.positions, &mut shape.indices);
+        }
+        if shape.texcoords.len() != 0 {
+            shape.tangents = Model::generate_tangents(
+                &shape.positions,
+                &shape.normals,
+                &shape.texcoords,
+                &shape.indices,
+            );
+        }
+        shape
+    }
+}
+
+impl<B: BufRead> ObjStream<B> {
+    fn new(reader: B) -> Self {
+        ObjStream {
+            reader,
+            positions: Vec::new(),
+            texcoords: Vec::new(),
+            normals: Vec::new(),
+            current: ShapeBuilder::new(String::from("unnamed_object")),
+            done: false,
+        }
+    }
+
+    fn parse_floats(args: &[&str], out: &mut [f32]) -> Result<(), String> {
+        if args.len() < out.len() {
+            return Err(String::from("Missing vertex attribute components"));
+        }
+        for (o, a) in out.iter_mut().zip(args) {
+            *o = try!(a.parse::<f32>().map_err(|e| e.to_string()));
+        }
+        Ok(())
+    }
+
+    // Resolves a 1-based (or negative, relative to the end) OBJ index
+    fn resolve_index(idx: &str, count: usize) -> Result<usize, String> {
+        let i = try!(idx.parse::<isize>().map_err(|e| e.to_string()));
+        let r = if i < 0 { count as isize + i } else { i - 1 };
+        if r < 0 || r as usize >= count {
+            return Err(format!("Face index {} out of range", i));
+        }
+        Ok(r as usize)
+    }
+
+    fn face_vertex(&mut self, vert: &str) -> Result<u32, String> {
+        let mut parts = vert.split('/');
+        let v = try!(Self::resolve_index(
+            parts.next().unwrap_or(""),
+            self.positions.len()
+        ));
+        let t = match parts.next() {
+            Some(t) if !t.is_empty() => Some(try!(Self::resolve_index(t, self.texcoords.len()))),
+            _ => None,
+        };
+        let n = match parts.next() {
+            Some(n) if !n.is_empty() => Some(try!(Self::resolve_index(n, self.normals.len()))),
+            _ => None,
+        };
+
+        let key = (v, t, n);
+        if let Some(idx) = self.current.vertex_map.get(&key) {
+            return Ok(*idx);
+        }
+        let cur = &mut self.current;
+        let idx = cur.num_verts as u32;
+        cur.shape.positions.extend_from_slice(&self.positions[v]);
+        if let Some(t) = t {
+            cur.shape.texcoords.extend_from_slice(&self.texcoords[t]);
+        }
+        if let Some(n) = n {
+            cur.shape.normals.extend_from_slice(&self.normals[n]);
+        }
+        cur.num_verts += 1;
+        cur.vertex_map.insert(key, idx);
+        Ok(idx)
+    }
+
+    // Starts a new shape, returning the previous one if it had any faces
+    fn begin_shape(&mut self, name: String) -> Option<Shape> {
+        if self.current.shape.indices.is_empty() {
+            self.current.shape.name = name;
+            return None;
+        }
+        let prev = ::std::mem::replace(&mut self.current, ShapeBuilder::new(name));
+        Some(prev.finish())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<Option<Shape>, String> {
+        let mut words = line.split_whitespace();
+        let tag = match words.next() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let args: Vec<&str> = words.collect();
+        match tag {
+            "v" => {
+                let mut p = [0.0; 3];
+                try!(Self::parse_floats(&args, &mut p));
+                self.positions.push(p);
+            }
+            "vt" => {
+                let mut t = [0.0; 2];
+                try!(Self::parse_floats(&args, &mut t));
+                self.texcoords.push(t);
+            }
+            "vn" => {
+                let mut n = [0.0; 3];
+                try!(Self::parse_floats(&args, &mut n));
+                self.normals.push(n);
+            }
+            "o" | "g" => return Ok(self.begin_shape(args.join(" "))),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(String::from("Face with less than 3 vertices"));
+                }
+                let mut verts = Vec::with_capacity(args.len());
+                for a in &args {
+                    verts.push(try!(self.face_vertex(a)));
+                }
+                // Triangulate polygons as a fan
+                for i in 1..(verts.len() - 1) {
+                    self.current
+                        .shape
+                        .indices
+                        .extend_from_slice(&[verts[0], verts[i], verts[i + 1]]);
+                }
+            }
+            _ => (),
+        }
+        Ok(None)
+    }
+}
+
+impl<B: BufRead> Iterator for ObjStream<B> {
+    type Item = Result<Shape, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        while !self.done {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => {
+                    self.done = true;
+                    return self.begin_shape(String::new()).map(Ok);
+                }
+                Ok(_) => match self.parse_line(&line) {
+                    Ok(Some(shape)) => return Some(Ok(shape)),
+                    Ok(None) => (),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.to_string()));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assets::set_asset_roots;
+    use assets::tests::TempRoot;
+
+    // Shape of `num_verts` vertices at x = 0, 1, 2, .. with uvs but no normals
+    fn shape(num_verts: usize, indices: Vec<u32>) -> Shape {
+        Shape {
+            name: String::new(),
+            positions: (0..num_verts)
+                .flat_map(|i| vec![i as f32, 0.0, 0.0])
+                .collect(),
+            normals: vec![0.0; num_verts * 3],
+            texcoords: vec![0.5; num_verts * 2],
+            tangents: Vec::new(),
+            indices,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            material: None,
+        }
+    }
+
+    #[test]
+    fn flatten_rebases_later_shape_indices() {
+        let model = Model {
+            shapes: vec![shape(3, vec![0, 1, 2]), shape(4, vec![0, 1, 2, 2, 3, 0])],
+        };
+        let (vdata, num_verts, indices) = model.flatten();
+        assert_eq!(num_verts, 7);
+        assert_eq!(vdata.len(), 7 * (3 + 3 + 2));
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5, 5, 6, 3]);
+    }
+
+    #[test]
+    fn flatten_pads_missing_uvs() {
+        let mut without_uvs = shape(3, vec![0, 1, 2]);
+        without_uvs.texcoords.clear();
+        let model = Model {
+            shapes: vec![without_uvs, shape(2, vec![0, 1, 1])],
+        };
+        let (vdata, num_verts, _) = model.flatten();
+        let uvs = &vdata[num_verts * 6..];
+        assert_eq!(uvs, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn quad_tangents_follow_u_axis() {
+        // Unit quad in the XY plane facing +Z, u along +X and v along +Y
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+        let normals = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let texcoords = [0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let indices = [0, 1, 2, 2, 3, 0];
+        let tangents = Model::generate_tangents(&positions, &normals, &texcoords, &indices);
+        assert_eq!(tangents.len(), 4 * 4);
+        for t in tangents.chunks(4) {
+            assert_eq!(t, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    const UNIT_CUBE_OBJ: &str = "\
+v -0.5 -0.5 -0.5
+v 0.5 -0.5 -0.5
+v 0.5 0.5 -0.5
+v -0.5 0.5 -0.5
+v -0.5 -0.5 0.5
+v 0.5 -0.5 0.5
+v 0.5 0.5 0.5
+v -0.5 0.5 0.5
+f 1 3 2
+f 1 4 3
+f 5 6 7
+f 5 7 8
+f 1 2 6
+f 1 6 5
+f 4 7 3
+f 4 8 7
+f 1 5 8
+f 1 8 4
+f 2 3 7
+f 2 7 6
+";
+
+    #[test]
+    fn unit_cube_bounds() {
+        let model = Model::from_buf(&mut UNIT_CUBE_OBJ.as_bytes()).unwrap();
+        let aabb = model.aabb();
+        assert_eq!(aabb.min, vec3(-0.5, -0.5, -0.5));
+        assert_eq!(aabb.max, vec3(0.5, 0.5, 0.5));
+        assert_eq!(model.shapes[0].aabb(), aabb);
+    }
+
+    #[test]
+    fn obj_material_comes_from_mtllib() {
+        let mtl = b"newmtl red\nKd 0.8 0.1 0.2\nmap_Kd red.png\n";
+        let root = TempRoot::new(&[("cube.mtl", mtl)]);
+        set_asset_roots(&[&root.dir]);
+        let obj = format!("mtllib cube.mtl\nusemtl red\n{}", UNIT_CUBE_OBJ);
+        let model = Model::from_buf_in(&mut obj.as_bytes(), Path::new("")).unwrap();
+        let material = model.shapes[0].material.clone().unwrap();
+        assert_eq!(material.name, "red");
+        assert_eq!(material.diffuse, [0.8, 0.1, 0.2]);
+        assert_eq!(material.diffuse_texture, Some("red.png".to_string()));
+    }
 }
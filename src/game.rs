@@ -1,15 +1,34 @@
 use assets::*;
 use gl;
 use glutin::{
-    dpi::*, Api, ContextBuilder, ElementState, Event, EventsLoop, GlContext, GlProfile, GlRequest,
-    GlWindow, VirtualKeyCode, WindowBuilder, WindowEvent,
+    dpi::*, Api, ContextBuilder, Event, EventsLoop, GlContext, GlProfile, GlRequest, GlWindow,
+    Icon, MouseButton, MouseCursor, VirtualKeyCode, WindowBuilder, WindowEvent,
 };
+use graphics::capabilities::Capabilities;
+use graphics::readback;
 use graphics::*;
+use image;
+use input::InputState;
+use mainloop::LoopEvent;
 use math::*;
-use std::path::Path;
-use std::time::Instant;
+use std::cell::Cell;
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 const WND_DIMENSIONS: (f32, f32) = (1280.0, 720.0);
+const ZOOM_DEGREES_PER_LINE: f32 = 5.0;
+const ORBIT_RADIANS_PER_PIXEL: f32 = 0.01;
+const MODEL_RADIANS_PER_SEC: f32 = 0.5;
+
+// Contexts to try creating in order. The shaders target GLSL ES 3.00, which
+// `Shader` rewrites to GLSL 3.30 on desktop contexts lacking ES 3 support
+const CONTEXT_REQUESTS: [(GlRequest, GlProfile); 3] = [
+    (GlRequest::Specific(Api::OpenGlEs, (3, 0)), GlProfile::Compatibility),
+    (GlRequest::Specific(Api::OpenGl, (3, 3)), GlProfile::Core),
+    (GlRequest::Latest, GlProfile::Compatibility),
+];
 
 struct Timer {
     start: Instant,
@@ -30,174 +49,501 @@ impl Timer {
     }
 }
 
+pub struct GameConfig {
+    /// Asset path of an image to use as the window icon
+    pub icon: Option<PathBuf>,
+    pub cursor: MouseCursor,
+    pub hide_cursor: bool,
+    /// Confine the cursor to the window, e.g. for mouse-look controls
+    pub grab_cursor: bool,
+    /// Stop simulating and rendering while the window is in the background
+    pub pause_when_unfocused: bool,
+    /// Use a reversed depth range for better far plane precision
+    pub reversed_z: bool,
+    /// Clamp depth instead of clipping at the near/far planes.
+    /// Needs desktop GL (or EXT_depth_clamp on GLES)
+    pub depth_clamp: bool,
+    /// Wait for the display refresh on buffer swaps
+    pub vsync: bool,
+    /// Render into an sRGB framebuffer and sample color textures as sRGB,
+    /// so that shading happens in linear space
+    pub srgb: bool,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            icon: None,
+            cursor: MouseCursor::Default,
+            hide_cursor: false,
+            grab_cursor: false,
+            pause_when_unfocused: true,
+            reversed_z: false,
+            depth_clamp: false,
+            vsync: true,
+            srgb: false,
+        }
+    }
+}
+
+/// Drawable size of the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameSize {
+    /// Physical pixels
+    pub width: u32,
+    pub height: u32,
+    /// Physical pixels per logical point
+    pub dpi_factor: f64,
+}
+
+impl FrameSize {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// Whether there is nothing to draw into, e.g. while minimized
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// What the sample mesh gets drawn with
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// The regular lit and textured shading
+    Shaded,
+    /// World space vertex normals as RGB
+    Normals,
+    /// Texture coordinates as RG
+    Uvs,
+    /// Per face normals lit by the moving light, ignoring vertex normals
+    Flat,
+}
+
 pub struct Game {
     events_loop: EventsLoop,
     window: GlWindow,
+    input: InputState,
     shdr: Shader,
+    debug_shdr: Shader,
+    debug_view: DebugView,
     mesh: Mesh,
-    tex: Texture,
+    material: Material,
+    camera: Camera,
+    // Model rotation at the last two updates, rendering blends between them
+    prev_model_angle: f32,
+    model_angle: f32,
+    grid: GroundGrid,
     text_renderer: TextRenderer,
     timer: Timer,
     status: String,
+    pause_when_unfocused: bool,
+    reversed_z: bool,
+    events: Vec<LoopEvent>,
+    // Set when a screenshot of the next rendered frame was asked for
+    screenshot_pending: Cell<bool>,
+    clear_params: ClearParams,
 }
 
 impl Game {
-    pub fn new() -> Game {
+    pub fn new() -> Result<Game, String> {
+        Self::with_config(GameConfig::default())
+    }
+
+    /// Creates the window, its GL context and the sample scene. Fails with
+    /// a description of what went wrong, e.g. when the driver lacks the
+    /// requested GL version or there is no display to open the window on
+    pub fn with_config(config: GameConfig) -> Result<Game, String> {
         // Event pump
         let events_loop = EventsLoop::new();
 
+        // Window icon
+        let icon = config.icon.as_ref().and_then(|p| match Self::load_icon(p) {
+            Ok(icon) => Some(icon),
+            Err(e) => {
+                println!("Could not load window icon {:?}: {}", p, e);
+                None
+            }
+        });
+
         // Plain window
         let window = WindowBuilder::new()
             .with_dimensions(LogicalSize::new(
                 WND_DIMENSIONS.0 as f64,
                 WND_DIMENSIONS.1 as f64,
-            )).with_resizable(false);
-
-        // Accelerated 3D context
-        let context = ContextBuilder::new()
-            .with_multisampling(4)
-            .with_gl_profile(GlProfile::Compatibility)
-            .with_gl_debug_flag(true)
-            .with_gl(GlRequest::Specific(Api::OpenGlEs, (3, 0)));
+            )).with_resizable(false)
+            .with_window_icon(icon);
 
         // Window with accelerated 3D context
-        let gl_window = GlWindow::new(window, context, &events_loop).unwrap();
+        let gl_window = try!(Self::create_window(window, &config, &events_loop));
 
         // Make context current before calling gl function loader
-        unsafe { gl_window.make_current().unwrap() };
+        unsafe {
+            try!(gl_window
+                .make_current()
+                .map_err(|e| format!("Could not make the GL context current: {}", e)));
+        }
 
         // Load OpenGL function pointers
         gl::load_with(|symbol| gl_window.get_proc_address(symbol) as *const _);
+        let caps = Capabilities::query();
+        println!("OpenGL {} (GLSL {})", caps.version, caps.glsl_version);
+
+        // Depth setup
+        unsafe {
+            if config.reversed_z && gl::ClipControl::is_loaded() {
+                // Use the [0, 1] clip depth range so the reversed projection
+                // doesn't waste precision remapping from [-1, 1]
+                gl::ClipControl(gl::LOWER_LEFT, gl::ZERO_TO_ONE);
+            }
+            if config.depth_clamp {
+                gl::Enable(gl::DEPTH_CLAMP);
+            }
+        }
+
+        // Color space setup
+        if config.srgb && !enable_srgb_framebuffer(true) {
+            println!("No control over sRGB framebuffer writes");
+        }
+
+        // Cursor setup
+        gl_window.set_cursor(config.cursor);
+        gl_window.hide_cursor(config.hide_cursor);
+        if config.grab_cursor {
+            if let Err(e) = gl_window.grab_cursor(true) {
+                println!("Could not grab cursor: {}", e);
+            }
+        }
 
         // Load sample shader
-        let shdr = Shader::new(
+        let shdr = try!(Shader::new(
             include_str!("shaders/default.vert"),
             None,
             include_str!("shaders/default.frag"),
             Some(&["vpos", "vnrm", "vuv0"]),
-        );
+        ).map_err(|e| format!("Could not build the sample shader: {}", e)));
+
+        // Load debug visualization shader
+        let debug_shdr = try!(Shader::new(
+            include_str!("shaders/default.vert"),
+            None,
+            include_str!("shaders/debug.frag"),
+            Some(&["vpos", "vnrm", "vuv0"]),
+        ).map_err(|e| format!("Could not build the debug shader: {}", e)));
 
         // Load sample 3D model
-        let (vdata, num_verts, indcs) = Self::load_flattened_model("spot/spot.obj").unwrap();
+        let (vdata, num_verts, indcs) = try!(Self::load_flattened_model("spot/spot.obj"));
 
         // Load sample mesh
         let mesh = Mesh::from_data(
             &vdata,
             num_verts,
-            Some(&indcs),
+            Some(Indices::U32(&indcs)),
             vattr_flag(Vattr::Position) | vattr_flag(Vattr::Normal) | vattr_flag(Vattr::UV0),
         );
 
         // Load sample image
-        let img_data = load(Path::new("spot/spot.png")).unwrap();
-        let img = Image::from_buf(img_data).unwrap();
+        let img_data = try!(load(Path::new("spot/spot.png")));
+        let img = try!(Image::from_buf(img_data));
 
-        // Load sample texture
-        let tex = Texture::from_image(&img);
+        // Load sample texture, an albedo map
+        let tex_params = if config.srgb {
+            TextureParams::srgb()
+        } else {
+            TextureParams::default()
+        };
+        let tex = Texture::from_image_with_params(&img, &tex_params);
 
         // Make text renderer and load sample font
-        let mut text_renderer = TextRenderer::new();
-        let mut font_data = load(Path::new("Hack-Regular.ttf")).unwrap();
-        text_renderer.add_font("sans", &mut font_data);
+        let mut text_renderer = try!(TextRenderer::new());
+        text_renderer.set_dpi_factor(gl_window.get_hidpi_factor());
+        let mut font_data = try!(load(Path::new("Hack-Regular.ttf")));
+        try!(text_renderer.add_font("sans", &mut font_data));
+
+        let mut input = InputState::new();
+        input.set_hidpi_factor(gl_window.get_hidpi_factor());
 
-        Game {
+        Ok(Game {
             events_loop: events_loop,
             window: gl_window,
+            input: input,
             shdr: shdr,
+            debug_shdr: debug_shdr,
+            debug_view: DebugView::Shaded,
             mesh: mesh,
-            tex: tex,
+            material: Material::new().with_texture("tex", Rc::new(tex)),
+            camera: Camera::new(vec3(0.0, 0.0, -3.0), vec3(0.0, 0.0, 0.0))
+                .with_reversed_z(config.reversed_z),
+            prev_model_angle: 26.0_f32.to_radians(),
+            model_angle: 26.0_f32.to_radians(),
+            grid: try!(GroundGrid::new()).with_height(-0.75),
             text_renderer: text_renderer,
             timer: Timer::new(),
             status: String::new(),
+            pause_when_unfocused: config.pause_when_unfocused,
+            reversed_z: config.reversed_z,
+            events: Vec::new(),
+            screenshot_pending: Cell::new(false),
+            // The far plane is at depth 0 with a reversed range
+            clear_params: ClearParams::default()
+                .with_depth(Some(if config.reversed_z { 0.0 } else { 1.0 })),
+        })
+    }
+
+    // Goes through CONTEXT_REQUESTS until one of them yields a window
+    fn create_window(
+        window: WindowBuilder,
+        config: &GameConfig,
+        events_loop: &EventsLoop,
+    ) -> Result<GlWindow, String> {
+        let mut failures = Vec::new();
+        for &(request, profile) in CONTEXT_REQUESTS.iter() {
+            let context = ContextBuilder::new()
+                .with_multisampling(4)
+                .with_gl_profile(profile)
+                .with_gl_debug_flag(true)
+                .with_vsync(config.vsync)
+                .with_srgb(config.srgb)
+                .with_gl(request);
+            match GlWindow::new(window.clone(), context, events_loop) {
+                Ok(gl_window) => {
+                    println!("Created {}", describe_gl_request(&request));
+                    return Ok(gl_window);
+                }
+                Err(e) => failures.push(format!("{}: {}", describe_gl_request(&request), e)),
+            }
         }
+        Err(format!(
+            "Could not create a window with a GL context, tried\n  {}",
+            failures.join("\n  ")
+        ))
+    }
+
+    fn load_icon(fpath: &Path) -> Result<Icon, String> {
+        let img_data = try!(load(fpath));
+        let img = try!(Image::from_buf(img_data));
+        Self::icon_from_image(&img)
+    }
+
+    fn icon_from_image(img: &Image) -> Result<Icon, String> {
+        // Loaded images are flipped for GL, icons expect top-left origin
+        let img = image::imageops::flip_vertical(img);
+        let (width, height) = img.dimensions();
+        Icon::from_rgba(img.into_raw(), width, height).map_err(|e| e.to_string())
+    }
+
+    #[allow(dead_code)]
+    pub fn set_icon(&self, img: &Image) -> Result<(), String> {
+        let icon = try!(Self::icon_from_image(img));
+        self.window.set_window_icon(Some(icon));
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn set_cursor(&self, cursor: MouseCursor) {
+        self.window.set_cursor(cursor);
+    }
+
+    #[allow(dead_code)]
+    pub fn hide_cursor(&self, hide: bool) {
+        self.window.hide_cursor(hide);
+    }
+
+    #[allow(dead_code)]
+    pub fn grab_cursor(&self, grab: bool) -> Result<(), String> {
+        self.window.grab_cursor(grab)
     }
 
     fn load_flattened_model(fpath: &str) -> Result<(Vec<f32>, usize, Vec<u32>), String> {
-        let mut mdl_data = try!(load(Path::new(fpath)));
-        let mut model = try!(Model::from_buf(&mut mdl_data));
-        let (mut vpos, mut vnrm, mut vuv0, mut indc) =
-            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
-        let mut nvrt = 0;
-        for s in model.shapes.iter_mut() {
-            vpos.append(&mut s.positions);
-            vnrm.append(&mut s.normals);
-            vuv0.append(&mut s.texcoords);
-            indc.append(&mut s.indices);
-            nvrt += vpos.len() / 3;
-        }
-        let mut vdata = Vec::new();
-        vdata.append(&mut vpos);
-        vdata.append(&mut vnrm);
-        vdata.append(&mut vuv0);
-        Ok((vdata, nvrt, indc))
-    }
-
-    pub fn update(&mut self, _dt: f32) -> bool {
+        let fpath = Path::new(fpath);
+        let mut mdl_data = try!(load(fpath));
+        let base_dir = fpath.parent().unwrap_or(Path::new(""));
+        let model = try!(Model::from_buf_in(&mut mdl_data, base_dir));
+        Ok(model.flatten())
+    }
+
+    pub fn update(&mut self, dt: f32) -> bool {
         let mut exit_flag = false;
         let wnd = &mut self.window;
+        let input = &mut self.input;
+        let text_renderer = &mut self.text_renderer;
+        let events = &mut self.events;
+        input.begin_frame();
         self.events_loop.poll_events(|event| match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => exit_flag = true,
-                WindowEvent::Resized(logical_size) => {
-                    let dpi_factor = wnd.get_hidpi_factor();
-                    wnd.resize(logical_size.to_physical(dpi_factor));
-                }
-                WindowEvent::KeyboardInput { input, .. } => {
-                    if input.state == ElementState::Released {
-                        if let Some(key) = input.virtual_keycode {
-                            match key {
-                                VirtualKeyCode::Escape => exit_flag = true,
-                                _ => (),
-                            }
-                        }
+            Event::WindowEvent { event, .. } => {
+                input.handle_event(&event);
+                match event {
+                    WindowEvent::CloseRequested => exit_flag = true,
+                    WindowEvent::Resized(logical_size) => {
+                        let dpi_factor = wnd.get_hidpi_factor();
+                        let physical_size = logical_size.to_physical(dpi_factor);
+                        wnd.resize(physical_size);
+                        events.push(LoopEvent::Resized(
+                            physical_size.width as u32,
+                            physical_size.height as u32,
+                        ));
+                    }
+                    WindowEvent::Focused(focused) => events.push(LoopEvent::Focused(focused)),
+                    WindowEvent::HiDpiFactorChanged(dpi_factor) => {
+                        text_renderer.set_dpi_factor(dpi_factor);
                     }
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
             _ => (),
         });
+        if self.input.was_released(VirtualKeyCode::Escape) {
+            exit_flag = true;
+        }
+        if self.is_paused() {
+            return exit_flag;
+        }
+        if self.input.was_pressed(VirtualKeyCode::N) {
+            self.toggle_debug_view(DebugView::Normals);
+        }
+        if self.input.was_pressed(VirtualKeyCode::U) {
+            self.toggle_debug_view(DebugView::Uvs);
+        }
+        if self.input.was_pressed(VirtualKeyCode::F) {
+            self.toggle_debug_view(DebugView::Flat);
+        }
+        if self.input.was_pressed(VirtualKeyCode::F12) {
+            self.screenshot_pending.set(true);
+        }
+        if self.input.is_button_down(MouseButton::Left) {
+            let (dx, dy) = self.input.cursor_delta();
+            self.camera.orbit_by(
+                -dx as f32 * ORBIT_RADIANS_PER_PIXEL,
+                dy as f32 * ORBIT_RADIANS_PER_PIXEL,
+            );
+        }
+        self.camera.zoom(self.input.scroll_delta() * ZOOM_DEGREES_PER_LINE);
+        self.camera.update(dt);
+        self.prev_model_angle = self.model_angle;
+        self.model_angle += MODEL_RADIANS_PER_SEC * dt;
+        if self.model_angle > 2.0 * PI {
+            // Shift both so that the blend doesn't spin back around
+            self.model_angle -= 2.0 * PI;
+            self.prev_model_angle -= 2.0 * PI;
+        }
         exit_flag
     }
 
-    pub fn render(&self, _interpolation: f32) {
+    /// Window events gathered since the last call, for the main loop hooks
+    pub fn drain_events(&mut self) -> Vec<LoopEvent> {
+        self.events.drain(..).collect()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
         unsafe {
-            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::Enable(gl::DEPTH_TEST);
-        }
-
-        let wnd_sz = self.window.get_inner_size().unwrap();
-        let wnd_ratio = wnd_sz.width as f32 / wnd_sz.height as f32;
-        let proj = perspective(wnd_ratio, 60.0_f32.to_radians(), 0.1, 100.0);
-        let view = look_at(
-            &vec3(0.0, 0.0, -3.0),
-            &vec3(0.0, 0.0, 0.0),
-            &vec3(0.0, 1.0, 0.0),
-        );
-        let modl = rotate_y(&identity(), 26.0_f32.to_radians());
+            gl::Viewport(0, 0, width as i32, height as i32);
+        }
+    }
+
+    /// Sets what gets cleared at the start of every frame
+    #[allow(dead_code)]
+    pub fn set_clear_params(&mut self, clear_params: ClearParams) {
+        self.clear_params = clear_params;
+    }
+
+    #[allow(dead_code)]
+    pub fn clear_params(&self) -> &ClearParams {
+        &self.clear_params
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.input.is_focused()
+    }
+
+    /// Whether updates and rendering stop while the window is unfocused
+    pub fn pauses_when_unfocused(&self) -> bool {
+        self.pause_when_unfocused
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_when_unfocused && !self.is_focused()
+    }
+
+    fn toggle_debug_view(&mut self, view: DebugView) {
+        self.debug_view = if self.debug_view == view {
+            DebugView::Shaded
+        } else {
+            view
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+    }
+
+    #[allow(dead_code)]
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    /// Draws the state between the last two updates, `interpolation` being
+    /// how far the time since the last update got into the next one. Fails
+    /// when the frame can't be presented, e.g. after losing the context
+    pub fn render(&self, interpolation: f32) -> Result<(), String> {
+        if self.is_paused() {
+            // Nothing changed since the last frame
+            return Ok(());
+        }
+        let frame_size = match self.frame_size() {
+            Some(size) if !size.is_empty() => size,
+            _ => return Ok(()),
+        };
+        self.text_renderer
+            .set_target_size(Some((frame_size.width, frame_size.height)));
+
+        let depth_func = if self.reversed_z {
+            DepthFunc::Greater
+        } else {
+            DepthFunc::Less
+        };
+        RenderState::new().with_depth_test(depth_func).apply();
+        self.clear_params.clear();
+
+        let aspect_ratio = frame_size.aspect_ratio();
+        let view_proj = self.camera.view_projection(aspect_ratio);
+        let eye = self.camera.eye;
+        let angle = lerp(self.prev_model_angle, self.model_angle, interpolation);
+        let modl = rotate_y(&identity(), angle);
         let nmm = mat4_to_mat3(&inverse_transpose(modl)); // mat3(transpose(inverse(model)))
-        let mvp = proj * view * modl;
+        let mvp = view_proj * modl;
         let mdl = modl;
 
-        self.shdr.activate();
-        self.shdr.set_uniform("model", mdl.as_ref());
-        self.shdr.set_uniform("nmm", nmm.as_ref());
-        self.shdr.set_uniform("mvp", mvp.as_ref());
-        self.shdr.set_uniform("tex", 0);
+        self.grid.draw(&view_proj, &eye);
+
+        let shdr = match self.debug_view {
+            DebugView::Shaded => &self.shdr,
+            DebugView::Normals | DebugView::Uvs | DebugView::Flat => &self.debug_shdr,
+        };
+        shdr.activate();
+        shdr.set_uniform("model", mdl.as_ref());
+        shdr.set_uniform("nmm", nmm.as_ref());
+        shdr.set_uniform("mvp", mvp.as_ref());
+        match self.debug_view {
+            DebugView::Normals => shdr.set_uniform("mode", 0),
+            DebugView::Uvs => shdr.set_uniform("mode", 1),
+            DebugView::Flat => shdr.set_uniform("mode", 2),
+            DebugView::Shaded => (),
+        }
 
         // Make time varying movable light
         let time = self.timer.elapsed_msec() / 1000.0;
         let light_pos: Vec3 = vec3(time.sin(), 0.0, time.cos()) * 10.0;
-        self.shdr.set_uniform("light_pos", light_pos.as_ref());
+        shdr.set_uniform("light_pos", light_pos.as_ref());
 
-        self.tex.bind(0);
-        self.mesh.draw();
+        self.material.draw(shdr, &self.mesh, &[]);
 
         {
             let tscl = 1.2;
             let pad = 0.03;
             let tmvp = scale(
-                &translation(&vec3(-1.0 + pad, 1.0 - pad * wnd_ratio, 0.0)),
+                &translation(&vec3(-1.0 + pad, 1.0 - pad * aspect_ratio, 0.0)),
                 &(&vec3(tscl, tscl, tscl)),
             );
             Text::new(&self.status, "sans", &tmvp.as_ref())
@@ -206,16 +552,83 @@ impl Game {
                 .draw(&self.text_renderer);
         }
 
-        self.window.swap_buffers().unwrap();
+        // The back buffer contents are undefined once swapped
+        if self.screenshot_pending.replace(false) {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = format!("screenshot-{}.png", secs);
+            match self.screenshot(&path) {
+                Ok(()) => println!("Saved screenshot {}", path),
+                Err(e) => println!("Could not save screenshot {}: {}", path, e),
+            }
+        }
+
+        try!(self
+            .window
+            .swap_buffers()
+            .map_err(|e| format!("Could not present the frame: {}", e)));
+        stats::end_frame();
+        Ok(())
+    }
+
+    /// Current size of the window's framebuffer, None once the window is gone
+    pub fn frame_size(&self) -> Option<FrameSize> {
+        let logical_size = self.window.get_inner_size()?;
+        let dpi_factor = self.window.get_hidpi_factor();
+        let size = logical_size.to_physical(dpi_factor);
+        Some(FrameSize {
+            width: size.width.round() as u32,
+            height: size.height.round() as u32,
+            dpi_factor,
+        })
+    }
+
+    /// Saves the color contents of the window's back buffer as a PNG file,
+    /// at the window's physical pixel size
+    pub fn screenshot<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let size = try!(self.frame_size().ok_or("Window is gone".to_string()));
+        let frame = unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            readback::read_color(0, 0, size.width, size.height)
+        };
+        save_png(&frame, path, Origin::BottomLeft)
     }
 
     pub fn perf(&mut self, ms: f32, ut: f32, rt: f32) {
         let fps = 1000.0 / ms;
+        let fs = stats::last_frame();
         let title = format!(
             "[Fps: {:.2} / Msec: {:.2} (CPU: {:.2} | GPU: {:.2})]",
             fps, ms, ut, rt
         );
         self.window.set_title(title.as_str());
-        self.status = format!("{:.2} FPS {:.2}|{:.2}|{:.2} (CPU|GPU|TOT)", fps, ut, rt, ms);
+        self.status = format!(
+            "{:.2} FPS {:.2}|{:.2}|{:.2} (CPU|GPU|TOT)\r{} DC {} TRI {} TEX {} BUF",
+            fps, ut, rt, ms, fs.draw_calls, fs.triangles, fs.texture_binds, fs.buffer_uploads
+        );
+    }
+}
+
+// Human readable form of a context request, e.g. "an OpenGL ES 3.0 context"
+fn describe_gl_request(request: &GlRequest) -> String {
+    let api_name = |api: &Api| match api {
+        Api::OpenGl => "an OpenGL",
+        Api::OpenGlEs => "an OpenGL ES",
+        Api::WebGl => "a WebGL",
+    };
+    match request {
+        GlRequest::Latest => "the latest available GL context".to_string(),
+        GlRequest::Specific(api, (major, minor)) => {
+            format!("{} {}.{} context", api_name(api), major, minor)
+        }
+        GlRequest::GlThenGles {
+            opengl_version,
+            opengles_version,
+        } => format!(
+            "an OpenGL {}.{} or OpenGL ES {}.{} context",
+            opengl_version.0, opengl_version.1, opengles_version.0, opengles_version.1
+        ),
     }
 }
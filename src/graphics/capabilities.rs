@@ -0,0 +1,205 @@
+use gl;
+use gl::types::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+//
+// What the current GL context supports. Queried once, on first use or
+// explicitly through `Capabilities::query` after the context was made
+// current, and cached from then on.
+//
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub version: String,
+    pub glsl_version: String,
+    /// Whether this is an OpenGL ES context
+    pub es: bool,
+    /// Major and minor version of the context
+    pub major: u32,
+    pub minor: u32,
+    /// Highest anisotropic filtering level, 1 when unsupported
+    pub max_anisotropy: f32,
+    extensions: HashSet<String>,
+}
+
+thread_local! {
+    static CAPABILITIES: RefCell<Option<Capabilities>> = RefCell::new(None);
+}
+
+#[allow(dead_code)]
+impl Capabilities {
+    /// Builds the capabilities from the GL_VERSION, GL_SHADING_LANGUAGE_VERSION
+    /// and space separated GL_EXTENSIONS strings
+    pub fn from_strings(version: &str, glsl_version: &str, extensions: &str) -> Self {
+        let (major, minor) = parse_version(version);
+        Capabilities {
+            version: version.to_string(),
+            glsl_version: glsl_version.to_string(),
+            es: version.starts_with("OpenGL ES"),
+            major,
+            minor,
+            max_anisotropy: 1.0,
+            extensions: extensions.split_whitespace().map(String::from).collect(),
+        }
+    }
+
+    /// Queries the current context and caches the result
+    pub fn query() -> Self {
+        let caps = unsafe {
+            let version = get_string(gl::VERSION);
+            let glsl_version = get_string(gl::SHADING_LANGUAGE_VERSION);
+            let extensions = if gl::GetStringi::is_loaded() {
+                let mut count: GLint = 0;
+                gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+                (0..count as GLuint)
+                    .map(|i| c_string(gl::GetStringi(gl::EXTENSIONS, i)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            } else {
+                get_string(gl::EXTENSIONS)
+            };
+            let mut caps = Capabilities::from_strings(&version, &glsl_version, &extensions);
+            if caps.supports_anisotropy() {
+                gl::GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut caps.max_anisotropy);
+            }
+            caps
+        };
+        CAPABILITIES.with(|c| *c.borrow_mut() = Some(caps.clone()));
+        caps
+    }
+
+    /// The cached capabilities, querying them if that hasn't happened yet
+    pub fn current() -> Self {
+        match CAPABILITIES.with(|c| c.borrow().clone()) {
+            Some(caps) => caps,
+            None => Capabilities::query(),
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    /// Whether fragment shaders can use dFdx/dFdy/fwidth. They are core in
+    /// desktop GL and ES 3.0, while ES 2.0 needs an extension
+    pub fn supports_derivatives(&self) -> bool {
+        !self.es || self.major >= 3 || self.has_extension("GL_OES_standard_derivatives")
+    }
+
+    /// Whether glReadPixels can read DEPTH_COMPONENT. Desktop GL always can,
+    /// ES only through an extension
+    pub fn supports_depth_readback(&self) -> bool {
+        !self.es || self.has_extension("GL_NV_read_depth")
+    }
+
+    /// Whether textures can use anisotropic filtering
+    pub fn supports_anisotropy(&self) -> bool {
+        self.has_extension("GL_EXT_texture_filter_anisotropic")
+            || self.has_extension("GL_ARB_texture_filter_anisotropic")
+    }
+
+    /// The anisotropy level to actually set for a requested one, or None
+    /// when anisotropic filtering is unsupported
+    pub fn clamp_anisotropy(&self, level: f32) -> Option<f32> {
+        if !self.supports_anisotropy() {
+            return None;
+        }
+        Some(level.max(1.0).min(self.max_anisotropy.max(1.0)))
+    }
+}
+
+// Extracts the "major.minor" pair from strings like "4.5.0 NVIDIA 390.77"
+// or "OpenGL ES 3.0 Mesa 18.0.5"
+fn parse_version(version: &str) -> (u32, u32) {
+    let number = version
+        .split_whitespace()
+        .find(|w| w.starts_with(|c: char| c.is_digit(10)))
+        .unwrap_or("");
+    let mut parts = number.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor)
+}
+
+unsafe fn get_string(name: GLenum) -> String {
+    c_string(gl::GetString(name))
+}
+
+unsafe fn c_string(ptr: *const GLubyte) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr as *const c_char)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_es_version_string() {
+        let caps = Capabilities::from_strings(
+            "OpenGL ES 3.0 Mesa 18.0.5",
+            "OpenGL ES GLSL ES 3.00",
+            "GL_EXT_texture_filter_anisotropic GL_OES_standard_derivatives",
+        );
+        assert!(caps.es);
+        assert_eq!((caps.major, caps.minor), (3, 0));
+        assert!(caps.has_extension("GL_OES_standard_derivatives"));
+        assert!(!caps.has_extension("GL_OES"));
+    }
+
+    #[test]
+    fn parses_desktop_version_string() {
+        let caps = Capabilities::from_strings("4.5.0 NVIDIA 390.77", "4.50 NVIDIA", "");
+        assert!(!caps.es);
+        assert_eq!((caps.major, caps.minor), (4, 5));
+        assert!(caps.supports_derivatives());
+        assert!(caps.supports_depth_readback());
+    }
+
+    #[test]
+    fn es_reads_depth_only_with_extension() {
+        let caps = Capabilities::from_strings("OpenGL ES 3.0", "", "");
+        assert!(!caps.supports_depth_readback());
+        let caps = Capabilities::from_strings("OpenGL ES 3.0", "", "GL_NV_read_depth");
+        assert!(caps.supports_depth_readback());
+    }
+
+    #[test]
+    fn es2_needs_extension_for_derivatives() {
+        let caps = Capabilities::from_strings(
+            "OpenGL ES 2.0 Mesa 18.0.5",
+            "OpenGL ES GLSL ES 1.0.16",
+            "GL_EXT_texture_filter_anisotropic GL_OES_depth24",
+        );
+        assert!(!caps.supports_derivatives());
+        let caps = Capabilities::from_strings(
+            "OpenGL ES 2.0 Mesa 18.0.5",
+            "OpenGL ES GLSL ES 1.0.16",
+            "GL_OES_depth24 GL_OES_standard_derivatives",
+        );
+        assert!(caps.supports_derivatives());
+    }
+
+    #[test]
+    fn clamps_anisotropy_to_maximum() {
+        let mut caps =
+            Capabilities::from_strings("4.5.0", "4.50", "GL_EXT_texture_filter_anisotropic");
+        caps.max_anisotropy = 16.0;
+        assert_eq!(caps.clamp_anisotropy(64.0), Some(16.0));
+        assert_eq!(caps.clamp_anisotropy(4.0), Some(4.0));
+        assert_eq!(caps.clamp_anisotropy(0.5), Some(1.0));
+    }
+
+    #[test]
+    fn anisotropy_unsupported_without_extension() {
+        let caps = Capabilities::from_strings("OpenGL ES 3.0", "", "");
+        assert_eq!(caps.clamp_anisotropy(8.0), None);
+    }
+}
@@ -0,0 +1,75 @@
+use super::color::output_color;
+use super::state::RenderState;
+use gl;
+use gl::types::*;
+
+//
+// What gets cleared at the start of a frame (or pass) and to which values.
+// Leaving a buffer out keeps its previous contents, e.g. to layer a pass
+// on top of what an earlier one drew.
+//
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClearParams {
+    /// sRGB authored clear color, None to keep the color buffer
+    pub color: Option<[f32; 4]>,
+    /// None to keep the depth buffer
+    pub depth: Option<f32>,
+    /// None to keep the stencil buffer
+    pub stencil: Option<i32>,
+}
+
+/// Clears color to opaque black and depth to the far plane
+impl Default for ClearParams {
+    fn default() -> Self {
+        ClearParams {
+            color: Some([0.0, 0.0, 0.0, 1.0]),
+            depth: Some(1.0),
+            stencil: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ClearParams {
+    pub fn with_color(mut self, color: Option<[f32; 4]>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: Option<f32>) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_stencil(mut self, stencil: Option<i32>) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    /// Clears the requested buffers of the bound framebuffer
+    pub fn clear(&self) {
+        let mut mask: GLbitfield = 0;
+        unsafe {
+            if let Some(c) = self.color {
+                let c = output_color(&c);
+                gl::ClearColor(c[0], c[1], c[2], c[3]);
+                mask |= gl::COLOR_BUFFER_BIT;
+            }
+            if let Some(depth) = self.depth {
+                gl::ClearDepthf(depth);
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if let Some(stencil) = self.stencil {
+                gl::ClearStencil(stencil);
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+            if mask == 0 {
+                return;
+            }
+            // Depth writes must be on for the depth clear to take effect
+            let prev_state = RenderState::current().with_depth_write(true).apply();
+            gl::Clear(mask);
+            prev_state.apply();
+        }
+    }
+}
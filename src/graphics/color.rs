@@ -0,0 +1,54 @@
+use super::capabilities::Capabilities;
+use gl;
+use math::srgb_to_linear;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+//
+// Colors in the crate (text colors, clear colors, ...) are authored in sRGB.
+// When rendering into an sRGB framebuffer the GPU encodes the shader output
+// itself, so every color uniform must be handed over in linear space instead.
+// Call `set_srgb_framebuffer` whenever GL_FRAMEBUFFER_SRGB gets toggled and
+// pass authored colors through `output_color` before giving them to the GPU.
+//
+
+static SRGB_FRAMEBUFFER: AtomicBool = AtomicBool::new(false);
+
+#[allow(dead_code)]
+pub fn set_srgb_framebuffer(enabled: bool) {
+    SRGB_FRAMEBUFFER.store(enabled, Ordering::Relaxed);
+}
+
+pub fn srgb_framebuffer() -> bool {
+    SRGB_FRAMEBUFFER.load(Ordering::Relaxed)
+}
+
+/// Toggles GL_FRAMEBUFFER_SRGB, so that writes to sRGB capable framebuffers
+/// get encoded from linear. The default framebuffer must have been created
+/// sRGB capable for it to matter. Returns false when the context has no
+/// control over it (GLES without EXT_sRGB_write_control).
+#[allow(dead_code)]
+pub fn enable_srgb_framebuffer(enabled: bool) -> bool {
+    let caps = Capabilities::current();
+    if caps.es && !caps.has_extension("GL_EXT_sRGB_write_control") {
+        return false;
+    }
+    unsafe {
+        if enabled {
+            gl::Enable(gl::FRAMEBUFFER_SRGB);
+        } else {
+            gl::Disable(gl::FRAMEBUFFER_SRGB);
+        }
+    }
+    set_srgb_framebuffer(enabled);
+    true
+}
+
+/// Converts an sRGB authored color to the space the framebuffer expects
+pub fn output_color(c: &[f32; 4]) -> [f32; 4] {
+    if srgb_framebuffer() {
+        let l = srgb_to_linear([c[0], c[1], c[2]]);
+        [l[0], l[1], l[2], c[3]]
+    } else {
+        *c
+    }
+}
@@ -0,0 +1,317 @@
+use super::texture::{Texture, TextureFormat};
+use gl;
+use gl::types::*;
+
+//
+// Offscreen render target with one or more color textures and an optional
+// depth renderbuffer or texture. The texture attachments can be sampled in
+// later passes once the framebuffer got unbound.
+// Multisampled framebuffers render into renderbuffers instead, which can't
+// be sampled and have to be resolved into a regular framebuffer first.
+//
+pub struct Framebuffer {
+    id: GLuint,
+    depth_rbo: GLuint,
+    width: u32,
+    height: u32,
+    // Sample count, 0 when not multisampled
+    samples: u32,
+    color_attachments: Vec<Texture>,
+    color_rbos: Vec<GLuint>,
+    depth_texture: Option<Texture>,
+}
+
+#[allow(dead_code)]
+impl Framebuffer {
+    pub fn new(
+        width: u32,
+        height: u32,
+        color_formats: &[TextureFormat],
+        with_depth: bool,
+    ) -> Result<Framebuffer, String> {
+        Self::create(width, height, color_formats, with_depth, None)
+    }
+
+    /// Creates a framebuffer whose depth gets written to a texture, so that
+    /// later passes can sample it (e.g. shadow maps)
+    pub fn with_depth_texture(
+        width: u32,
+        height: u32,
+        color_formats: &[TextureFormat],
+    ) -> Result<Framebuffer, String> {
+        let depth_texture = Texture::empty(width, height, TextureFormat::Depth24);
+        Self::create(width, height, color_formats, false, Some(depth_texture))
+    }
+
+    /// Creates a framebuffer with `samples` samples per pixel, which has to
+    /// be resolved with `resolve_to` before its contents can be sampled
+    pub fn multisampled(
+        width: u32,
+        height: u32,
+        samples: u32,
+        color_formats: &[TextureFormat],
+        with_depth: bool,
+    ) -> Result<Framebuffer, String> {
+        let mut max_samples: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        }
+        if samples == 0 || samples > max_samples as u32 {
+            return Err(format!(
+                "Unsupported sample count {} (max {})",
+                samples, max_samples
+            ));
+        }
+
+        let mut id: GLuint = 0;
+        let mut depth_rbo: GLuint = 0;
+        let mut color_rbos = vec![0; color_formats.len()];
+        let status;
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+            if !color_rbos.is_empty() {
+                gl::GenRenderbuffers(color_rbos.len() as GLsizei, color_rbos.as_mut_ptr());
+            }
+            let mut draw_buffers = Vec::with_capacity(color_rbos.len());
+            for (i, (rbo, format)) in color_rbos.iter().zip(color_formats).enumerate() {
+                let attachment = gl::COLOR_ATTACHMENT0 + i as GLenum;
+                gl::BindRenderbuffer(gl::RENDERBUFFER, *rbo);
+                gl::RenderbufferStorageMultisample(
+                    gl::RENDERBUFFER,
+                    samples as GLsizei,
+                    format.internal_format(),
+                    width as GLsizei,
+                    height as GLsizei,
+                );
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, *rbo);
+                draw_buffers.push(attachment);
+            }
+            if draw_buffers.is_empty() {
+                gl::DrawBuffers(1, &gl::NONE);
+                gl::ReadBuffer(gl::NONE);
+            } else {
+                gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+            }
+            if with_depth {
+                gl::GenRenderbuffers(1, &mut depth_rbo);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, depth_rbo);
+                gl::RenderbufferStorageMultisample(
+                    gl::RENDERBUFFER,
+                    samples as GLsizei,
+                    gl::DEPTH_COMPONENT24,
+                    width as GLsizei,
+                    height as GLsizei,
+                );
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    depth_rbo,
+                );
+            }
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        let fb = Framebuffer {
+            id,
+            depth_rbo,
+            width,
+            height,
+            samples,
+            color_attachments: Vec::new(),
+            color_rbos,
+            depth_texture: None,
+        };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Framebuffer incomplete (status 0x{:X})", status));
+        }
+        Ok(fb)
+    }
+
+    fn create(
+        width: u32,
+        height: u32,
+        color_formats: &[TextureFormat],
+        with_depth: bool,
+        depth_texture: Option<Texture>,
+    ) -> Result<Framebuffer, String> {
+        let color_attachments = color_formats
+            .iter()
+            .map(|f| Texture::empty(width, height, *f))
+            .collect::<Vec<_>>();
+        let mut id: GLuint = 0;
+        let mut depth_rbo: GLuint = 0;
+        let status;
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+            let mut draw_buffers = Vec::with_capacity(color_attachments.len());
+            for (i, tex) in color_attachments.iter().enumerate() {
+                let attachment = gl::COLOR_ATTACHMENT0 + i as GLenum;
+                gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    attachment,
+                    gl::TEXTURE_2D,
+                    tex.id(),
+                    0,
+                );
+                draw_buffers.push(attachment);
+            }
+            if draw_buffers.is_empty() {
+                // Depth only target (e.g. shadow maps)
+                gl::DrawBuffers(1, &gl::NONE);
+                gl::ReadBuffer(gl::NONE);
+            } else {
+                gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+            }
+            if with_depth {
+                gl::GenRenderbuffers(1, &mut depth_rbo);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, depth_rbo);
+                gl::RenderbufferStorage(
+                    gl::RENDERBUFFER,
+                    gl::DEPTH_COMPONENT24,
+                    width as GLsizei,
+                    height as GLsizei,
+                );
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    depth_rbo,
+                );
+            }
+            if let Some(tex) = &depth_texture {
+                gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::TEXTURE_2D,
+                    tex.id(),
+                    0,
+                );
+            }
+            status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        let fb = Framebuffer {
+            id,
+            depth_rbo,
+            width,
+            height,
+            samples: 0,
+            color_attachments,
+            color_rbos: Vec::new(),
+            depth_texture,
+        };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            // Dropping fb releases whatever got created
+            return Err(format!("Framebuffer incomplete (status 0x{:X})", status));
+        }
+        Ok(fb)
+    }
+
+    /// Redirects rendering into the framebuffer and sets the viewport to
+    /// cover it
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        }
+    }
+
+    /// Restores the default framebuffer, the caller is responsible for
+    /// restoring its viewport
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Resolves the color attachments of this multisampled framebuffer into
+    /// the matching color textures of `target`, which must be single sampled
+    /// and of the same dimensions. Leaves the default framebuffer bound
+    pub fn resolve_to(&self, target: &Framebuffer) -> Result<(), String> {
+        if self.samples == 0 {
+            return Err("Resolving a framebuffer that isn't multisampled".to_string());
+        }
+        if target.samples != 0 {
+            return Err("Resolve target must not be multisampled".to_string());
+        }
+        if self.dimensions() != target.dimensions() {
+            return Err(format!(
+                "Resolve target is {:?}, expected {:?}",
+                target.dimensions(),
+                self.dimensions()
+            ));
+        }
+        let count = self.color_rbos.len().min(target.color_attachments.len());
+        let (w, h) = (self.width as GLint, self.height as GLint);
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.id);
+            // A blit reads from one buffer and writes to all draw buffers,
+            // so resolve one attachment at a time
+            let mut draw_buffers = vec![gl::NONE; count];
+            for i in 0..count {
+                let attachment = gl::COLOR_ATTACHMENT0 + i as GLenum;
+                gl::ReadBuffer(attachment);
+                draw_buffers[i] = attachment;
+                gl::DrawBuffers((i + 1) as GLsizei, draw_buffers.as_ptr());
+                gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+                draw_buffers[i] = gl::NONE;
+            }
+            // Restore the draw and read buffers the framebuffers were created with
+            let all_buffers = (0..target.color_attachments.len())
+                .map(|i| gl::COLOR_ATTACHMENT0 + i as GLenum)
+                .collect::<Vec<_>>();
+            if !all_buffers.is_empty() {
+                gl::DrawBuffers(all_buffers.len() as GLsizei, all_buffers.as_ptr());
+            }
+            gl::ReadBuffer(if self.color_rbos.is_empty() {
+                gl::NONE
+            } else {
+                gl::COLOR_ATTACHMENT0
+            });
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Ok(())
+    }
+
+    /// Sample count, 0 if not multisampled
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Empty for multisampled framebuffers
+    pub fn color_attachments(&self) -> &[Texture] {
+        &self.color_attachments
+    }
+
+    pub fn color_attachment(&self, index: usize) -> Option<&Texture> {
+        self.color_attachments.get(index)
+    }
+
+    pub fn depth_texture(&self) -> Option<&Texture> {
+        self.depth_texture.as_ref()
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.color_rbos.is_empty() {
+                gl::DeleteRenderbuffers(self.color_rbos.len() as GLsizei, self.color_rbos.as_ptr());
+            }
+            if self.depth_rbo != 0 {
+                gl::DeleteRenderbuffers(1, &self.depth_rbo);
+            }
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use gl;
+use gl::types::*;
+
+//
+// Measures GPU time spent on a span of GL commands with TIME_ELAPSED queries.
+// Results are read one frame late from a pair of alternating queries, so that
+// asking for them never waits on the GPU.
+//
+pub struct GpuTimer {
+    queries: [GLuint; 2],
+    // Whether the query has been issued and its result not read back yet
+    pending: [bool; 2],
+    current: usize,
+    last_elapsed: Option<f32>,
+}
+
+impl GpuTimer {
+    /// None when the context lacks timer queries
+    pub fn new() -> Option<GpuTimer> {
+        if !gl::GenQueries::is_loaded() || !gl::GetQueryObjectui64v::is_loaded() {
+            return None;
+        }
+        let mut queries = [0; 2];
+        unsafe {
+            gl::GenQueries(2, queries.as_mut_ptr());
+        }
+        Some(GpuTimer {
+            queries,
+            pending: [false; 2],
+            current: 0,
+            last_elapsed: None,
+        })
+    }
+
+    pub fn begin(&mut self) {
+        unsafe {
+            gl::BeginQuery(gl::TIME_ELAPSED, self.queries[self.current]);
+        }
+    }
+
+    pub fn end(&mut self) {
+        unsafe {
+            gl::EndQuery(gl::TIME_ELAPSED);
+        }
+        self.pending[self.current] = true;
+        self.current = 1 - self.current;
+        self.poll(self.current);
+    }
+
+    // Picks up the result of the query if the GPU is done with it
+    fn poll(&mut self, idx: usize) {
+        if !self.pending[idx] {
+            return;
+        }
+        let mut available: GLuint = 0;
+        unsafe {
+            gl::GetQueryObjectuiv(self.queries[idx], gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        if available == 0 {
+            return;
+        }
+        let mut elapsed_ns: GLuint64 = 0;
+        unsafe {
+            gl::GetQueryObjectui64v(self.queries[idx], gl::QUERY_RESULT, &mut elapsed_ns);
+        }
+        self.pending[idx] = false;
+        self.last_elapsed = Some(elapsed_ns as f32 / 1_000_000.0);
+    }
+
+    /// Most recent measurement in msec, usually from the previous span
+    pub fn last_elapsed(&self) -> Option<f32> {
+        self.last_elapsed
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries(2, self.queries.as_ptr());
+        }
+    }
+}
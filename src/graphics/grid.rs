@@ -0,0 +1,69 @@
+use super::color::output_color;
+use super::mesh::Mesh;
+use super::shader::Shader;
+use super::state::{BlendMode, RenderState};
+use math::*;
+
+//
+// Anti-aliased ground grid on the XZ plane. Drawn as a single large quad,
+// with the grid lines computed in the fragment shader and faded out with
+// distance from the viewer.
+//
+pub struct GroundGrid {
+    shader: Shader,
+    mesh: Mesh,
+    pub height: f32,
+    pub extent: f32,
+    pub cell_size: f32,
+    pub fade_distance: f32,
+    pub color: [f32; 4],
+}
+
+#[allow(dead_code)]
+impl GroundGrid {
+    pub fn new() -> Result<GroundGrid, String> {
+        let shader = try!(Shader::new(
+            include_str!("../shaders/grid.vert"),
+            None,
+            include_str!("../shaders/grid.frag"),
+            Some(&["vpos"]),
+        ));
+        Ok(GroundGrid {
+            shader,
+            mesh: Mesh::quad(),
+            height: 0.0,
+            extent: 100.0,
+            cell_size: 1.0,
+            fade_distance: 30.0,
+            color: [0.5, 0.5, 0.5, 1.0],
+        })
+    }
+
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn draw(&self, view_proj: &Mat4, eye_pos: &Vec3) {
+        // Lay the XY quad down onto the XZ plane
+        let model = scale(
+            &rotate_x(
+                &translation(&vec3(0.0, self.height, 0.0)),
+                -90.0_f32.to_radians(),
+            ),
+            &vec3(self.extent, self.extent, 1.0),
+        );
+        self.shader.activate();
+        self.shader.set_uniform("model", model.as_ref());
+        self.shader.set_uniform("vp", view_proj.as_ref());
+        self.shader.set_uniform("eye_pos", eye_pos.as_ref());
+        self.shader.set_uniform("line_color", &output_color(&self.color));
+        self.shader.set_uniform("cell_size", self.cell_size);
+        self.shader.set_uniform("fade_dist", self.fade_distance);
+        let prev_state = RenderState::current()
+            .with_blend(BlendMode::Alpha)
+            .apply();
+        self.mesh.draw();
+        prev_state.apply();
+    }
+}
@@ -0,0 +1,578 @@
+//
+// Font agnostic paragraph layout. Works purely on font metrics and glyph
+// advances, so it can be exercised without a GL context or a glyph cache.
+// Mixed direction text goes through a simplified bidi reordering, while
+// complex shaping (contextual forms, ligatures) and mirroring aren't handled.
+//
+
+/// Distance between the baselines of consecutive lines
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineHeight {
+    /// The font's own ascent - descent + line gap
+    Normal,
+    /// A multiple of the font's own line height
+    Multiplier(f32),
+    /// A fixed height in pixels
+    Pixels(f32),
+}
+
+/// Base direction of a paragraph
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+/// Font metrics and spacing (in pixels) used to place glyphs and stack lines
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub line_height: LineHeight,
+    /// Extra space added to every glyph's advance
+    pub letter_spacing: f32,
+    pub direction: TextDirection,
+}
+
+impl LayoutMetrics {
+    pub fn advance_height(&self) -> f32 {
+        let natural = self.ascent - self.descent + self.line_gap;
+        match self.line_height {
+            LineHeight::Normal => natural,
+            LineHeight::Multiplier(m) => natural * m,
+            LineHeight::Pixels(px) => px,
+        }
+    }
+}
+
+/// A single character placed on a line
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutGlyph {
+    /// Byte offset of the character in the source string
+    pub index: usize,
+    pub chr: char,
+    /// Caret position of the glyph origin relative to the left edge of the
+    /// paragraph
+    pub x: f32,
+    pub advance: f32,
+    /// Whether the glyph belongs to a right to left run
+    pub rtl: bool,
+}
+
+impl LayoutGlyph {
+    // Caret position before the glyph's character
+    fn leading_x(&self) -> f32 {
+        if self.rtl {
+            self.x + self.advance
+        } else {
+            self.x
+        }
+    }
+
+    // Caret position after the glyph's character
+    fn trailing_x(&self) -> f32 {
+        if self.rtl {
+            self.x
+        } else {
+            self.x + self.advance
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.index + self.chr.len_utf8()
+    }
+}
+
+/// A laid out line of text
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub glyphs: Vec<LayoutGlyph>,
+    pub width: f32,
+    /// Left edge of the line, past the paragraph's left edge on the shorter
+    /// lines of right to left paragraphs
+    pub x: f32,
+    pub baseline_y: f32,
+    /// Byte offset in the source string where the line starts, which
+    /// locates lines without glyphs
+    pub start: usize,
+}
+
+impl Line {
+    fn new(baseline_y: f32, start: usize) -> Self {
+        Line {
+            glyphs: Vec::new(),
+            width: 0.0,
+            x: 0.0,
+            baseline_y,
+            start,
+        }
+    }
+
+    // Caret position right after the last glyph
+    fn end_x(&self) -> f32 {
+        self.glyphs.last().map_or(0.0, |g| g.x + g.advance)
+    }
+
+    // Reorders the glyphs from logical to visual order and places them left
+    // to right. Runs of characters with the same resolved direction are laid
+    // out in base direction order, and right to left runs are reversed.
+    // Kerning is dropped on reordered lines
+    fn reorder(&mut self, direction: TextDirection) {
+        let rtl_base = direction == TextDirection::RightToLeft;
+        if !rtl_base && !self.glyphs.iter().any(|g| strong_rtl(g.chr) == Some(true)) {
+            return;
+        }
+        // Neutrals take the direction of the strong characters around them
+        // when both sides agree, and the base direction otherwise
+        let strong = self
+            .glyphs
+            .iter()
+            .map(|g| strong_rtl(g.chr))
+            .collect::<Vec<_>>();
+        let mut prev_strong = Vec::with_capacity(strong.len());
+        let mut last = None;
+        for s in &strong {
+            last = s.or(last);
+            prev_strong.push(last);
+        }
+        let mut next = None;
+        for (i, s) in strong.iter().enumerate().rev() {
+            next = s.or(next);
+            self.glyphs[i].rtl = match (*s, prev_strong[i], next) {
+                (Some(rtl), _, _) => rtl,
+                (None, Some(a), Some(b)) if a == b => a,
+                _ => rtl_base,
+            };
+        }
+
+        let mut runs: Vec<Vec<LayoutGlyph>> = Vec::new();
+        for g in self.glyphs.drain(..) {
+            let same_run = runs.last().map_or(false, |r| r[0].rtl == g.rtl);
+            if same_run {
+                runs.last_mut().unwrap().push(g);
+            } else {
+                runs.push(vec![g]);
+            }
+        }
+        if rtl_base {
+            runs.reverse();
+        }
+        let mut caret = 0.0;
+        for mut run in runs {
+            if run[0].rtl {
+                run.reverse();
+            }
+            for mut g in run {
+                g.x = caret;
+                caret += g.advance;
+                self.glyphs.push(g);
+            }
+        }
+        self.width = caret;
+    }
+
+    // Moves the line `dx` to the right
+    fn indent(&mut self, dx: f32) {
+        self.x += dx;
+        for g in &mut self.glyphs {
+            g.x += dx;
+        }
+    }
+
+    // Drops trailing whitespace so it doesn't count towards the line width
+    fn trim_end(&mut self) {
+        while self.glyphs.last().map_or(false, |g| g.chr.is_whitespace()) {
+            self.glyphs.pop();
+        }
+        self.width = self.end_x();
+    }
+}
+
+// Some(true) for right to left characters (Hebrew, Arabic and related
+// scripts), Some(false) for other letters and digits, None for neutrals.
+// Arabic-Indic digits are weak and count as neutrals
+fn strong_rtl(c: char) -> Option<bool> {
+    match c as u32 {
+        0x0660..=0x0669 | 0x06F0..=0x06F9 => None,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF => Some(true),
+        _ if c.is_alphanumeric() => Some(false),
+        _ => None,
+    }
+}
+
+// Finishes `line` and returns the one that follows it, starting at byte
+// offset `start`
+fn next_line(lines: &mut Vec<Line>, line: Line, advance_height: f32, start: usize) -> Line {
+    let baseline_y = line.baseline_y + advance_height;
+    lines.push(line);
+    Line::new(baseline_y, start)
+}
+
+/// Breaks `text` into lines no wider than `wrap_width`, using `advance` to
+/// query a character's advance width and `kerning` to query the kerning
+/// between a pair of consecutive characters. Lines are wrapped at the last
+/// whitespace that fits, and words longer than a whole line are split.
+/// Lines are broken in logical order and reordered for display afterwards.
+pub fn layout_paragraph<A, K>(
+    text: &str,
+    metrics: &LayoutMetrics,
+    wrap_width: f32,
+    advance: A,
+    kerning: K,
+) -> Vec<Line>
+where
+    A: Fn(char) -> f32,
+    K: Fn(char, char) -> f32,
+{
+    let advance_height = metrics.advance_height();
+    let mut lines = Vec::new();
+    let mut line = Line::new(metrics.ascent, 0);
+    let mut caret = 0.0;
+    let mut last_chr = None;
+    // Glyph index in the current line where the last word starts
+    let mut word_start = None;
+    let mut prev_chr = None;
+    for (index, c) in text.char_indices() {
+        // A "\r\n" pair only breaks the line once
+        let crlf = c == '\n' && prev_chr == Some('\r');
+        prev_chr = Some(c);
+        if c.is_control() {
+            match c {
+                // The line got started by the '\r' already
+                '\n' if crlf => line.start = index + 1,
+                '\r' | '\n' => {
+                    line = next_line(&mut lines, line, advance_height, index + 1);
+                    caret = 0.0;
+                    last_chr = None;
+                    word_start = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if let Some(prev) = last_chr.take() {
+            caret += kerning(prev, c);
+        }
+        let adv = advance(c) + metrics.letter_spacing;
+        if caret + adv > wrap_width && !line.glyphs.is_empty() {
+            if c.is_whitespace() {
+                // Whitespace at the break is swallowed
+                line.trim_end();
+                line = next_line(&mut lines, line, advance_height, index + c.len_utf8());
+                caret = 0.0;
+                word_start = None;
+                continue;
+            }
+            // Carry the partial word over to the next line, unless it
+            // spans the whole line already
+            let word = match word_start.take() {
+                Some(start) if start > 0 => line.glyphs.split_off(start),
+                _ => Vec::new(),
+            };
+            line.trim_end();
+            let start = word.first().map_or(index, |g| g.index);
+            line = next_line(&mut lines, line, advance_height, start);
+            let shift = word.first().map_or(caret, |g| g.x);
+            caret -= shift;
+            if !word.is_empty() {
+                word_start = Some(0);
+            }
+            line.glyphs = word
+                .into_iter()
+                .map(|g| LayoutGlyph { x: g.x - shift, ..g })
+                .collect();
+        }
+        if c.is_whitespace() {
+            word_start = None;
+        } else if word_start.is_none() {
+            word_start = Some(line.glyphs.len());
+        }
+        last_chr = Some(c);
+        line.glyphs.push(LayoutGlyph {
+            index,
+            chr: c,
+            x: caret,
+            advance: adv,
+            rtl: false,
+        });
+        caret += adv;
+        line.width = caret;
+    }
+    lines.push(line);
+    for line in &mut lines {
+        line.reorder(metrics.direction);
+    }
+    // Right to left paragraphs are anchored on the right, so the lines
+    // shorter than the widest one get moved over
+    if metrics.direction == TextDirection::RightToLeft {
+        let width = lines.iter().fold(0.0f32, |w, l| w.max(l.width));
+        for line in &mut lines {
+            let dx = width - line.width;
+            line.indent(dx);
+        }
+    }
+    lines
+}
+
+/// Byte offset of the caret position nearest to (`x`, `y`), given in the
+/// space of the laid out lines: y grows downwards from the top of the first
+/// line. Points past the end of a line map to the end of that line, and
+/// points above or below the text to the first or last line
+pub fn hit_test(lines: &[Line], metrics: &LayoutMetrics, x: f32, y: f32) -> usize {
+    if lines.is_empty() {
+        return 0;
+    }
+    let row = (y / metrics.advance_height()).floor().max(0.0) as usize;
+    let line = &lines[row.min(lines.len() - 1)];
+    // The caret lands on the left or right edge of the glyph under the point
+    let left_edge = |g: &LayoutGlyph| if g.rtl { g.end() } else { g.index };
+    let right_edge = |g: &LayoutGlyph| if g.rtl { g.index } else { g.end() };
+    match line.glyphs.iter().find(|g| x < g.x + g.advance / 2.0) {
+        Some(g) => left_edge(g),
+        None => line.glyphs.last().map_or(line.start, right_edge),
+    }
+}
+
+/// Caret position before the character at byte offset `index`, as the x
+/// offset from the line start and the baseline of its line. Offsets past
+/// the end of a line place the caret after its last glyph, so the caret
+/// after a character is the one before the character that follows it
+pub fn caret_position(lines: &[Line], index: usize) -> (f32, f32) {
+    let line = match lines.iter().rev().find(|l| l.start <= index) {
+        Some(line) => line,
+        None => return (0.0, 0.0),
+    };
+    // Glyphs are in visual order, so look the characters up by offset
+    let next = line
+        .glyphs
+        .iter()
+        .filter(|g| g.index >= index)
+        .min_by_key(|g| g.index);
+    let x = match next {
+        Some(g) => g.leading_x(),
+        None => line
+            .glyphs
+            .iter()
+            .max_by_key(|g| g.index)
+            .map_or(line.x, |g| g.trailing_x()),
+    };
+    (x, line.baseline_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monospaced stand-in font: 10px advances, 10px lines, no kerning
+    fn metrics() -> LayoutMetrics {
+        LayoutMetrics {
+            ascent: 8.0,
+            descent: -2.0,
+            line_gap: 0.0,
+            line_height: LineHeight::Normal,
+            letter_spacing: 0.0,
+            direction: TextDirection::LeftToRight,
+        }
+    }
+
+    fn layout(text: &str, metrics: &LayoutMetrics, wrap_width: f32) -> Vec<Line> {
+        layout_paragraph(text, metrics, wrap_width, |_| 10.0, |_, _| 0.0)
+    }
+
+    fn line_texts(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| l.glyphs.iter().map(|g| g.chr).collect())
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_last_whitespace() {
+        let lines = layout("hello world foo", &metrics(), 60.0);
+        assert_eq!(line_texts(&lines), ["hello", "world", "foo"]);
+        assert_eq!(lines[0].width, 50.0);
+        assert_eq!(lines[1].start, 6);
+    }
+
+    #[test]
+    fn carries_partial_word_over() {
+        let lines = layout("ab cdef", &metrics(), 50.0);
+        assert_eq!(line_texts(&lines), ["ab", "cdef"]);
+        assert_eq!(lines[1].glyphs[0].x, 0.0);
+    }
+
+    #[test]
+    fn splits_overlong_words() {
+        let lines = layout("abcdefgh", &metrics(), 30.0);
+        assert_eq!(line_texts(&lines), ["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn newline_is_a_hard_break() {
+        let lines = layout("ab\ncd ef", &metrics(), 1000.0);
+        assert_eq!(line_texts(&lines), ["ab", "cd ef"]);
+    }
+
+    #[test]
+    fn newlines_stack_lines() {
+        let lines = layout("a\nb\nc", &metrics(), 1000.0);
+        assert_eq!(line_texts(&lines), ["a", "b", "c"]);
+        assert!(lines[0].baseline_y < lines[1].baseline_y);
+        assert!(lines[1].baseline_y < lines[2].baseline_y);
+        assert_eq!(lines[1].baseline_y - lines[0].baseline_y, 10.0);
+    }
+
+    #[test]
+    fn crlf_breaks_once() {
+        let lines = layout("a\r\nb\rc", &metrics(), 1000.0);
+        assert_eq!(line_texts(&lines), ["a", "b", "c"]);
+        assert_eq!(lines[1].start, 3);
+    }
+
+    #[test]
+    fn line_height_scales_line_advance() {
+        let doubled = LayoutMetrics {
+            line_height: LineHeight::Multiplier(2.0),
+            ..metrics()
+        };
+        let lines = layout("a\nb", &doubled, 1000.0);
+        assert_eq!(lines[1].baseline_y - lines[0].baseline_y, 20.0);
+        let fixed = LayoutMetrics {
+            line_height: LineHeight::Pixels(14.0),
+            ..metrics()
+        };
+        let lines = layout("a\nb", &fixed, 1000.0);
+        assert_eq!(lines[1].baseline_y - lines[0].baseline_y, 14.0);
+    }
+
+    #[test]
+    fn letter_spacing_shifts_carets() {
+        let spaced = LayoutMetrics {
+            letter_spacing: 2.0,
+            ..metrics()
+        };
+        let lines = layout("abc", &spaced, 1000.0);
+        let xs = lines[0].glyphs.iter().map(|g| g.x).collect::<Vec<_>>();
+        assert_eq!(xs, [0.0, 12.0, 24.0]);
+        assert_eq!(lines[0].width, 36.0);
+        assert_eq!(caret_position(&lines, 2), (24.0, 8.0));
+    }
+
+    #[test]
+    fn letter_spacing_counts_towards_wrapping() {
+        let spaced = LayoutMetrics {
+            letter_spacing: 5.0,
+            ..metrics()
+        };
+        let lines = layout("abcd", &spaced, 50.0);
+        assert_eq!(line_texts(&lines), ["abc", "d"]);
+    }
+
+    #[test]
+    fn click_before_first_glyph_hits_start() {
+        let lines = layout("ab\ncd", &metrics(), 1000.0);
+        assert_eq!(hit_test(&lines, &metrics(), -5.0, 2.0), 0);
+    }
+
+    #[test]
+    fn click_between_glyphs_hits_nearest_edge() {
+        let lines = layout("ab\ncd", &metrics(), 1000.0);
+        assert_eq!(hit_test(&lines, &metrics(), 14.0, 2.0), 1);
+        assert_eq!(hit_test(&lines, &metrics(), 16.0, 2.0), 2);
+    }
+
+    #[test]
+    fn click_past_line_end_hits_line_end() {
+        let lines = layout("ab\ncd", &metrics(), 1000.0);
+        assert_eq!(hit_test(&lines, &metrics(), 1000.0, 2.0), 2);
+    }
+
+    #[test]
+    fn click_on_second_line() {
+        let lines = layout("ab\ncd", &metrics(), 1000.0);
+        assert_eq!(hit_test(&lines, &metrics(), 12.0, 15.0), 4);
+        // Below the text, the last line is used
+        assert_eq!(hit_test(&lines, &metrics(), 0.0, 500.0), 3);
+    }
+
+    #[test]
+    fn click_on_empty_line_hits_its_start() {
+        let lines = layout("a\n\nb", &metrics(), 1000.0);
+        assert_eq!(hit_test(&lines, &metrics(), 50.0, 15.0), 2);
+        assert_eq!(caret_position(&lines, 2), (0.0, 18.0));
+    }
+
+    #[test]
+    fn caret_at_end_of_text() {
+        let lines = layout("ab\ncd", &metrics(), 1000.0);
+        assert_eq!(caret_position(&lines, 5), (20.0, 18.0));
+        assert_eq!(caret_position(&lines, 0), (0.0, 8.0));
+    }
+
+    fn rtl_metrics() -> LayoutMetrics {
+        LayoutMetrics {
+            direction: TextDirection::RightToLeft,
+            ..metrics()
+        }
+    }
+
+    #[test]
+    fn rtl_line_is_reversed() {
+        let lines = layout("אבג", &rtl_metrics(), 100.0);
+        assert_eq!(line_texts(&lines), ["גבא"]);
+        let xs = lines[0].glyphs.iter().map(|g| g.x).collect::<Vec<_>>();
+        assert_eq!(xs, [0.0, 10.0, 20.0]);
+        assert!(lines[0].glyphs.iter().all(|g| g.rtl));
+    }
+
+    #[test]
+    fn mixed_direction_runs_get_reordered() {
+        let lines = layout("abc אבג def", &metrics(), 200.0);
+        assert_eq!(line_texts(&lines), ["abc גבא def"]);
+        let lines = layout("אב cd", &rtl_metrics(), 200.0);
+        assert_eq!(line_texts(&lines), ["cd בא"]);
+    }
+
+    #[test]
+    fn arabic_indic_digits_are_weak() {
+        assert_eq!(strong_rtl('\u{0663}'), None);
+        assert_eq!(strong_rtl('\u{06F3}'), None);
+        assert_eq!(strong_rtl('\u{0627}'), Some(true));
+        // They follow the surrounding text instead of starting a run
+        let lines = layout("ab \u{0663} cd", &metrics(), 200.0);
+        assert!(lines[0].glyphs.iter().all(|g| !g.rtl));
+    }
+
+    #[test]
+    fn rtl_carets_run_right_to_left() {
+        // Visual order is ג ב א, each glyph 2 bytes long
+        let lines = layout("אבג", &rtl_metrics(), 100.0);
+        assert_eq!(caret_position(&lines, 0), (30.0, 8.0));
+        assert_eq!(caret_position(&lines, 2), (20.0, 8.0));
+        assert_eq!(caret_position(&lines, 6), (0.0, 8.0));
+    }
+
+    #[test]
+    fn rtl_hit_test_maps_to_logical_offsets() {
+        let lines = layout("אבג", &rtl_metrics(), 100.0);
+        let m = rtl_metrics();
+        assert_eq!(hit_test(&lines, &m, 28.0, 5.0), 0);
+        assert_eq!(hit_test(&lines, &m, 12.0, 5.0), 4);
+        assert_eq!(hit_test(&lines, &m, 1.0, 5.0), 6);
+    }
+
+    #[test]
+    fn rtl_lines_are_right_anchored() {
+        let lines = layout("אבג דה", &rtl_metrics(), 35.0);
+        assert_eq!(line_texts(&lines), ["גבא", "הד"]);
+        assert_eq!(lines[1].x, 10.0);
+        assert_eq!(lines[1].glyphs[0].x, 10.0);
+        // An empty trailing line keeps its caret at the right edge
+        let lines = layout("אבג\n", &rtl_metrics(), 100.0);
+        assert_eq!(caret_position(&lines, 7), (30.0, 18.0));
+    }
+}
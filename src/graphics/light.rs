@@ -0,0 +1,127 @@
+use super::shader::{Shader, Uniform};
+use math::*;
+
+/// Shader using the lights uploaded with `upload_point_lights` and
+/// `upload_spot_lights`, to pair with the default vertex shader
+#[allow(dead_code)]
+pub const FORWARD_FRAGMENT_SHADER: &str = include_str!("../shaders/forward.frag");
+/// Array sizes in the forward shader, extra lights are left out
+pub const MAX_POINT_LIGHTS: usize = 8;
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+//
+// Light sources for forward shading. Colors are linear and premultiplied by
+// the light's intensity, so they may go above 1. Both kinds fall off with
+// the inverse square of the distance.
+//
+
+/// Light radiating in all directions, fading out completely at `radius`
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: [f32; 3],
+    pub radius: f32,
+}
+
+/// Light shining a cone along `direction`. Full intensity within
+/// `inner_cone` radians of the direction, fading out towards `outer_cone`
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: [f32; 3],
+    pub inner_cone: f32,
+    pub outer_cone: f32,
+}
+
+// Name of a field of a light in one of the shader's light arrays
+fn light_uniform(array: &str, index: usize, field: &str) -> String {
+    format!("{}[{}].{}", array, index, field)
+}
+
+/// Sets the `point_lights` array and `num_point_lights` count of an active
+/// shader, up to MAX_POINT_LIGHTS lights
+#[allow(dead_code)]
+pub fn upload_point_lights(shader: &Shader, lights: &[PointLight]) {
+    for (name, value) in point_light_uniforms(lights) {
+        shader.set_uniform(&name, value);
+    }
+}
+
+// Every uniform set by upload_point_lights along with its name
+fn point_light_uniforms<'a>(lights: &'a [PointLight]) -> Vec<(String, Uniform<'a>)> {
+    let lights = &lights[..lights.len().min(MAX_POINT_LIGHTS)];
+    let mut uniforms = Vec::with_capacity(lights.len() * 3 + 1);
+    for (i, light) in lights.iter().enumerate() {
+        let name = |field| light_uniform("point_lights", i, field);
+        uniforms.push((name("position"), Uniform::from(light.position.as_ref())));
+        uniforms.push((name("color"), Uniform::from(&light.color)));
+        uniforms.push((name("radius"), Uniform::from(light.radius)));
+    }
+    let count = Uniform::from(lights.len() as i32);
+    uniforms.push(("num_point_lights".to_string(), count));
+    uniforms
+}
+
+/// Sets the `spot_lights` array and `num_spot_lights` count of an active
+/// shader, up to MAX_SPOT_LIGHTS lights. Cone angles are passed on as
+/// cosines and directions normalized
+#[allow(dead_code)]
+pub fn upload_spot_lights(shader: &Shader, lights: &[SpotLight]) {
+    let lights = &lights[..lights.len().min(MAX_SPOT_LIGHTS)];
+    for (i, light) in lights.iter().enumerate() {
+        let name = |field| light_uniform("spot_lights", i, field);
+        shader.set_uniform(&name("position"), light.position.as_ref());
+        shader.set_uniform(&name("direction"), normalize(&light.direction).as_ref());
+        shader.set_uniform(&name("color"), &light.color);
+        shader.set_uniform(&name("cos_inner"), light.inner_cone.cos());
+        shader.set_uniform(&name("cos_outer"), light.outer_cone.cos());
+    }
+    shader.set_uniform("num_spot_lights", lights.len() as i32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(x: f32) -> PointLight {
+        PointLight {
+            position: vec3(x, 2.0, 3.0),
+            color: [1.0, 0.5, 0.25],
+            radius: 10.0 + x,
+        }
+    }
+
+    #[test]
+    fn point_light_fields_map_to_indexed_names() {
+        let lights = [light(0.0), light(1.0)];
+        let uniforms = point_light_uniforms(&lights);
+        assert_eq!(uniforms.len(), 7);
+        assert_eq!(uniforms[3].0, "point_lights[1].position");
+        assert_eq!(uniforms[3].1, Uniform::Float3(&[1.0, 2.0, 3.0]));
+        assert_eq!(uniforms[4].0, "point_lights[1].color");
+        assert_eq!(uniforms[4].1, Uniform::Float3(&[1.0, 0.5, 0.25]));
+        assert_eq!(uniforms[5].0, "point_lights[1].radius");
+        assert_eq!(uniforms[5].1, Uniform::Float1(11.0));
+        assert_eq!(uniforms[6].0, "num_point_lights");
+        assert_eq!(uniforms[6].1, Uniform::Int1(2));
+    }
+
+    #[test]
+    fn point_lights_past_the_maximum_are_dropped() {
+        let lights = (0..MAX_POINT_LIGHTS + 3)
+            .map(|i| light(i as f32))
+            .collect::<Vec<_>>();
+        let uniforms = point_light_uniforms(&lights);
+        let last = format!("point_lights[{}].radius", MAX_POINT_LIGHTS - 1);
+        assert!(uniforms.iter().any(|u| u.0 == last));
+        let past = format!("point_lights[{}].position", MAX_POINT_LIGHTS);
+        assert!(!uniforms.iter().any(|u| u.0 == past));
+        assert_eq!(
+            uniforms.last().unwrap().1,
+            Uniform::Int1(MAX_POINT_LIGHTS as i32)
+        );
+    }
+}
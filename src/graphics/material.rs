@@ -0,0 +1,123 @@
+use super::mesh::Mesh;
+use super::shader::Shader;
+use super::texture::Texture;
+use std::rc::Rc;
+
+/// Surface parameters fed to a shader before drawing a mesh
+pub struct Material {
+    /// Fragments whose alpha falls below this threshold are discarded.
+    /// Used for cutout textures (foliage, fences, decals) in the opaque pass.
+    /// A value of 0.0 disables alpha testing.
+    pub alpha_cutoff: f32,
+    // Sampler uniform and texture pairs, bound to consecutive units from 0
+    textures: Vec<(String, Rc<Texture>)>,
+}
+
+#[allow(dead_code)]
+impl Material {
+    pub fn new() -> Self {
+        Material {
+            alpha_cutoff: 0.0,
+            textures: Vec::new(),
+        }
+    }
+
+    pub fn with_alpha_cutoff(mut self, alpha_cutoff: f32) -> Self {
+        self.alpha_cutoff = alpha_cutoff;
+        self
+    }
+
+    /// Adds a texture (e.g. albedo, normal or roughness map) sampled through
+    /// the given sampler uniform. Textures take consecutive units in the
+    /// order they were added, replacing a texture keeps its unit
+    pub fn with_texture(mut self, uniform: &str, texture: Rc<Texture>) -> Self {
+        set_sampler(&mut self.textures, uniform, texture);
+        self
+    }
+
+    /// Number of texture units the material's own textures occupy. Extra
+    /// textures passed to `draw` should use the units from here on
+    pub fn texture_units(&self) -> u32 {
+        self.textures.len() as u32
+    }
+
+    /// Sets the material's uniforms and binds its textures
+    pub fn apply(&self, shdr: &Shader) {
+        shdr.set_uniform("alpha_cutoff", self.alpha_cutoff);
+        for (uniform, tex, unit) in sampler_units(&self.textures) {
+            tex.bind(unit);
+            shdr.set_uniform(uniform, unit as i32);
+        }
+    }
+
+    /// Applies the material, binds the given extra textures and draws the
+    /// mesh. The shader is expected to be active.
+    pub fn draw(&self, shdr: &Shader, mesh: &Mesh, textures: &[(&str, &Texture, u32)]) {
+        self.apply(shdr);
+        bind_textures(shdr, textures);
+        mesh.draw();
+    }
+}
+
+// Points `uniform` to `texture`, appending it when the sampler is new
+fn set_sampler<T>(textures: &mut Vec<(String, T)>, uniform: &str, texture: T) {
+    match textures.iter().position(|(u, _)| u == uniform) {
+        Some(i) => textures[i].1 = texture,
+        None => textures.push((uniform.to_string(), texture)),
+    }
+}
+
+// Texture unit of each sampler, consecutive from 0 in insertion order
+fn sampler_units<T>(textures: &[(String, T)]) -> Vec<(&str, &T, u32)> {
+    textures
+        .iter()
+        .enumerate()
+        .map(|(unit, (uniform, tex))| (&uniform[..], tex, unit as u32))
+        .collect()
+}
+
+/// Binds each `(sampler uniform, texture, unit)` triple, pointing the
+/// sampler uniform of the active shader to the texture's unit
+pub fn bind_textures(shdr: &Shader, textures: &[(&str, &Texture, u32)]) {
+    for (uniform, tex, unit) in textures {
+        tex.bind(*unit);
+        shdr.set_uniform(uniform, *unit as i32);
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samplers_take_consecutive_units_in_order() {
+        let mut textures = Vec::new();
+        set_sampler(&mut textures, "albedo", 'a');
+        set_sampler(&mut textures, "normal", 'n');
+        set_sampler(&mut textures, "roughness", 'r');
+        assert_eq!(
+            sampler_units(&textures),
+            [
+                ("albedo", &'a', 0),
+                ("normal", &'n', 1),
+                ("roughness", &'r', 2)
+            ]
+        );
+        // Replacing a texture keeps its unit
+        set_sampler(&mut textures, "normal", 'N');
+        assert_eq!(
+            sampler_units(&textures),
+            [
+                ("albedo", &'a', 0),
+                ("normal", &'N', 1),
+                ("roughness", &'r', 2)
+            ]
+        );
+    }
+}
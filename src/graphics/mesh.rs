@@ -1,7 +1,11 @@
+use assets::Shape;
 use gl;
+use super::stats;
 use gl::types::*;
+use math::Aabb;
 use std;
 
+#[allow(dead_code)]
 #[derive(Clone, Copy)]
 pub enum Vattr {
     Position = 0,
@@ -10,6 +14,10 @@ pub enum Vattr {
     UV1,
     Tangent,
     Color,
+    /// Indices of the 4 joints influencing a vertex, stored as floats
+    Joints,
+    /// Skinning weights of the 4 joints
+    Weights,
 }
 
 // Parallel to the Vattr enum
@@ -20,32 +28,235 @@ const VATTR_MAP: &[(GLenum, usize)] = &[
     (gl::FLOAT, 2),
     (gl::FLOAT, 3),
     (gl::FLOAT, 3),
+    (gl::FLOAT, 4),
+    (gl::FLOAT, 4),
 ];
 
 pub fn vattr_flag(a: Vattr) -> u32 {
     1 << (a as u32)
 }
 
+fn component_size(component_type: GLenum) -> usize {
+    match component_type {
+        gl::BYTE => std::mem::size_of::<GLbyte>(),
+        gl::UNSIGNED_BYTE => std::mem::size_of::<GLubyte>(),
+        gl::SHORT => std::mem::size_of::<GLshort>(),
+        gl::UNSIGNED_SHORT => std::mem::size_of::<GLushort>(),
+        gl::INT => std::mem::size_of::<GLint>(),
+        gl::UNSIGNED_INT => std::mem::size_of::<GLuint>(),
+        gl::HALF_FLOAT => std::mem::size_of::<GLhalf>(),
+        gl::FLOAT => std::mem::size_of::<GLfloat>(),
+        gl::DOUBLE => std::mem::size_of::<GLdouble>(),
+        _ => 0,
+    }
+}
+
+/// Byte offsets of each enabled attribute in a planar vertex buffer, where
+/// every attribute stream of `num_verts` elements follows the previous one
+/// in Vattr order
+pub fn planar_offsets(num_verts: usize, attrib_mask: u32) -> Vec<(u32, usize)> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    for (attr_idx, (component_type, num_components)) in VATTR_MAP.iter().enumerate() {
+        if (attrib_mask & (1 << attr_idx)) != 0 {
+            offsets.push((attr_idx as u32, offset));
+            offset += num_verts * num_components * component_size(*component_type);
+        }
+    }
+    offsets
+}
+
+/// Number of floats a planar buffer of `num_verts` vertices holds
+pub fn planar_len(num_verts: usize, attrib_mask: u32) -> usize {
+    VATTR_MAP
+        .iter()
+        .enumerate()
+        .filter(|(attr_idx, _)| (attrib_mask & (1 << attr_idx)) != 0)
+        .map(|(_, (_, num_components))| num_verts * num_components)
+        .sum()
+}
+
+/// Element type of an index buffer
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    pub fn gl_type(self) -> GLenum {
+        match self {
+            IndexType::U16 => gl::UNSIGNED_SHORT,
+            IndexType::U32 => gl::UNSIGNED_INT,
+        }
+    }
+
+    /// Bytes taken by a single index
+    pub fn size(self) -> usize {
+        match self {
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        }
+    }
+}
+
+/// Index data of either element type, 16 bit indices halve the index buffer
+/// size of meshes with fewer than 65536 vertices
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Indices<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+#[allow(dead_code)]
+impl<'a> Indices<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(i) => i.len(),
+            Indices::U32(i) => i.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        match self {
+            Indices::U16(_) => IndexType::U16,
+            Indices::U32(_) => IndexType::U32,
+        }
+    }
+
+    fn as_bytes(&self) -> (*const GLvoid, GLsizeiptr) {
+        match self {
+            Indices::U16(i) => (i.as_ptr() as _, std::mem::size_of_val(*i) as _),
+            Indices::U32(i) => (i.as_ptr() as _, std::mem::size_of_val(*i) as _),
+        }
+    }
+}
+
+/// How the vertices of a mesh assemble into primitives
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Triangles,
+    TriangleStrip,
+    Lines,
+    LineStrip,
+    Points,
+}
+
+impl Primitive {
+    pub fn gl_mode(self) -> GLenum {
+        match self {
+            Primitive::Triangles => gl::TRIANGLES,
+            Primitive::TriangleStrip => gl::TRIANGLE_STRIP,
+            Primitive::Lines => gl::LINES,
+            Primitive::LineStrip => gl::LINE_STRIP,
+            Primitive::Points => gl::POINTS,
+        }
+    }
+
+    // Triangles rasterized for the given element count, for the frame stats
+    fn triangle_count(self, count: usize) -> u32 {
+        match self {
+            Primitive::Triangles => (count / 3) as u32,
+            Primitive::TriangleStrip => count.saturating_sub(2) as u32,
+            _ => 0,
+        }
+    }
+}
+
+// Arguments of a glDrawElements call covering part of an index buffer
+#[derive(Debug, PartialEq)]
+struct ElementRange {
+    count: GLsizei,
+    index_type: GLenum,
+    // Byte offset of the first index into the index buffer
+    offset: usize,
+}
+
+impl ElementRange {
+    fn new(
+        first_index: usize,
+        count: usize,
+        num_indcs: usize,
+        index_type: IndexType,
+    ) -> Result<Self, String> {
+        if first_index + count > num_indcs {
+            return Err(format!(
+                "Index range {}..{} out of bounds, mesh has {} indices",
+                first_index,
+                first_index + count,
+                num_indcs
+            ));
+        }
+        Ok(ElementRange {
+            count: count as GLsizei,
+            index_type: index_type.gl_type(),
+            offset: first_index * index_type.size(),
+        })
+    }
+}
+
 pub struct Mesh {
+    // 0 when the context lacks vertex array objects
+    vao: GLuint,
     vbo: GLuint,
+    // Vertex buffer size in floats
+    vbo_len: usize,
     ebo: GLuint,
     num_verts: usize,
     num_indcs: usize,
+    index_type: IndexType,
     attrib_mask: u32,
+    primitive: Primitive,
+    aabb: Aabb,
 }
 
+#[allow(dead_code)]
 impl Mesh {
     pub fn from_data(
         vdata: &[f32],
         num_verts: usize,
-        indices: Option<&[u32]>,
+        indices: Option<Indices>,
+        attrib_mask: u32,
+    ) -> Mesh {
+        Self::from_data_with_usage(vdata, num_verts, indices, attrib_mask, gl::STATIC_DRAW)
+    }
+
+    /// Same as `from_data`, but hints the driver that the vertex data
+    /// will be changed through `update_vertices`
+    pub fn from_dynamic_data(
+        vdata: &[f32],
+        num_verts: usize,
+        indices: Option<Indices>,
         attrib_mask: u32,
     ) -> Mesh {
+        Self::from_data_with_usage(vdata, num_verts, indices, attrib_mask, gl::DYNAMIC_DRAW)
+    }
+
+    fn from_data_with_usage(
+        vdata: &[f32],
+        num_verts: usize,
+        indices: Option<Indices>,
+        attrib_mask: u32,
+        usage: GLenum,
+    ) -> Mesh {
+        // num_verts counts vertices, not floats
+        debug_assert_eq!(
+            vdata.len(),
+            planar_len(num_verts, attrib_mask),
+            "Vertex data size does not match vertex count and attributes"
+        );
         let mut vbo: GLuint = 0;
         let mut ebo: GLuint = 0;
-        let num_indcs = match indices {
-            Some(indices) => indices.len(),
-            None => 0,
+        let (num_indcs, index_type) = match indices {
+            Some(indices) => (indices.len(), indices.index_type()),
+            None => (0, IndexType::U32),
         };
         unsafe {
             gl::GenBuffers(1, &mut vbo);
@@ -54,102 +265,348 @@ impl Mesh {
                 gl::ARRAY_BUFFER,
                 std::mem::size_of_val(vdata) as GLsizeiptr,
                 vdata.as_ptr() as *const GLvoid,
-                gl::STATIC_DRAW,
+                usage,
             );
+            stats::record_buffer_upload();
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             if let Some(indices) = indices {
                 gl::GenBuffers(1, &mut ebo);
                 gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-                gl::BufferData(
-                    gl::ELEMENT_ARRAY_BUFFER,
-                    std::mem::size_of_val(indices) as GLsizeiptr,
-                    indices.as_ptr() as *const GLvoid,
-                    gl::STATIC_DRAW,
-                );
+                let (data, size) = indices.as_bytes();
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+                stats::record_buffer_upload();
                 gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
             }
         }
-        Mesh {
+        let mut mesh = Mesh {
+            vao: 0,
             vbo,
+            vbo_len: vdata.len(),
             ebo,
             num_verts,
             num_indcs,
+            index_type,
             attrib_mask,
+            primitive: Primitive::Triangles,
+            aabb: Aabb::empty(),
+        };
+        // Positions come first in the planar layout
+        if (attrib_mask & vattr_flag(Vattr::Position)) != 0 {
+            mesh.aabb = Aabb::from_positions(&vdata[..num_verts * 3]);
         }
+        mesh.vao = mesh.make_vao();
+        mesh
     }
 
-    fn attrib_setup(&self) {
-        let mut offset = 0;
-        for attr in [
-            Vattr::Position,
-            Vattr::Normal,
-            Vattr::UV0,
-            Vattr::UV1,
-            Vattr::Tangent,
-            Vattr::Color,
-        ]
-            .iter()
-        {
-            if (self.attrib_mask & vattr_flag(*attr)) != 0 {
-                let attr_idx = *attr as u32;
-                let (component_type, num_components) = VATTR_MAP[attr_idx as usize];
-                unsafe {
-                    gl::EnableVertexAttribArray(attr_idx);
-                    gl::VertexAttribPointer(
-                        attr_idx,
-                        num_components as GLint,
-                        component_type,
-                        gl::FALSE,
-                        0,
-                        offset as *const GLvoid,
-                    );
-                }
-                offset += self.num_verts
-                    * num_components
-                    * (match component_type {
-                        gl::BYTE => std::mem::size_of::<GLbyte>(),
-                        gl::UNSIGNED_BYTE => std::mem::size_of::<GLubyte>(),
-                        gl::SHORT => std::mem::size_of::<GLshort>(),
-                        gl::UNSIGNED_SHORT => std::mem::size_of::<GLushort>(),
-                        gl::INT => std::mem::size_of::<GLint>(),
-                        gl::UNSIGNED_INT => std::mem::size_of::<GLuint>(),
-                        gl::HALF_FLOAT => std::mem::size_of::<GLhalf>(),
-                        gl::FLOAT => std::mem::size_of::<GLfloat>(),
-                        gl::DOUBLE => std::mem::size_of::<GLdouble>(),
-                        _ => 0,
-                    });
-            }
+    /// Overwrites part of the vertex buffer, starting `offset` floats into
+    /// it. The buffer never gets reallocated, so writing past its end is
+    /// an error
+    pub fn update_vertices(&self, offset: usize, data: &[f32]) -> Result<(), String> {
+        if offset + data.len() > self.vbo_len {
+            return Err(format!(
+                "Vertex update of {} floats at {} exceeds buffer of {}",
+                data.len(),
+                offset,
+                self.vbo_len
+            ));
         }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                (offset * std::mem::size_of::<f32>()) as GLintptr,
+                std::mem::size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const GLvoid,
+            );
+            stats::record_buffer_upload();
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        Ok(())
     }
 
-    pub fn draw(&self) {
+    /// Draws the vertices as the given primitive instead of triangles
+    pub fn with_primitive(mut self, primitive: Primitive) -> Mesh {
+        self.primitive = primitive;
+        self
+    }
+
+    // Records the attribute layout and index buffer once, so that drawing
+    // only needs to bind the vertex array
+    fn make_vao(&self) -> GLuint {
+        if !gl::GenVertexArrays::is_loaded() {
+            return 0;
+        }
+        let mut vao: GLuint = 0;
         unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
             gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
             self.attrib_setup();
             if self.is_indexed() {
                 gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-                gl::DrawElements(
-                    gl::TRIANGLES,
-                    self.num_indcs as GLsizei,
-                    gl::UNSIGNED_INT,
-                    std::ptr::null(),
-                );
-                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
-            } else {
-                gl::DrawArrays(gl::TRIANGLES, 0, self.num_verts as GLsizei);
             }
+            gl::BindVertexArray(0);
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        }
+        vao
+    }
+
+    // Builds a mesh from separate position, normal and uv0 streams
+    fn from_pnt(positions: &[f32], normals: &[f32], uvs: &[f32], indices: Indices) -> Mesh {
+        let mut vdata = Vec::with_capacity(positions.len() + normals.len() + uvs.len());
+        vdata.extend_from_slice(positions);
+        vdata.extend_from_slice(normals);
+        vdata.extend_from_slice(uvs);
+        Mesh::from_data(
+            &vdata,
+            positions.len() / 3,
+            Some(indices),
+            vattr_flag(Vattr::Position) | vattr_flag(Vattr::Normal) | vattr_flag(Vattr::UV0),
+        )
+    }
+
+    /// Uploads the positions, normals, uv0 and skinning streams of a shape,
+    /// skipping the streams the shape lacks
+    #[allow(dead_code)]
+    pub fn from_shape(shape: &Shape) -> Mesh {
+        let num_verts = shape.positions.len() / 3;
+        let mut vdata = Vec::new();
+        let mut attrib_mask = 0;
+        let joints = shape.joints.iter().map(|&j| j as f32).collect::<Vec<_>>();
+        let streams = [
+            (Vattr::Position, &shape.positions[..]),
+            (Vattr::Normal, &shape.normals[..]),
+            (Vattr::UV0, &shape.texcoords[..]),
+            (Vattr::Joints, &joints[..]),
+            (Vattr::Weights, &shape.weights[..]),
+        ];
+        // Streams are appended in Vattr order, as the planar layout expects
+        for &(attr, data) in streams.iter() {
+            let (_, num_components) = VATTR_MAP[attr as usize];
+            if !data.is_empty() && data.len() == num_verts * num_components {
+                vdata.extend_from_slice(data);
+                attrib_mask |= vattr_flag(attr);
+            }
+        }
+        Mesh::from_data(
+            &vdata,
+            num_verts,
+            Some(Indices::U32(&shape.indices)),
+            attrib_mask,
+        )
+    }
+
+    /// Quad spanning [-1, 1] on the XY plane, facing +Z
+    pub fn quad() -> Mesh {
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let positions = [
+            -1.0, -1.0, 0.0,
+             1.0, -1.0, 0.0,
+             1.0,  1.0, 0.0,
+            -1.0,  1.0, 0.0,
+        ];
+        let normals: Vec<f32> = [0.0, 0.0, 1.0].iter().cycle().take(12).cloned().collect();
+        let uvs = [0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        Self::from_pnt(&positions, &normals, &uvs, Indices::U16(&[0, 1, 2, 0, 2, 3]))
+    }
+
+    /// Single triangle covering the [-1, 1] square, for fullscreen passes.
+    /// Positions only, derive the uvs from them in the vertex shader
+    pub fn fullscreen_triangle() -> Mesh {
+        let positions = [-1.0, -1.0, 0.0, 3.0, -1.0, 0.0, -1.0, 3.0, 0.0];
+        Mesh::from_data(&positions, 3, None, vattr_flag(Vattr::Position))
+    }
+
+    /// Cube spanning [-1, 1] on every axis, with per face normals and uvs
+    pub fn cube() -> Mesh {
+        // (normal, tangent u, tangent v) of each face
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let (mut positions, mut normals, mut uvs, mut indices) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (i, (n, u, v)) in faces.iter().enumerate() {
+            for (cu, cv) in corners.iter() {
+                for k in 0..3 {
+                    positions.push(n[k] + cu * u[k] + cv * v[k]);
+                }
+                normals.extend_from_slice(n);
+                uvs.extend_from_slice(&[(cu + 1.0) / 2.0, (cv + 1.0) / 2.0]);
+            }
+            let base = (i * 4) as u16;
+            indices.extend([0, 1, 2, 0, 2, 3].iter().map(|x| base + x));
+        }
+        Self::from_pnt(&positions, &normals, &uvs, Indices::U16(&indices))
+    }
+
+    /// UV sphere of radius 1, with `segments` slices around the Y axis
+    /// and half as many stacks from pole to pole
+    pub fn sphere(segments: u32) -> Mesh {
+        let slices = segments.max(3);
+        let stacks = (segments / 2).max(2);
+        let (mut positions, mut normals, mut uvs, mut indices) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for i in 0..(stacks + 1) {
+            let v = i as f32 / stacks as f32;
+            let phi = v * std::f32::consts::PI;
+            for j in 0..(slices + 1) {
+                let u = j as f32 / slices as f32;
+                let theta = u * 2.0 * std::f32::consts::PI;
+                let n = [
+                    phi.sin() * theta.cos(),
+                    phi.cos(),
+                    -phi.sin() * theta.sin(),
+                ];
+                positions.extend_from_slice(&n);
+                normals.extend_from_slice(&n);
+                uvs.extend_from_slice(&[u, 1.0 - v]);
+            }
+        }
+        let row = slices + 1;
+        for i in 0..stacks {
+            for j in 0..slices {
+                let a = i * row + j;
+                let b = a + row;
+                indices.extend_from_slice(&[a, b, b + 1, a, b + 1, a + 1]);
+            }
+        }
+        Self::from_pnt(&positions, &normals, &uvs, Indices::U32(&indices))
+    }
+
+    fn attrib_setup(&self) {
+        for (attr_idx, offset) in planar_offsets(self.num_verts, self.attrib_mask) {
+            let (component_type, num_components) = VATTR_MAP[attr_idx as usize];
+            unsafe {
+                gl::EnableVertexAttribArray(attr_idx);
+                gl::VertexAttribPointer(
+                    attr_idx,
+                    num_components as GLint,
+                    component_type,
+                    gl::FALSE,
+                    0,
+                    offset as *const GLvoid,
+                );
+            }
+        }
+    }
+
+    pub fn draw(&self) {
+        unsafe {
+            self.with_bound_buffers(|| self.draw_call());
+        }
+    }
+
+    /// Draws `count` indices starting at index `first_index`, adding
+    /// `base_vertex` to each of them before fetching vertices. Lets meshes
+    /// packing several parts into shared buffers draw each part on its own.
+    /// Non zero base vertices need GL 3.2 or GLES 3.2
+    pub fn draw_range(
+        &self,
+        first_index: usize,
+        count: usize,
+        base_vertex: i32,
+    ) -> Result<(), String> {
+        if !self.is_indexed() {
+            return Err("Mesh has no indices to draw a range of".to_string());
+        }
+        let range = try!(ElementRange::new(
+            first_index,
+            count,
+            self.num_indcs,
+            self.index_type
+        ));
+        let base_vertex_supported = gl::DrawElementsBaseVertex::is_loaded();
+        if base_vertex != 0 && !base_vertex_supported {
+            return Err("Drawing with a base vertex is not supported".to_string());
+        }
+        let mode = self.primitive.gl_mode();
+        let offset = range.offset as *const GLvoid;
+        unsafe {
+            self.with_bound_buffers(|| {
+                if base_vertex_supported {
+                    gl::DrawElementsBaseVertex(
+                        mode,
+                        range.count,
+                        range.index_type,
+                        offset,
+                        base_vertex,
+                    );
+                } else {
+                    gl::DrawElements(mode, range.count, range.index_type, offset);
+                }
+            });
+        }
+        stats::record_draw(self.primitive.triangle_count(count));
+        Ok(())
+    }
+
+    // Binds the vertex array, or the buffers and attributes without one,
+    // around `draw`
+    unsafe fn with_bound_buffers<F: FnOnce()>(&self, draw: F) {
+        if self.vao != 0 {
+            gl::BindVertexArray(self.vao);
+            draw();
+            gl::BindVertexArray(0);
+            return;
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        self.attrib_setup();
+        if self.is_indexed() {
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+        }
+        draw();
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    // Issues the draw assuming buffers and attributes are already bound
+    unsafe fn draw_call(&self) {
+        if self.is_indexed() {
+            gl::DrawElements(
+                self.primitive.gl_mode(),
+                self.num_indcs as GLsizei,
+                self.index_type.gl_type(),
+                std::ptr::null(),
+            );
+            stats::record_draw(self.primitive.triangle_count(self.num_indcs));
+        } else {
+            gl::DrawArrays(self.primitive.gl_mode(), 0, self.num_verts as GLsizei);
+            stats::record_draw(self.primitive.triangle_count(self.num_verts));
         }
     }
 
     pub fn is_indexed(&self) -> bool {
         self.num_indcs != 0
     }
+
+    pub fn index_type(&self) -> IndexType {
+        self.index_type
+    }
+
+    pub fn primitive(&self) -> Primitive {
+        self.primitive
+    }
+
+    /// Bounds of the vertex positions at construction, empty for meshes
+    /// without a position attribute
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
 }
 
 impl Drop for Mesh {
     fn drop(&mut self) {
         unsafe {
+            if self.vao != 0 {
+                gl::DeleteVertexArrays(1, &self.vao);
+            }
             if self.is_indexed() {
                 gl::DeleteBuffers(1, &mut self.ebo);
             }
@@ -157,3 +614,105 @@ impl Drop for Mesh {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headless::Headless;
+
+    // Maps the vertex buffer of `mesh` and copies its contents out
+    fn read_vertices(mesh: &Mesh) -> Vec<f32> {
+        let mut data = vec![0.0f32; mesh.vbo_len];
+        let size = std::mem::size_of_val(&data[..]);
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
+            let mapped =
+                gl::MapBufferRange(gl::ARRAY_BUFFER, 0, size as GLsizeiptr, gl::MAP_READ_BIT);
+            assert!(!mapped.is_null());
+            std::ptr::copy_nonoverlapping(mapped as *const f32, data.as_mut_ptr(), data.len());
+            gl::UnmapBuffer(gl::ARRAY_BUFFER);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        data
+    }
+
+    #[test]
+    fn planar_offsets_follow_attribute_order() {
+        let mask = vattr_flag(Vattr::Position) | vattr_flag(Vattr::Normal) | vattr_flag(Vattr::UV0);
+        assert_eq!(planar_offsets(4, mask), [(0, 0), (1, 48), (2, 96)]);
+        assert_eq!(planar_len(4, mask), 32);
+        // Skinning streams follow at the vjnt and vwgt locations of skinned.vert
+        let mask = mask | vattr_flag(Vattr::Joints) | vattr_flag(Vattr::Weights);
+        assert_eq!(
+            planar_offsets(4, mask),
+            [(0, 0), (1, 48), (2, 96), (6, 128), (7, 192)]
+        );
+        assert_eq!(planar_len(4, mask), 64);
+    }
+
+    #[test]
+    fn planar_offsets_skip_missing_attributes() {
+        let mask = vattr_flag(Vattr::Position) | vattr_flag(Vattr::UV0) | vattr_flag(Vattr::Color);
+        // 4 * 3 floats of positions, then 4 * 2 floats of uvs
+        assert_eq!(planar_offsets(4, mask), [(0, 0), (2, 48), (5, 80)]);
+        assert_eq!(planar_len(4, mask), 32);
+        // Weights without joints keep their own location
+        let mask = vattr_flag(Vattr::Position) | vattr_flag(Vattr::Weights);
+        assert_eq!(planar_offsets(4, mask), [(0, 0), (7, 48)]);
+        assert_eq!(planar_len(4, mask), 28);
+    }
+
+    #[test]
+    fn u16_indices_select_unsigned_short() {
+        let indices = Indices::U16(&[0, 1, 2]);
+        assert_eq!(indices.index_type(), IndexType::U16);
+        assert_eq!(indices.index_type().gl_type(), gl::UNSIGNED_SHORT);
+        assert_eq!(indices.index_type().size(), 2);
+        assert_eq!(indices.as_bytes().1, 6);
+        let indices = Indices::U32(&[0, 1, 2]);
+        assert_eq!(indices.index_type().gl_type(), gl::UNSIGNED_INT);
+        assert_eq!(indices.as_bytes().1, 12);
+    }
+
+    #[test]
+    fn element_range_of_u16_indices() {
+        let range = ElementRange::new(6, 12, 24, IndexType::U16).unwrap();
+        assert_eq!(
+            range,
+            ElementRange {
+                count: 12,
+                index_type: gl::UNSIGNED_SHORT,
+                offset: 12,
+            }
+        );
+    }
+
+    #[test]
+    fn element_range_of_u32_indices() {
+        let range = ElementRange::new(6, 18, 24, IndexType::U32).unwrap();
+        assert_eq!(
+            range,
+            ElementRange {
+                count: 18,
+                index_type: gl::UNSIGNED_INT,
+                offset: 24,
+            }
+        );
+        assert!(ElementRange::new(6, 19, 24, IndexType::U32).is_err());
+    }
+
+    #[test]
+    #[ignore] // Needs a GL ES 3.0 headless driver (OsMesa on Linux)
+    fn vertex_update_reaches_the_buffer() {
+        let _headless = Headless::new(1, 1).unwrap();
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let mesh = Mesh::from_dynamic_data(&positions, 3, None, vattr_flag(Vattr::Position));
+        mesh.update_vertices(3, &[2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(
+            read_vertices(&mesh),
+            [0.0, 0.0, 0.0, 2.0, 3.0, 4.0, 0.0, 1.0, 0.0]
+        );
+        assert!(mesh.update_vertices(7, &[5.0, 6.0, 7.0]).is_err());
+        assert_eq!(read_vertices(&mesh)[6..], [0.0, 1.0, 0.0]);
+    }
+}
@@ -1,10 +1,32 @@
+pub mod capabilities;
+pub mod clear;
+pub mod color;
+pub mod framebuffer;
+pub mod gpu_timer;
+pub mod grid;
+pub mod layout;
+pub mod light;
+pub mod material;
 pub mod mesh;
+pub mod postprocess;
+pub mod readback;
 pub mod sdf;
 pub mod shader;
+pub mod shadow;
+pub mod sprite;
+pub mod state;
+pub mod stats;
 pub mod text;
 pub mod texture;
+pub mod texture_cache;
+pub mod ubo;
 
+pub use self::clear::*;
+pub use self::color::*;
+pub use self::grid::*;
+pub use self::material::*;
 pub use self::mesh::*;
 pub use self::shader::*;
+pub use self::state::*;
 pub use self::text::*;
 pub use self::texture::*;
@@ -0,0 +1,159 @@
+use super::framebuffer::Framebuffer;
+use super::material::bind_textures;
+use super::mesh::Mesh;
+use super::readback::viewport;
+use super::shader::Shader;
+use super::state::RenderState;
+use super::texture::{Texture, TextureFormat};
+use gl;
+use gl::types::*;
+
+const FULLSCREEN_VERTEX_SHADER: &str = include_str!("../shaders/fullscreen.vert");
+const THRESHOLD_FRAGMENT_SHADER: &str = include_str!("../shaders/bloom_threshold.frag");
+const BLUR_FRAGMENT_SHADER: &str = include_str!("../shaders/blur.frag");
+const COMPOSITE_FRAGMENT_SHADER: &str = include_str!("../shaders/bloom_composite.frag");
+
+//
+// Bloom post process. The bright parts of the input get extracted, blurred
+// at half resolution by ping-ponging between two framebuffers, and added
+// back on top of the input into the output framebuffer.
+//
+pub struct PostProcess {
+    mesh: Mesh,
+    threshold_shader: Shader,
+    blur_shader: Shader,
+    composite_shader: Shader,
+    format: TextureFormat,
+    ping_pong: [Framebuffer; 2],
+    output: Framebuffer,
+    /// Brightness above which pixels start to bloom
+    pub threshold: f32,
+    /// Strength of the bloom added back on top of the input
+    pub intensity: f32,
+    /// Horizontal + vertical blur pass pairs, more gives a wider bloom
+    pub blur_passes: u32,
+}
+
+#[allow(dead_code)]
+impl PostProcess {
+    /// Creates the chain for inputs of the given dimensions. Use a floating
+    /// point `format` to keep HDR values above 1 through the chain
+    pub fn new(width: u32, height: u32, format: TextureFormat) -> Result<PostProcess, String> {
+        let attribs: &[&str] = &["vpos"];
+        let threshold_shader = try!(Shader::new(
+            FULLSCREEN_VERTEX_SHADER,
+            None,
+            THRESHOLD_FRAGMENT_SHADER,
+            Some(attribs),
+        ));
+        let blur_shader = try!(Shader::new(
+            FULLSCREEN_VERTEX_SHADER,
+            None,
+            BLUR_FRAGMENT_SHADER,
+            Some(attribs),
+        ));
+        let composite_shader = try!(Shader::new(
+            FULLSCREEN_VERTEX_SHADER,
+            None,
+            COMPOSITE_FRAGMENT_SHADER,
+            Some(attribs),
+        ));
+        let (ping_pong, output) = try!(Self::create_targets(width, height, format));
+        Ok(PostProcess {
+            mesh: Mesh::fullscreen_triangle(),
+            threshold_shader,
+            blur_shader,
+            composite_shader,
+            format,
+            ping_pong,
+            output,
+            threshold: 1.0,
+            intensity: 0.5,
+            blur_passes: 3,
+        })
+    }
+
+    fn create_targets(
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Result<([Framebuffer; 2], Framebuffer), String> {
+        let (bw, bh) = ((width / 2).max(1), (height / 2).max(1));
+        let ping = try!(Framebuffer::new(bw, bh, &[format], false));
+        let pong = try!(Framebuffer::new(bw, bh, &[format], false));
+        let output = try!(Framebuffer::new(width, height, &[format], false));
+        Ok(([ping, pong], output))
+    }
+
+    /// Recreates the framebuffers for inputs of new dimensions
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let (ping_pong, output) = try!(Self::create_targets(width, height, self.format));
+        self.ping_pong = ping_pong;
+        self.output = output;
+        Ok(())
+    }
+
+    /// Runs the chain on `input`, which should match the dimensions the
+    /// chain was created with. The viewport and render state are restored
+    /// afterwards and the default framebuffer is left bound
+    pub fn apply(&self, input: &Texture) -> &Texture {
+        let vp = viewport();
+        let prev_state = RenderState::new().apply();
+
+        // Threshold into the first half resolution target
+        self.ping_pong[0].bind();
+        self.threshold_shader.activate();
+        self.threshold_shader.set_uniform("threshold", self.threshold);
+        bind_textures(&self.threshold_shader, &[("tex", input, 0)]);
+        self.mesh.draw();
+
+        // Separable blur, ending up back in the first target
+        let (bw, bh) = self.ping_pong[0].dimensions();
+        let texel = [1.0 / bw as f32, 1.0 / bh as f32];
+        self.blur_shader.activate();
+        for _ in 0..self.blur_passes {
+            self.blur_pass(0, [texel[0], 0.0]);
+            self.blur_pass(1, [0.0, texel[1]]);
+        }
+
+        self.output.bind();
+        self.composite_shader.activate();
+        self.composite_shader.set_uniform("intensity", self.intensity);
+        bind_textures(
+            &self.composite_shader,
+            &[("tex", input, 0), ("bloom", self.bloom_texture(), 1)],
+        );
+        self.mesh.draw();
+        self.output.unbind();
+
+        prev_state.apply();
+        unsafe {
+            gl::Viewport(vp[0], vp[1], vp[2] as GLsizei, vp[3] as GLsizei);
+        }
+        self.output_texture()
+    }
+
+    // Blurs ping_pong[src] along `direction` into the other target
+    fn blur_pass(&self, src: usize, direction: [f32; 2]) {
+        let dst = 1 - src;
+        self.ping_pong[dst].bind();
+        self.blur_shader.set_uniform("direction", &direction);
+        bind_textures(&self.blur_shader, &[("tex", self.texture(src), 0)]);
+        self.mesh.draw();
+    }
+
+    fn texture(&self, index: usize) -> &Texture {
+        // Always created with a single color attachment
+        self.ping_pong[index].color_attachment(0).unwrap()
+    }
+
+    /// The blurred bright parts of the last input, at half resolution
+    pub fn bloom_texture(&self) -> &Texture {
+        self.texture(0)
+    }
+
+    /// The result of the last `apply`
+    pub fn output_texture(&self) -> &Texture {
+        self.output.color_attachment(0).unwrap()
+    }
+}
@@ -0,0 +1,61 @@
+use super::capabilities::Capabilities;
+use assets::Image;
+use gl;
+use gl::types::*;
+
+/// Current viewport as (x, y, width, height)
+pub fn viewport() -> [GLint; 4] {
+    let mut vp: [GLint; 4] = [0; 4];
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, vp.as_mut_ptr());
+    }
+    vp
+}
+
+/// Reads the depth buffer value at pixel (x, y) of the viewport of the
+/// currently bound framebuffer. Coordinates have their origin at the top-left
+/// corner like cursor positions do, while GL's window space origin is
+/// bottom-left. Fails on GL ES, which can't read depth without an extension.
+#[allow(dead_code)]
+pub fn read_depth(x: u32, y: u32) -> Result<f32, String> {
+    if !Capabilities::current().supports_depth_readback() {
+        return Err("Reading depth values is not supported by the context".to_string());
+    }
+    let vp = viewport();
+    let gl_x = vp[0] + x as GLint;
+    let gl_y = vp[1] + vp[3] - 1 - y as GLint;
+    let mut depth: GLfloat = 1.0;
+    unsafe {
+        gl::ReadPixels(
+            gl_x,
+            gl_y,
+            1,
+            1,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            &mut depth as *mut GLfloat as *mut GLvoid,
+        );
+    }
+    Ok(depth)
+}
+
+/// Reads an RGBA area of the currently bound read framebuffer. The rows keep
+/// GL's bottom-left origin, like the images loaded as textures do.
+#[allow(dead_code)]
+pub fn read_color(x: u32, y: u32, width: u32, height: u32) -> Image {
+    let mut data = vec![0u8; (width * height * 4) as usize];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            x as GLint,
+            y as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            data.as_mut_ptr() as *mut GLvoid,
+        );
+    }
+    // The buffer was sized for exactly these dimensions
+    Image::from_raw(width, height, data).unwrap()
+}
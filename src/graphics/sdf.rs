@@ -1,3 +1,5 @@
+#[cfg(feature = "parallel")]
+use rayon;
 use std;
 //
 // Sweep-and-update Euclidean distance transform of an
@@ -763,62 +765,70 @@ fn edtaa3(
     /* The transformation is completed. */
 }
 
-/* Create a distance map from the given grayscale image.
- * Returns a newly allocated distance field. This image must
- * be freed after usage. */
-pub fn make_distance_mapd(data: &mut [f64], width: usize, height: usize) {
+// Unsigned distance of every background pixel to the object, clamped at 0
+fn distance_transform(img: &[f64], width: usize, height: usize) -> Vec<f64> {
     let mut xdist = vec![0i16; width * height];
     let mut ydist = vec![0i16; width * height];
     let mut gx = vec![0.0; width * height];
     let mut gy = vec![0.0; width * height];
-    let mut outside = vec![0.0; width * height];
-    let mut inside = vec![0.0; width * height];
-    let mut vmin = std::f64::MAX;
-
-    /* Compute outside = edtaa3(bitmap); % Transform background (0's) */
-    computegradient(data, width, height, &mut gx, &mut gy);
+    let mut dist = vec![0.0; width * height];
+    computegradient(img, width, height, &mut gx, &mut gy);
     edtaa3(
-        data,
+        img,
         &mut gx,
         &mut gy,
         width as isize,
         height as isize,
         &mut xdist,
         &mut ydist,
-        &mut outside,
+        &mut dist,
     );
-    for i in 0..(width * height) {
-        if outside[i] < 0.0 {
-            outside[i] = 0.0;
+    for d in dist.iter_mut() {
+        if *d < 0.0 {
+            *d = 0.0;
         }
     }
+    dist
+}
+
+// The outside and inside transforms are independent, so with the parallel
+// feature they run concurrently
+#[cfg(feature = "parallel")]
+fn distance_transforms(
+    img: &[f64],
+    inverted: &[f64],
+    width: usize,
+    height: usize,
+) -> (Vec<f64>, Vec<f64>) {
+    rayon::join(
+        || distance_transform(img, width, height),
+        || distance_transform(inverted, width, height),
+    )
+}
 
+#[cfg(not(feature = "parallel"))]
+fn distance_transforms(
+    img: &[f64],
+    inverted: &[f64],
+    width: usize,
+    height: usize,
+) -> (Vec<f64>, Vec<f64>) {
+    (
+        distance_transform(img, width, height),
+        distance_transform(inverted, width, height),
+    )
+}
+
+/* Create a distance map from the given grayscale image.
+ * Returns a newly allocated distance field. This image must
+ * be freed after usage. */
+pub fn make_distance_mapd(data: &mut [f64], width: usize, height: usize) {
+    let mut vmin = std::f64::MAX;
+
+    /* Compute outside = edtaa3(bitmap); % Transform background (0's) */
     /* Compute inside = edtaa3(1-bitmap); % Transform foreground (1's) */
-    gx.clear();
-    gx.resize(width * height, 0.0);
-    gy.clear();
-    gy.resize(width * height, 0.0);
-    gx = vec![0.0; width * height];
-    gy = vec![0.0; width * height];
-    for i in 0..(width * height) {
-        data[i] = 1.0 - data[i];
-    }
-    computegradient(data, width, height, &mut gx, &mut gy);
-    edtaa3(
-        data,
-        &mut gx,
-        &mut gy,
-        width as isize,
-        height as isize,
-        &mut xdist,
-        &mut ydist,
-        &mut inside,
-    );
-    for i in 0..(width * height) {
-        if inside[i] < 0.0 {
-            inside[i] = 0.0;
-        }
-    }
+    let inverted = data.iter().map(|v| 1.0 - v).collect::<Vec<_>>();
+    let (mut outside, inside) = distance_transforms(data, &inverted, width, height);
 
     /* distmap = outside - inside; % Bipolar distance field */
     for i in 0..(width * height) {
@@ -875,3 +885,353 @@ pub fn make_distance_mapb(img: &[u8], width: usize, height: usize) -> Vec<u8> {
 
     out
 }
+
+//
+// Multi-channel signed distance fields (after Chlumsky's msdfgen). Instead of
+// a single distance to the outline, each of the R, G, B channels holds the
+// distance to a subset of its edges. Edges meeting at a sharp corner are
+// assigned different channel sets ("colors"), so that reconstructing the
+// shape with the median of the three channels keeps the corner sharp, where
+// a single channel field would round it off.
+//
+
+const MSDF_RED: u8 = 1;
+const MSDF_GREEN: u8 = 2;
+const MSDF_BLUE: u8 = 4;
+const MSDF_WHITE: u8 = MSDF_RED | MSDF_GREEN | MSDF_BLUE;
+const MSDF_CYAN: u8 = MSDF_GREEN | MSDF_BLUE;
+const MSDF_MAGENTA: u8 = MSDF_RED | MSDF_BLUE;
+const MSDF_YELLOW: u8 = MSDF_RED | MSDF_GREEN;
+// sin of the angle (3 rad) below which a direction change counts as a corner
+const MSDF_CORNER_CROSS: f64 = 0.141_120_008;
+
+/// Outline segment in pixel coordinates of the output image
+#[derive(Clone, Copy, Debug)]
+pub enum MsdfSegment {
+    Line([f64; 2], [f64; 2]),
+    /// Quadratic bezier (start, control, end)
+    Quad([f64; 2], [f64; 2], [f64; 2]),
+}
+
+type V2 = [f64; 2];
+
+fn v_sub(a: V2, b: V2) -> V2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn v_dot(a: V2, b: V2) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn v_cross(a: V2, b: V2) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn v_len(a: V2) -> f64 {
+    v_dot(a, a).sqrt()
+}
+
+fn v_norm(a: V2) -> V2 {
+    let l = v_len(a);
+    if l == 0.0 {
+        [0.0, 0.0]
+    } else {
+        [a[0] / l, a[1] / l]
+    }
+}
+
+impl MsdfSegment {
+    fn point(&self, t: f64) -> V2 {
+        match *self {
+            MsdfSegment::Line(a, b) => [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t],
+            MsdfSegment::Quad(a, c, b) => {
+                let u = 1.0 - t;
+                [
+                    u * u * a[0] + 2.0 * u * t * c[0] + t * t * b[0],
+                    u * u * a[1] + 2.0 * u * t * c[1] + t * t * b[1],
+                ]
+            }
+        }
+    }
+
+    fn direction(&self, t: f64) -> V2 {
+        match *self {
+            MsdfSegment::Line(a, b) => v_sub(b, a),
+            MsdfSegment::Quad(a, c, b) => {
+                let d = [
+                    2.0 * ((1.0 - t) * (c[0] - a[0]) + t * (b[0] - c[0])),
+                    2.0 * ((1.0 - t) * (c[1] - a[1]) + t * (b[1] - c[1])),
+                ];
+                // Control point coinciding with an endpoint
+                if d == [0.0, 0.0] {
+                    v_sub(b, a)
+                } else {
+                    d
+                }
+            }
+        }
+    }
+
+    // Parameter of the closest point on the segment to p
+    fn closest_t(&self, p: V2) -> f64 {
+        match *self {
+            MsdfSegment::Line(a, b) => {
+                let ab = v_sub(b, a);
+                let l2 = v_dot(ab, ab);
+                if l2 == 0.0 {
+                    0.0
+                } else {
+                    (v_dot(v_sub(p, a), ab) / l2).max(0.0).min(1.0)
+                }
+            }
+            MsdfSegment::Quad(a, c, b) => {
+                // Newton iterations on d/dt |B(t) - p|^2 from a few seeds
+                let mut best_t = 0.0;
+                let mut best_d = std::f64::MAX;
+                let dd = [b[0] - 2.0 * c[0] + a[0], b[1] - 2.0 * c[1] + a[1]];
+                for seed in 0..5 {
+                    let mut t = seed as f64 / 4.0;
+                    for _ in 0..6 {
+                        let q = v_sub(self.point(t), p);
+                        let d1 = self.direction(t);
+                        let f1 = v_dot(q, d1);
+                        let f2 = v_dot(d1, d1) + 2.0 * v_dot(q, dd);
+                        if f2.abs() < 1e-12 {
+                            break;
+                        }
+                        t = (t - f1 / f2).max(0.0).min(1.0);
+                    }
+                    let q = v_sub(self.point(t), p);
+                    let d = v_dot(q, q);
+                    if d < best_d {
+                        best_d = d;
+                        best_t = t;
+                    }
+                }
+                best_t
+            }
+        }
+    }
+
+    // Signed distance to p with an orthogonality measure for breaking ties
+    // between edges sharing an endpoint, and the closest parameter
+    fn signed_distance(&self, p: V2) -> (f64, f64, f64) {
+        let t = self.closest_t(p);
+        let q = v_sub(p, self.point(t));
+        let dist = v_len(q);
+        let dir = v_norm(self.direction(t));
+        let cross = v_cross(dir, v_norm(q));
+        let sign = if cross < 0.0 { -1.0 } else { 1.0 };
+        (sign * dist, cross.abs(), t)
+    }
+
+    // Turns the distance into a pseudo-distance when the closest point is an
+    // endpoint and p lies past it, by measuring against the tangent line
+    fn pseudo_distance(&self, p: V2, dist: f64, t: f64) -> f64 {
+        let (endpoint, dir, beyond) = if t <= 0.0 {
+            let dir = v_norm(self.direction(0.0));
+            let a = self.point(0.0);
+            (a, dir, v_dot(v_sub(p, a), dir) < 0.0)
+        } else if t >= 1.0 {
+            let dir = v_norm(self.direction(1.0));
+            let b = self.point(1.0);
+            (b, dir, v_dot(v_sub(p, b), dir) > 0.0)
+        } else {
+            return dist;
+        };
+        if !beyond {
+            return dist;
+        }
+        let perp = v_cross(dir, v_sub(p, endpoint));
+        if perp.abs() <= dist.abs() {
+            perp
+        } else {
+            dist
+        }
+    }
+
+    // Crossings of the horizontal ray from p towards +x, signed by direction
+    fn winding(&self, p: V2) -> i32 {
+        match *self {
+            MsdfSegment::Line(a, b) => line_winding(a, b, p),
+            MsdfSegment::Quad(..) => {
+                // Flattened finely enough for pixel center classification
+                let mut w = 0;
+                let n = 8;
+                for i in 0..n {
+                    let a = self.point(i as f64 / n as f64);
+                    let b = self.point((i + 1) as f64 / n as f64);
+                    w += line_winding(a, b, p);
+                }
+                w
+            }
+        }
+    }
+
+    fn area_term(&self) -> f64 {
+        let (a, b) = (self.point(0.0), self.point(1.0));
+        match *self {
+            MsdfSegment::Line(..) => v_cross(a, b),
+            MsdfSegment::Quad(_, c, _) => v_cross(a, c) + v_cross(c, b),
+        }
+    }
+}
+
+fn line_winding(a: V2, b: V2, p: V2) -> i32 {
+    if (a[1] <= p[1]) != (b[1] <= p[1]) {
+        let x = a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+        if x > p[0] {
+            return if b[1] > a[1] { 1 } else { -1 };
+        }
+    }
+    0
+}
+
+fn is_corner(a: V2, b: V2) -> bool {
+    v_dot(a, b) <= 0.0 || v_cross(a, b).abs() > MSDF_CORNER_CROSS
+}
+
+// Assigns channel sets to the edges of a contour so that edges meeting at a
+// corner never share all of their channels
+fn color_contour(contour: &[MsdfSegment]) -> Vec<u8> {
+    let n = contour.len();
+    let corners = (0..n)
+        .filter(|i| {
+            let prev = contour[(i + n - 1) % n].direction(1.0);
+            let next = contour[*i].direction(0.0);
+            is_corner(v_norm(prev), v_norm(next))
+        }).collect::<Vec<_>>();
+    let mut colors = vec![MSDF_WHITE; n];
+    match corners.len() {
+        // Smooth contour, nothing to preserve
+        0 => (),
+        // Teardrop, split the contour in three starting at the corner
+        1 => {
+            if n >= 3 {
+                let palette = [MSDF_MAGENTA, MSDF_WHITE, MSDF_YELLOW];
+                for k in 0..n {
+                    colors[(corners[0] + k) % n] = palette[3 * k / n];
+                }
+            }
+        }
+        // Switch color at every corner, the last spline must also differ
+        // from the first one it wraps around to
+        ncorners => {
+            let palette = [MSDF_CYAN, MSDF_MAGENTA, MSDF_YELLOW];
+            for (spline, start) in corners.iter().enumerate() {
+                let mut color = palette[spline % 3];
+                if spline == ncorners - 1 && spline % 3 == 0 {
+                    color = palette[1];
+                }
+                let end = corners[(spline + 1) % ncorners];
+                let mut i = *start;
+                loop {
+                    colors[i] = color;
+                    i = (i + 1) % n;
+                    if i == end {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    colors
+}
+
+/// Builds a multi-channel distance field of the given contours, returning
+/// `width * height` RGB triplets. Distances are in pixels, mapped so that
+/// 128 lies on the outline, higher values are inside and `range` pixels
+/// away from the outline saturate.
+pub fn make_msdf(contours: &[Vec<MsdfSegment>], width: usize, height: usize, range: f64) -> Vec<u8> {
+    let edges = contours
+        .iter()
+        .filter(|c| !c.is_empty())
+        .flat_map(|c| c.iter().cloned().zip(color_contour(c)))
+        .collect::<Vec<_>>();
+
+    // Signed distances use the edge direction, make inside positive
+    // whatever the outline orientation
+    let area: f64 = edges.iter().map(|(e, _)| e.area_term()).sum();
+    let orientation = if area < 0.0 { -1.0 } else { 1.0 };
+
+    let to_byte = |d: f64| {
+        let v = 0.5 + d / (2.0 * range);
+        (v.max(0.0).min(1.0) * 255.0).round() as u8
+    };
+
+    let mut out = vec![0u8; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let p = [x as f64 + 0.5, y as f64 + 0.5];
+            // Per channel closest edge: (|distance|, orthogonality, edge, t)
+            let mut closest = [(std::f64::MAX, 0.0, 0, 0.0); 3];
+            let mut true_dist = std::f64::MAX;
+            for (i, (e, color)) in edges.iter().enumerate() {
+                let (d, ortho, t) = e.signed_distance(p);
+                true_dist = true_dist.min(d.abs());
+                for (ch, bit) in [MSDF_RED, MSDF_GREEN, MSDF_BLUE].iter().enumerate() {
+                    if color & bit == 0 {
+                        continue;
+                    }
+                    let c = &mut closest[ch];
+                    let better = d.abs() < c.0 - 1e-9
+                        || ((d.abs() - c.0).abs() <= 1e-9 && ortho > c.1);
+                    if better {
+                        *c = (d.abs(), ortho, i, t);
+                    }
+                }
+            }
+            let inside = edges.iter().map(|(e, _)| e.winding(p)).sum::<i32>() != 0;
+            let true_signed = if inside { true_dist } else { -true_dist };
+
+            let mut channels = [true_signed; 3];
+            for ch in 0..3 {
+                let (_, _, i, t) = closest[ch];
+                if closest[ch].0 == std::f64::MAX {
+                    continue;
+                }
+                let e = &edges[i].0;
+                let (d, _, _) = e.signed_distance(p);
+                channels[ch] = orientation * e.pseudo_distance(p, d, t);
+            }
+            // Where the channels disagree with the actual inside/outside
+            // classification fall back to the plain distance
+            let mut sorted = channels;
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            if (sorted[1] > 0.0) != inside {
+                channels = [true_signed; 3];
+            }
+
+            let o = (y * width + x) * 3;
+            for ch in 0..3 {
+                out[o + ch] = to_byte(channels[ch]);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_transforms_match_serial() {
+        // Ring with a soft edge, so both transforms have work to do
+        let (w, h) = (24, 20);
+        let img = (0..w * h)
+            .map(|i| {
+                let (x, y) = ((i % w) as f64 - 11.5, (i / w) as f64 - 9.5);
+                let r = (x * x + y * y).sqrt();
+                (1.0 - (r - 6.0).abs() / 2.0).max(0.0).min(1.0)
+            })
+            .collect::<Vec<_>>();
+        let inverted = img.iter().map(|v| 1.0 - v).collect::<Vec<_>>();
+        let (outside, inside) = distance_transforms(&img, &inverted, w, h);
+        let serial_outside = distance_transform(&img, w, h);
+        let serial_inside = distance_transform(&inverted, w, h);
+        let bits = |v: &[f64]| v.iter().map(|d| d.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&outside), bits(&serial_outside));
+        assert_eq!(bits(&inside), bits(&serial_inside));
+    }
+}
@@ -1,10 +1,65 @@
+use super::capabilities::Capabilities;
+use assets::{load, modified, Load};
 use gl;
 use gl::types::*;
 use std;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::convert::From;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Asset paths a shader was loaded from, kept around for reloading
+struct ShaderFiles {
+    vs_path: PathBuf,
+    gs_path: Option<PathBuf>,
+    fs_path: PathBuf,
+    attribs: Vec<String>,
+    // Newest modification time among the files at the last (re)load
+    mtime: Option<SystemTime>,
+}
+
+impl ShaderFiles {
+    fn paths(&self) -> Vec<&Path> {
+        let mut paths = vec![self.vs_path.as_path(), self.fs_path.as_path()];
+        if let Some(gs_path) = &self.gs_path {
+            paths.push(gs_path);
+        }
+        paths
+    }
+
+    fn latest_mtime(&self) -> Option<SystemTime> {
+        self.paths().iter().filter_map(|p| modified(p)).max()
+    }
+
+    fn compile(&self) -> Result<GLuint, String> {
+        let vs_src = try!(read_source(&self.vs_path));
+        let gs_src = match &self.gs_path {
+            Some(gs_path) => Some(try!(read_source(gs_path))),
+            None => None,
+        };
+        let fs_src = try!(read_source(&self.fs_path));
+        let attribs = self.attribs.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+        Shader::compile(
+            &vs_src,
+            gs_src.as_ref().map(|s| s.as_str()),
+            &fs_src,
+            Some(&attribs),
+        )
+    }
+}
+
+fn read_source(path: &Path) -> Result<String, String> {
+    let reader = try!(load(path));
+    String::from_buf(reader).map_err(|e| format!("{}: {}", path.display(), e))
+}
 
 pub struct Shader {
     id: GLuint,
+    files: Option<ShaderFiles>,
+    // Memoized uniform lookups, including misses
+    uniform_locations: RefCell<HashMap<String, Option<i32>>>,
 }
 
 impl Shader {
@@ -13,12 +68,91 @@ impl Shader {
         gs_src: Option<&str>,
         fs_src: &str,
         attribs: Option<&[&str]>,
-    ) -> Shader {
+    ) -> Result<Shader, String> {
+        let id = try!(Shader::compile(vs_src, gs_src, fs_src, attribs));
+        Ok(Shader {
+            id,
+            files: None,
+            uniform_locations: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Loads and compiles shader sources from asset paths, remembering
+    /// them so that the shader can later be `reload`ed
+    #[allow(dead_code)]
+    pub fn from_files<P: AsRef<Path>>(
+        vs_path: P,
+        gs_path: Option<P>,
+        fs_path: P,
+        attribs: Option<&[&str]>,
+    ) -> Result<Shader, String> {
+        let mut files = ShaderFiles {
+            vs_path: vs_path.as_ref().to_path_buf(),
+            gs_path: gs_path.map(|p| p.as_ref().to_path_buf()),
+            fs_path: fs_path.as_ref().to_path_buf(),
+            attribs: attribs
+                .unwrap_or(&[])
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            mtime: None,
+        };
+        files.mtime = files.latest_mtime();
+        let id = try!(files.compile());
+        Ok(Shader {
+            id,
+            files: Some(files),
+            uniform_locations: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// True when any of the source files changed on disk since the last
+    /// successful (re)load. Always false for shaders built from strings.
+    #[allow(dead_code)]
+    pub fn is_stale(&self) -> bool {
+        match &self.files {
+            Some(files) => files.latest_mtime() != files.mtime,
+            None => false,
+        }
+    }
+
+    /// Recompiles the shader from its source files. On failure the current
+    /// program is kept untouched and the info log is returned.
+    #[allow(dead_code)]
+    pub fn reload(&mut self) -> Result<(), String> {
+        let (id, mtime) = match &self.files {
+            Some(files) => {
+                // Sample the time first so edits made while compiling
+                // still mark the shader stale
+                let mtime = files.latest_mtime();
+                (try!(files.compile()), mtime)
+            }
+            None => return Err("Shader was not loaded from files".to_string()),
+        };
+        unsafe {
+            gl::DeleteProgram(self.id);
+        }
+        self.id = id;
+        // Locations belong to the old program
+        self.uniform_locations.borrow_mut().clear();
+        if let Some(files) = &mut self.files {
+            files.mtime = mtime;
+        }
+        Ok(())
+    }
+
+    fn compile(
+        vs_src: &str,
+        gs_src: Option<&str>,
+        fs_src: &str,
+        attribs: Option<&[&str]>,
+    ) -> Result<GLuint, String> {
         let attachments = vec![
             (gl::VERTEX_SHADER, Some(vs_src)),
             (gl::GEOMETRY_SHADER, gs_src),
             (gl::FRAGMENT_SHADER, Some(fs_src)),
         ];
+        let caps = Capabilities::current();
         let prog;
         unsafe {
             prog = gl::CreateProgram();
@@ -30,14 +164,16 @@ impl Shader {
             }
             for a in attachments {
                 if let Some(src) = a.1 {
+                    let src = &*adapt_glsl_version(src, &caps);
                     let id = gl::CreateShader(a.0);
                     let s = src.as_ptr() as *const GLchar;
                     let l = src.len() as GLint;
                     gl::ShaderSource(id, 1, &s, &l);
                     gl::CompileShader(id);
                     if let Some(err) = Shader::check_compilation_error(id) {
-                        println!("{}", err);
-                        panic!("Shader compilation error occured!");
+                        gl::DeleteShader(id);
+                        gl::DeleteProgram(prog);
+                        return Err(err);
                     }
                     gl::AttachShader(prog, id);
                     gl::DeleteShader(id);
@@ -45,11 +181,11 @@ impl Shader {
             }
             gl::LinkProgram(prog);
             if let Some(err) = Shader::check_linking_error(prog) {
-                println!("{}", err);
-                panic!("Shader linking error occured!");
+                gl::DeleteProgram(prog);
+                return Err(err);
             }
         }
-        Shader { id: prog }
+        Ok(prog)
     }
 
     unsafe fn check_compilation_error(id: GLuint) -> Option<String> {
@@ -86,6 +222,17 @@ impl Shader {
     }
 
     fn get_uniform_location(&self, name: &str) -> Option<i32> {
+        if let Some(location) = self.uniform_locations.borrow().get(name) {
+            return *location;
+        }
+        let location = self.query_uniform_location(name);
+        self.uniform_locations
+            .borrow_mut()
+            .insert(name.to_string(), location);
+        location
+    }
+
+    fn query_uniform_location(&self, name: &str) -> Option<i32> {
         let n = format!("{}\0", name);
         let location = unsafe { gl::GetUniformLocation(self.id, n.as_ptr() as *const GLchar) };
         if location == -1 {
@@ -96,7 +243,7 @@ impl Shader {
 
     pub fn set_uniform<'a, T: Into<Uniform<'a>>>(&self, name: &str, value: T) {
         if let Some(loc) = self.get_uniform_location(name) {
-            let count = 1; // TODO: Support uniform arrays
+            let count = 1; // Arrays pass their own length
             unsafe {
                 match value.into() {
                     Uniform::Bool(v) => gl::Uniform1iv(loc, count, &(v as GLint) as *const GLint),
@@ -121,16 +268,51 @@ impl Shader {
                     Uniform::Matrix4(v) => {
                         gl::UniformMatrix4fv(loc, count, gl::FALSE, v.as_ptr() as *const GLfloat)
                     }
+                    Uniform::Matrix4Array(v) => gl::UniformMatrix4fv(
+                        loc,
+                        v.len() as GLsizei,
+                        gl::FALSE,
+                        v.as_ptr() as *const GLfloat,
+                    ),
                 }
             }
         }
     }
 
+    /// Makes the uniform block `name` read from the uniform buffer attached
+    /// to `binding`, see `UniformBuffer`. Blocks missing from the program,
+    /// e.g. optimized out, are ignored like unknown uniforms are
+    #[allow(dead_code)]
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) {
+        let n = format!("{}\0", name);
+        unsafe {
+            let index = gl::GetUniformBlockIndex(self.id, n.as_ptr() as *const GLchar);
+            if index != gl::INVALID_INDEX {
+                gl::UniformBlockBinding(self.id, index, binding);
+            }
+        }
+    }
+
     pub fn activate(&self) {
         unsafe { gl::UseProgram(self.id) }
     }
 }
 
+// Sources are written against GLSL ES 3.00. Desktop contexts accept that
+// version only with ES 3 compatibility (core since GL 4.3), otherwise it
+// gets swapped for the closest desktop version. Precision qualifiers are
+// valid in both and `GL_ES` is only defined on ES
+fn adapt_glsl_version<'a>(src: &'a str, caps: &Capabilities) -> Cow<'a, str> {
+    const ES_VERSION: &str = "#version 300 es";
+    let es3_compatible = caps.es
+        || (caps.major, caps.minor) >= (4, 3)
+        || caps.has_extension("GL_ARB_ES3_compatibility");
+    if es3_compatible || !src.trim_start().starts_with(ES_VERSION) {
+        return Cow::Borrowed(src);
+    }
+    Cow::Owned(src.replacen(ES_VERSION, "#version 330 core", 1))
+}
+
 impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
@@ -139,6 +321,7 @@ impl Drop for Shader {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Uniform<'a> {
     Bool(bool),
     Float1(f32),
@@ -156,6 +339,8 @@ pub enum Uniform<'a> {
     Matrix2(&'a [[f32; 2]; 2]),
     Matrix3(&'a [[f32; 3]; 3]),
     Matrix4(&'a [[f32; 4]; 4]),
+    /// Consecutive mat4 array elements, starting at the named one
+    Matrix4Array(&'a [[[f32; 4]; 4]]),
 }
 
 impl<'a> From<bool> for Uniform<'a> {
@@ -253,3 +438,9 @@ impl<'a> From<&'a [[f32; 4]; 4]> for Uniform<'a> {
         Uniform::Matrix4(item)
     }
 }
+
+impl<'a> From<&'a [[[f32; 4]; 4]]> for Uniform<'a> {
+    fn from(item: &'a [[[f32; 4]; 4]]) -> Self {
+        Uniform::Matrix4Array(item)
+    }
+}
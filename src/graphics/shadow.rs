@@ -0,0 +1,139 @@
+use super::framebuffer::Framebuffer;
+use super::mesh::Mesh;
+use super::readback::viewport;
+use super::shader::Shader;
+use super::state::{CullFace, DepthFunc, RenderState};
+use super::texture::Texture;
+use gl;
+use gl::types::*;
+use math::*;
+
+const DEPTH_VERTEX_SHADER: &str = "\
+#version 300 es
+in vec3 vpos;
+
+uniform mat4 mvp;
+
+void main()
+{
+    gl_Position = mvp * vec4(vpos, 1.0);
+}
+";
+
+const DEPTH_FRAGMENT_SHADER: &str = "\
+#version 300 es
+
+void main()
+{
+}
+";
+
+//
+// Depth only render pass from a light's point of view. The resulting depth
+// texture, along with the light view projection it was rendered with, lets
+// the main pass tell which fragments the light can't reach.
+//
+pub struct ShadowMap {
+    framebuffer: Framebuffer,
+    shader: Shader,
+    resolution: u32,
+    light_view_proj: Mat4,
+    cull_front_faces: bool,
+}
+
+#[allow(dead_code)]
+impl ShadowMap {
+    /// Creates a square shadow map of `resolution` texels per side
+    pub fn new(resolution: u32) -> Result<ShadowMap, String> {
+        let framebuffer = try!(Framebuffer::with_depth_texture(resolution, resolution, &[]));
+        let shader = try!(Shader::new(
+            DEPTH_VERTEX_SHADER,
+            None,
+            DEPTH_FRAGMENT_SHADER,
+            Some(&["vpos"]),
+        ));
+        Ok(ShadowMap {
+            framebuffer,
+            shader,
+            resolution,
+            light_view_proj: Mat4::identity(),
+            cull_front_faces: true,
+        })
+    }
+
+    /// Whether to render the back faces only (the default). This pushes the
+    /// stored depth to the far side of closed meshes, which avoids most
+    /// self shadowing acne on lit surfaces
+    pub fn with_front_face_culling(mut self, enabled: bool) -> Self {
+        self.cull_front_faces = enabled;
+        self
+    }
+
+    /// Renders the depth of each (mesh, model matrix) pair as seen through
+    /// `light_view_proj`, replacing the previous contents of the map.
+    /// The viewport and render state are restored afterwards
+    pub fn render(&mut self, light_view_proj: &Mat4, meshes: &[(&Mesh, &Mat4)]) {
+        self.light_view_proj = *light_view_proj;
+        let vp = viewport();
+        let cull_face = if self.cull_front_faces {
+            CullFace::Front
+        } else {
+            CullFace::Back
+        };
+        let prev_state = RenderState::new()
+            .with_depth_test(DepthFunc::Less)
+            .with_cull_face(cull_face)
+            .apply();
+
+        self.framebuffer.bind();
+        unsafe {
+            gl::ClearDepthf(1.0);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+        self.shader.activate();
+        for &(mesh, model) in meshes {
+            let mvp = light_view_proj * model;
+            self.shader.set_uniform("mvp", mvp.as_ref());
+            mesh.draw();
+        }
+        self.framebuffer.unbind();
+
+        prev_state.apply();
+        unsafe {
+            gl::Viewport(vp[0], vp[1], vp[2] as GLsizei, vp[3] as GLsizei);
+        }
+    }
+
+    /// Depth of the last render, for sampling in the main pass
+    pub fn depth_texture(&self) -> &Texture {
+        // Always created with a depth texture
+        self.framebuffer.depth_texture().unwrap()
+    }
+
+    /// The light view projection of the last render
+    pub fn light_view_proj(&self) -> &Mat4 {
+        &self.light_view_proj
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+}
+
+/// View projection of a directional light shining along `direction`,
+/// covering a sphere of `radius` around `center`. Assumes the default
+/// [-1, 1] clip space depth range.
+#[allow(dead_code)]
+pub fn directional_light_view_proj(direction: &Vec3, center: &Vec3, radius: f32) -> Mat4 {
+    let dir = normalize(direction);
+    // Any up vector works unless it is parallel to the light
+    let up = if dir.y.abs() > 0.99 {
+        vec3(0.0, 0.0, 1.0)
+    } else {
+        vec3(0.0, 1.0, 0.0)
+    };
+    let eye = center - dir * radius * 2.0;
+    let view = look_at(&eye, center, &up);
+    let proj = ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+    proj * view
+}
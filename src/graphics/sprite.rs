@@ -0,0 +1,429 @@
+use super::color::output_color;
+use super::readback::viewport;
+use super::shader::*;
+use super::state::RenderState;
+use super::stats;
+use super::texture::Texture;
+use gl;
+use gl::types::*;
+use math::screen_ortho;
+use std;
+use std::mem::size_of;
+
+const MIN_FRAME_DURATION: f32 = 1.0e-3;
+// Position, uv and color floats of a batch vertex
+const SPRITE_VERTEX_FLOATS: usize = 8;
+
+const SPRITE_VERTEX_SHADER: &str = "\
+#version 300 es
+in vec2 vpos;
+in vec2 vtco;
+in vec4 vcol;
+
+out vec2 tco;
+out vec4 col;
+
+uniform mat4 proj;
+
+void main()
+{
+    tco = vtco;
+    col = vcol;
+    gl_Position = proj * vec4(vpos, 0.0, 1.0);
+}
+";
+
+const SPRITE_FRAGMENT_SHADER: &str = "\
+#version 300 es
+
+#ifdef GL_ES
+precision mediump float;
+#endif
+
+out vec4 fcolor;
+in vec2 tco;
+in vec4 col;
+
+uniform sampler2D tex;
+
+void main()
+{
+    fcolor = texture(tex, tco) * col;
+}
+";
+
+/// Normalized texture coordinate rectangle of a sub image in a texture atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRegion {
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+}
+
+#[allow(dead_code)]
+impl TextureRegion {
+    pub fn new(u0: f32, v0: f32, u1: f32, v1: f32) -> Self {
+        TextureRegion { u0, v0, u1, v1 }
+    }
+
+    /// Region covering the whole texture
+    pub fn full() -> Self {
+        Self::new(0.0, 0.0, 1.0, 1.0)
+    }
+
+    /// Splits a sprite sheet of equally sized cells into regions, row by row
+    /// starting from the top left cell. Images are loaded flipped, so the top
+    /// row lies at the high end of the v axis.
+    pub fn grid(cols: u32, rows: u32) -> Vec<TextureRegion> {
+        let (cw, ch) = (1.0 / cols as f32, 1.0 / rows as f32);
+        let mut regions = Vec::with_capacity((cols * rows) as usize);
+        for r in 0..rows {
+            for c in 0..cols {
+                let u0 = c as f32 * cw;
+                let v1 = 1.0 - r as f32 * ch;
+                regions.push(Self::new(u0, v1 - ch, u0 + cw, v1));
+            }
+        }
+        regions
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackMode {
+    /// Wraps back to the first frame after the last one
+    Loop,
+    /// Plays forwards then backwards, without repeating the end frames
+    PingPong,
+    /// Stops on the last frame
+    Once,
+}
+
+/// Frame sequence advanced by the update step, frame durations are in seconds
+pub struct SpriteAnimation {
+    frames: Vec<(TextureRegion, f32)>,
+    mode: PlaybackMode,
+    current: usize,
+    elapsed: f32,
+    reverse: bool,
+    finished: bool,
+}
+
+#[allow(dead_code)]
+impl SpriteAnimation {
+    pub fn new(frames: Vec<(TextureRegion, f32)>, mode: PlaybackMode) -> Self {
+        assert!(!frames.is_empty(), "Sprite animation without frames");
+        // Zero length frames would never let the update loop catch up
+        let frames = frames
+            .into_iter()
+            .map(|(region, duration)| (region, duration.max(MIN_FRAME_DURATION)))
+            .collect();
+        SpriteAnimation {
+            frames,
+            mode,
+            current: 0,
+            elapsed: 0.0,
+            reverse: false,
+            finished: false,
+        }
+    }
+
+    /// Animation where every frame lasts `frame_duration` seconds
+    pub fn uniform(regions: &[TextureRegion], frame_duration: f32, mode: PlaybackMode) -> Self {
+        Self::new(regions.iter().map(|r| (*r, frame_duration)).collect(), mode)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.finished {
+            return;
+        }
+        self.elapsed += dt;
+        loop {
+            let duration = self.frames[self.current].1;
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+            self.step();
+            if self.finished {
+                self.elapsed = 0.0;
+                break;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        let last = self.frames.len() - 1;
+        match self.mode {
+            PlaybackMode::Loop => {
+                self.current = if self.current == last { 0 } else { self.current + 1 };
+            }
+            PlaybackMode::Once => {
+                if self.current == last {
+                    self.finished = true;
+                } else {
+                    self.current += 1;
+                }
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.reverse && self.current == 0 || !self.reverse && self.current == last {
+                    self.reverse = !self.reverse;
+                }
+                if self.reverse {
+                    self.current -= 1;
+                } else {
+                    self.current += 1;
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = 0;
+        self.elapsed = 0.0;
+        self.reverse = false;
+        self.finished = false;
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current
+    }
+
+    pub fn current_region(&self) -> &TextureRegion {
+        &self.frames[self.current].0
+    }
+
+    /// True once a `Once` animation reached its last frame
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// A textured quad for `SpriteBatch`, positioned in pixels from the top
+/// left corner of the viewport
+pub struct Sprite<'a> {
+    texture: &'a Texture,
+    pos: [f32; 2],
+    size: [f32; 2],
+    region: TextureRegion,
+    color: [f32; 4],
+}
+
+#[allow(dead_code)]
+impl<'a> Sprite<'a> {
+    pub fn new(texture: &'a Texture, pos: [f32; 2], size: [f32; 2]) -> Self {
+        Sprite {
+            texture,
+            pos,
+            size,
+            region: TextureRegion::full(),
+            color: [1.0; 4],
+        }
+    }
+
+    pub fn with_region(mut self, region: TextureRegion) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Multiplies the texture color
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+//
+// Collects the sprites drawn between `begin` and `end` and renders them with
+// one draw call per texture. Sprites sharing a texture are drawn in the order
+// they were submitted, but sprites of different textures get grouped, so
+// overlapping sprites should share a texture (atlas) to keep their order.
+//
+pub struct SpriteBatch {
+    shader: Shader,
+    vbo: GLuint,
+    ebo: GLuint,
+    // Texture and vertex data of every sprite submitted since `begin`
+    quads: Vec<(GLuint, [f32; 4 * SPRITE_VERTEX_FLOATS])>,
+    draw_calls: u32,
+}
+
+#[allow(dead_code)]
+impl SpriteBatch {
+    pub fn new() -> Result<SpriteBatch, String> {
+        let shader = try!(Shader::new(
+            SPRITE_VERTEX_SHADER,
+            None,
+            SPRITE_FRAGMENT_SHADER,
+            Some(&["vpos", "vtco", "vcol"]),
+        ));
+        let mut vbo: GLuint = 0;
+        let mut ebo: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenBuffers(1, &mut ebo);
+        }
+        Ok(SpriteBatch {
+            shader,
+            vbo,
+            ebo,
+            quads: Vec::new(),
+            draw_calls: 0,
+        })
+    }
+
+    pub fn begin(&mut self) {
+        self.quads.clear();
+    }
+
+    pub fn draw(&mut self, sprite: &Sprite) {
+        let (x0, y0) = (sprite.pos[0], sprite.pos[1]);
+        let (x1, y1) = (x0 + sprite.size[0], y0 + sprite.size[1]);
+        let r = &sprite.region;
+        let c = output_color(&sprite.color);
+        // Images are loaded flipped, so the top edge samples v1
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let verts = [
+            x0, y0, r.u0, r.v1, c[0], c[1], c[2], c[3],
+            x0, y1, r.u0, r.v0, c[0], c[1], c[2], c[3],
+            x1, y1, r.u1, r.v0, c[0], c[1], c[2], c[3],
+            x1, y0, r.u1, r.v1, c[0], c[1], c[2], c[3],
+        ];
+        self.quads.push((sprite.texture.id(), verts));
+    }
+
+    /// Renders the sprites submitted since `begin`
+    pub fn end(&mut self) {
+        self.draw_calls = 0;
+        if self.quads.is_empty() {
+            return;
+        }
+        let runs = texture_runs(&mut self.quads);
+        let vertices: Vec<f32> = self
+            .quads
+            .iter()
+            .flat_map(|q| q.1.iter().cloned())
+            .collect();
+        let indices: Vec<u32> = (0..self.quads.len() as u32)
+            .flat_map(|i| [0, 1, 2, 0, 2, 3].iter().map(move |x| x + i * 4))
+            .collect();
+
+        let vp = viewport();
+        let proj = screen_ortho((vp[2] - vp[0]) as f32, (vp[3] - vp[1]) as f32);
+        let stride = (SPRITE_VERTEX_FLOATS * size_of::<f32>()) as GLint;
+        unsafe {
+            // Upload data
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(&vertices[..]) as GLsizeiptr,
+                vertices.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            stats::record_buffer_upload();
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(&indices[..]) as GLsizeiptr,
+                indices.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            stats::record_buffer_upload();
+
+            // Setup attribute bindings
+            for (attr, (offset, size)) in [(0, 2), (2, 2), (4, 4)].iter().enumerate() {
+                gl::EnableVertexAttribArray(attr as GLuint);
+                gl::VertexAttribPointer(
+                    attr as GLuint,
+                    *size,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * size_of::<f32>()) as *const GLvoid,
+                );
+            }
+
+            // Draw each run of quads sharing a texture at once
+            let prev_state = RenderState::overlay().apply();
+            self.shader.activate();
+            self.shader.set_uniform("proj", proj.as_ref());
+            self.shader.set_uniform("tex", 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+            for (texture, start, count) in runs {
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                stats::record_texture_bind();
+                gl::DrawElements(
+                    gl::TRIANGLES,
+                    (count * 6) as GLsizei,
+                    gl::UNSIGNED_INT,
+                    (start * 6 * size_of::<u32>()) as *const GLvoid,
+                );
+                stats::record_draw((count * 2) as u32);
+                self.draw_calls += 1;
+            }
+            prev_state.apply();
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        self.quads.clear();
+    }
+
+    /// Draw calls issued by the last `end`
+    pub fn draw_calls(&self) -> u32 {
+        self.draw_calls
+    }
+}
+
+// Groups the quads by texture, returning the (texture, first quad, quad count)
+// of each draw call
+fn texture_runs<T>(quads: &mut [(GLuint, T)]) -> Vec<(GLuint, usize, usize)> {
+    // Stable, so the submission order holds within each texture
+    quads.sort_by_key(|q| q.0);
+    let mut runs: Vec<(GLuint, usize, usize)> = Vec::new();
+    for (i, q) in quads.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if run.0 == q.0 => run.2 += 1,
+            _ => runs.push((q.0, i, 1)),
+        }
+    }
+    runs
+}
+
+impl Drop for SpriteBatch {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.ebo);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_sharing_a_texture_take_one_draw() {
+        let mut quads = vec![(7, 'a'), (7, 'b'), (7, 'c'), (7, 'd')];
+        assert_eq!(texture_runs(&mut quads), [(7, 0, 4)]);
+    }
+
+    #[test]
+    fn texture_switch_takes_another_draw() {
+        let mut quads = vec![(2, 'a'), (1, 'b'), (2, 'c')];
+        assert_eq!(texture_runs(&mut quads), [(1, 0, 1), (2, 1, 2)]);
+        // Submission order holds within each texture
+        assert_eq!(quads, [(1, 'b'), (2, 'a'), (2, 'c')]);
+    }
+
+    #[test]
+    fn no_quads_no_draws() {
+        let mut quads: Vec<(GLuint, ())> = Vec::new();
+        assert!(texture_runs(&mut quads).is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+use gl;
+use gl::types::*;
+use std::cell::Cell;
+
+//
+// Fixed function pipeline state. The state last applied is tracked so that
+// `apply` only issues the GL calls for what actually changed. Renderers that
+// need a specific state apply it and restore the previous one afterwards,
+// instead of leaving their changes behind for the next draw.
+//
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl DepthFunc {
+    fn gl_func(&self) -> GLenum {
+        match *self {
+            DepthFunc::Never => gl::NEVER,
+            DepthFunc::Less => gl::LESS,
+            DepthFunc::Equal => gl::EQUAL,
+            DepthFunc::LessEqual => gl::LEQUAL,
+            DepthFunc::Greater => gl::GREATER,
+            DepthFunc::NotEqual => gl::NOTEQUAL,
+            DepthFunc::GreaterEqual => gl::GEQUAL,
+            DepthFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Blending disabled
+    Opaque,
+    /// Straight alpha
+    Alpha,
+    /// Alpha already multiplied into the color
+    Premultiplied,
+    Additive,
+}
+
+impl BlendMode {
+    fn gl_factors(&self) -> Option<(GLenum, GLenum)> {
+        match *self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Premultiplied => Some((gl::ONE, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Additive => Some((gl::SRC_ALPHA, gl::ONE)),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullFace {
+    /// Culling disabled
+    None,
+    Back,
+    Front,
+}
+
+/// Depth, blend and culling state. The default matches the initial state of
+/// a fresh GL context
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_func: DepthFunc,
+    pub blend: BlendMode,
+    pub cull_face: CullFace,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState {
+            depth_test: false,
+            depth_write: true,
+            depth_func: DepthFunc::Less,
+            blend: BlendMode::Opaque,
+            cull_face: CullFace::None,
+        }
+    }
+}
+
+thread_local! {
+    // None when the tracked state can't be trusted and must be fully reapplied
+    static CURRENT: Cell<Option<RenderState>> = Cell::new(Some(RenderState::default()));
+}
+
+#[allow(dead_code)]
+impl RenderState {
+    pub fn new() -> Self {
+        RenderState::default()
+    }
+
+    /// The state of 2D overlays drawn on top of the scene, like text and sprites
+    pub fn overlay() -> Self {
+        RenderState::new().with_blend(BlendMode::Alpha)
+    }
+
+    /// The last applied state
+    pub fn current() -> Self {
+        CURRENT.with(|c| c.get()).unwrap_or_default()
+    }
+
+    /// Forgets the tracked state, so the next `apply` sets everything.
+    /// Needed after the GL state was changed behind our back (e.g. when the
+    /// context is recreated)
+    pub fn invalidate() {
+        CURRENT.with(|c| c.set(None));
+    }
+
+    pub fn with_depth_test(mut self, func: DepthFunc) -> Self {
+        self.depth_test = true;
+        self.depth_func = func;
+        self
+    }
+
+    pub fn without_depth_test(mut self) -> Self {
+        self.depth_test = false;
+        self
+    }
+
+    pub fn with_depth_write(mut self, enabled: bool) -> Self {
+        self.depth_write = enabled;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_cull_face(mut self, cull_face: CullFace) -> Self {
+        self.cull_face = cull_face;
+        self
+    }
+
+    /// Makes this the current state, issuing GL calls only for what differs
+    /// from the last applied state. Returns that previous state, so that it
+    /// can be applied back once done
+    pub fn apply(&self) -> RenderState {
+        let prev = CURRENT.with(|c| c.replace(Some(*self)));
+        for change in self.changes_from(prev.as_ref()) {
+            unsafe {
+                change.emit();
+            }
+        }
+        prev.unwrap_or_default()
+    }
+
+    // What has to be set to get from `prev` to this state, everything when
+    // the previous state is unknown
+    fn changes_from(&self, prev: Option<&RenderState>) -> Vec<StateChange> {
+        let all = prev.is_none();
+        let prev = prev.cloned().unwrap_or_default();
+        let mut changes = Vec::new();
+        if all || prev.depth_test != self.depth_test {
+            changes.push(StateChange::DepthTest(self.depth_test));
+        }
+        if all || prev.depth_write != self.depth_write {
+            changes.push(StateChange::DepthWrite(self.depth_write));
+        }
+        if all || prev.depth_func != self.depth_func {
+            changes.push(StateChange::DepthFunc(self.depth_func));
+        }
+        if all || prev.blend != self.blend {
+            changes.push(StateChange::Blend(self.blend));
+        }
+        if all || prev.cull_face != self.cull_face {
+            changes.push(StateChange::CullFace(self.cull_face));
+        }
+        changes
+    }
+}
+
+// A single piece of state to set, as emitted by RenderState::apply
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StateChange {
+    DepthTest(bool),
+    DepthWrite(bool),
+    DepthFunc(DepthFunc),
+    Blend(BlendMode),
+    CullFace(CullFace),
+}
+
+impl StateChange {
+    unsafe fn emit(&self) {
+        match *self {
+            StateChange::DepthTest(enabled) => set_capability(gl::DEPTH_TEST, enabled),
+            StateChange::DepthWrite(enabled) => {
+                gl::DepthMask(if enabled { gl::TRUE } else { gl::FALSE })
+            }
+            StateChange::DepthFunc(func) => gl::DepthFunc(func.gl_func()),
+            StateChange::Blend(blend) => match blend.gl_factors() {
+                Some((src, dst)) => {
+                    gl::Enable(gl::BLEND);
+                    gl::BlendFunc(src, dst);
+                }
+                None => gl::Disable(gl::BLEND),
+            },
+            StateChange::CullFace(cull_face) => match cull_face {
+                CullFace::None => gl::Disable(gl::CULL_FACE),
+                CullFace::Back => {
+                    gl::Enable(gl::CULL_FACE);
+                    gl::CullFace(gl::BACK);
+                }
+                CullFace::Front => {
+                    gl::Enable(gl::CULL_FACE);
+                    gl::CullFace(gl::FRONT);
+                }
+            },
+        }
+    }
+}
+
+unsafe fn set_capability(cap: GLenum, enabled: bool) {
+    if enabled {
+        gl::Enable(cap);
+    } else {
+        gl::Disable(cap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_state_changes_nothing() {
+        let state = RenderState::overlay().with_depth_test(DepthFunc::Greater);
+        assert!(state.changes_from(Some(&state)).is_empty());
+    }
+
+    #[test]
+    fn unknown_state_sets_everything() {
+        assert_eq!(RenderState::new().changes_from(None).len(), 5);
+    }
+
+    #[test]
+    fn only_differences_get_set() {
+        let prev = RenderState::new();
+        let next = RenderState::new()
+            .with_depth_test(DepthFunc::Less)
+            .with_blend(BlendMode::Additive);
+        assert_eq!(
+            next.changes_from(Some(&prev)),
+            [
+                StateChange::DepthTest(true),
+                StateChange::Blend(BlendMode::Additive),
+            ]
+        );
+    }
+
+    #[test]
+    fn restoring_reverses_changes() {
+        let prev = RenderState::new().with_depth_test(DepthFunc::Less);
+        let next = RenderState::overlay().with_cull_face(CullFace::Back);
+        assert_eq!(
+            next.changes_from(Some(&prev)),
+            [
+                StateChange::DepthTest(false),
+                StateChange::Blend(BlendMode::Alpha),
+                StateChange::CullFace(CullFace::Back),
+            ]
+        );
+        assert_eq!(
+            prev.changes_from(Some(&next)),
+            [
+                StateChange::DepthTest(true),
+                StateChange::Blend(BlendMode::Opaque),
+                StateChange::CullFace(CullFace::None),
+            ]
+        );
+    }
+}
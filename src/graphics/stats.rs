@@ -0,0 +1,64 @@
+use std::cell::Cell;
+
+//
+// Per-frame rendering counters. The graphics primitives record into the
+// current frame's counters and `end_frame` publishes them, so the totals of
+// the last complete frame can be queried at any time (e.g. by a perf HUD).
+// Counting is enabled by default in debug builds only.
+//
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub triangles: u32,
+    pub texture_binds: u32,
+    pub buffer_uploads: u32,
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = Cell::new(cfg!(debug_assertions));
+    static CURRENT: Cell<FrameStats> = Cell::new(FrameStats::default());
+    static LAST: Cell<FrameStats> = Cell::new(FrameStats::default());
+}
+
+fn record<F: FnOnce(&mut FrameStats)>(f: F) {
+    if ENABLED.with(|e| e.get()) {
+        CURRENT.with(|c| {
+            let mut stats = c.get();
+            f(&mut stats);
+            c.set(stats);
+        });
+    }
+}
+
+#[allow(dead_code)]
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+pub fn record_draw(triangles: u32) {
+    record(|s| {
+        s.draw_calls += 1;
+        s.triangles += triangles;
+    });
+}
+
+pub fn record_texture_bind() {
+    record(|s| s.texture_binds += 1);
+}
+
+pub fn record_buffer_upload() {
+    record(|s| s.buffer_uploads += 1);
+}
+
+/// Publishes the counters of the frame that just finished and resets them
+pub fn end_frame() -> FrameStats {
+    let stats = CURRENT.with(|c| c.replace(FrameStats::default()));
+    LAST.with(|l| l.set(stats));
+    stats
+}
+
+/// Counters of the last completed frame
+pub fn last_frame() -> FrameStats {
+    LAST.with(|l| l.get())
+}
@@ -1,28 +1,49 @@
+use super::capabilities::Capabilities;
+use super::color::output_color;
+use super::layout::{self, LayoutMetrics, Line, LineHeight, TextDirection};
+use super::readback::viewport;
 use super::sdf;
 use super::shader::*;
+use super::state::RenderState;
+use super::stats;
 use gl;
 use gl::types::*;
 use rusttype::gpu_cache::Cache;
-use rusttype::{point, Font, PositionedGlyph, Rect, Scale};
+use rusttype::{point, Font, Point, PositionedGlyph, Rect, Scale, Segment};
 use std;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::BufRead;
 use std::mem::size_of;
+use std::ops::Range;
 
 const FONT_LOAD_SIZE: f32 = 48.0;
+// Display size of text that doesn't ask for one
+const DEFAULT_PIXEL_SIZE: f32 = 16.0;
+// Initial glyph cache dimensions, the cache doubles whenever it fills up
+const CACHE_SIZE: u32 = 512;
+// Empty border reserved around every cached glyph; the rusttype cache
+// supports either none or a single pixel
+const GLYPH_PADDING: u32 = 1;
+// Distance (in atlas pixels) at which msdf values saturate
+const MSDF_RANGE: f64 = 4.0;
+// Memory bound of the distance fields kept around for evicted glyphs
+const SDF_MEMO_BYTES: usize = 4 * 1024 * 1024;
 
 const VERTEX_SHADER: &str = "\
 #version 300 es
 in vec2 vpos;
 in vec2 vtco;
+in vec4 vcol;
 
 out vec2 tco;
+out vec4 col;
 uniform mat4 mvp;
 
 void main()
 {
     tco = vtco;
+    col = vcol;
     gl_Position = mvp * vec4(vpos, 0.0, 1.0);
 }
 ";
@@ -43,24 +64,44 @@ precision mediump float;
 
 out vec4 fcolor;
 in vec2 tco;
+in vec4 col;
 
-uniform vec4 col;
 uniform float scl;
 uniform sampler2D tex;
 uniform bool ssp;
 uniform bool dfd;
+uniform float sft;
+uniform float bld;
+uniform bool msd;
+uniform sampler2D mtex;
 
 const float SQRT2_2 = 0.70710678118654757;
 
 float contour(float d, float w)
 {
-    return smoothstep(0.5 - w, 0.5 + w, d);
+    // Lowering the threshold dilates the glyph (synthetic bold)
+    float t = 0.5 - bld;
+    return smoothstep(t - w, t + w, d);
+}
+
+float median(float r, float g, float b)
+{
+    return max(min(r, g), min(max(r, g), b));
+}
+
+float distance_at(vec2 uv)
+{
+    if (msd) {
+        vec3 s = texture(mtex, uv).rgb;
+        return median(s.r, s.g, s.b);
+    }
+    return texture(tex, uv).a;
 }
 
 void main()
 {
     vec2 uv = tco;
-    float dist = texture(tex, uv).a;
+    float dist = distance_at(uv);
 
     // Keep outlines a constant width irrespective of scaling
     float fw = 0.0;
@@ -72,6 +113,8 @@ void main()
     } else {
         fw = (1.0 / scl) * SQRT2_2 / gl_FragCoord.w;
     }
+    // User controlled edge softness
+    fw *= sft;
     float alpha = contour(dist, fw);
 
     if (ssp) {
@@ -79,10 +122,10 @@ void main()
         float dscale = 0.354; // half of 1/sqrt2
         vec2 duv = dscale * (dFdx(uv) + dFdy(uv));
         vec4 box = vec4(uv - duv, uv + duv);
-        float asum = contour(texture(tex, box.xy).a, fw)
-                   + contour(texture(tex, box.zw).a, fw)
-                   + contour(texture(tex, box.xw).a, fw)
-                   + contour(texture(tex, box.zy).a, fw);
+        float asum = contour(distance_at(box.xy), fw)
+                   + contour(distance_at(box.zw), fw)
+                   + contour(distance_at(box.xw), fw)
+                   + contour(distance_at(box.zy), fw);
         // Weighted average, with 4 extra points having 0.5 weight each,
         // so 1 + 0.5 * 4 = 3 is the divisor
         alpha = (alpha + 0.5 * asum) / 3.0;
@@ -92,16 +135,111 @@ void main()
 }
 ";
 
-struct Vertex([f32; 2], [f32; 2]);
+struct Vertex([f32; 2], [f32; 2], [f32; 4]);
+
+// Font id, glyph id, scale bits and padded region size of a rasterized glyph
+type SdfKey = (usize, u32, u32, u32, usize, usize);
+
+// Distance fields of recently rasterized glyphs, so that a glyph evicted
+// from the cache and rasterized again skips the distance transform.
+// The oldest entries get dropped once the memory bound is exceeded.
+struct SdfMemo {
+    entries: HashMap<SdfKey, Vec<u8>>,
+    order: VecDeque<SdfKey>,
+    bytes: usize,
+}
+
+impl SdfMemo {
+    fn new() -> Self {
+        SdfMemo {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    fn get(&self, key: &SdfKey) -> Option<&Vec<u8>> {
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, key: SdfKey, dist_map: Vec<u8>) {
+        self.bytes += dist_map.len();
+        if let Some(old) = self.entries.insert(key, dist_map) {
+            self.bytes -= old.len();
+        } else {
+            self.order.push_back(key);
+        }
+        while self.bytes > SDF_MEMO_BYTES {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(old) = self.entries.remove(&oldest) {
+                        self.bytes -= old.len();
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+// Shader uniforms of a text draw
+#[derive(Clone, Copy, PartialEq)]
+struct DrawUniforms {
+    transform: [[f32; 4]; 4],
+    super_sample: bool,
+    dfd: bool,
+    scale: f32,
+    softness: f32,
+    bold: f32,
+    msdf: bool,
+}
+
+impl DrawUniforms {
+    fn apply(&self, shader: &Shader) {
+        shader.set_uniform("mvp", &self.transform);
+        shader.set_uniform("ssp", self.super_sample);
+        shader.set_uniform("dfd", self.dfd);
+        shader.set_uniform("scl", self.scale);
+        shader.set_uniform("sft", self.softness);
+        shader.set_uniform("bld", self.bold);
+        shader.set_uniform("msd", self.msdf);
+    }
+}
+
+/// Color of the characters whose byte offsets fall in the range
+pub type ColorRun = (Range<usize>, [f32; 4]);
 
 pub struct TextRenderer {
     font_id_gen: usize,
-    font_map: HashMap<String, (usize, Font<'static>)>,
+    font_map: HashMap<(String, FontStyle), (usize, Font<'static>)>,
     cache: RefCell<Cache<'static>>,
     cache_img_id: GLuint,
+    // RGB distance atlas mirroring the glyph cache layout, filled lazily
+    // for the glyphs drawn by msdf texts
+    msdf_img_id: GLuint,
+    // Padded cache regions (top left corner) currently holding valid msdf data
+    msdf_regions: RefCell<HashSet<(u32, u32)>>,
+    sdf_memo: RefCell<SdfMemo>,
     shader: Shader,
     draw_vbo: GLuint,
     draw_ebo: GLuint,
+    ui_scaling: UiScaling,
+    dpi_factor: f32,
+    // Size of the target texts get drawn into, None to query the viewport
+    target_size: Cell<Option<(u32, u32)>>,
+    // Whether the context allows derivatives based antialiasing
+    derivatives: bool,
+}
+
+/// How UI sizes map to framebuffer pixels
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum UiScaling {
+    /// Sizes are in logical points and get multiplied by the HiDPI factor,
+    /// so they keep the same physical size on any display
+    Logical,
+    /// Sizes are in framebuffer pixels, for pixel-exact art
+    Physical,
 }
 
 #[allow(dead_code)]
@@ -118,6 +256,61 @@ pub enum VAlignment {
     Bottom,
 }
 
+/// Laid out lines of a text. Positions are in on screen pixels relative to
+/// the top left corner of the first line, with y growing downwards, before
+/// alignment and the transform get applied. Character offsets are byte
+/// offsets into the text contents
+pub struct TextLayout {
+    lines: Vec<Line>,
+    metrics: LayoutMetrics,
+    // Load size to display size
+    scale: f32,
+}
+
+#[allow(dead_code)]
+impl TextLayout {
+    /// Offset of the caret position nearest to a point, e.g. a click.
+    /// Points past the end of a line map to the end of that line
+    pub fn hit_test(&self, x: f32, y: f32) -> usize {
+        layout::hit_test(&self.lines, &self.metrics, x / self.scale, y / self.scale)
+    }
+
+    /// Position of the caret before the character at `index`, on the
+    /// baseline of its line. The caret after a character is the one at
+    /// the offset right after it
+    pub fn caret_position(&self, index: usize) -> [f32; 2] {
+        let (x, baseline_y) = layout::caret_position(&self.lines, index);
+        [x * self.scale, baseline_y * self.scale]
+    }
+
+    /// Height of a line, the distance between consecutive baselines
+    pub fn line_height(&self) -> f32 {
+        self.metrics.advance_height() * self.scale
+    }
+
+    pub fn num_lines(&self) -> u32 {
+        self.lines.len() as u32
+    }
+}
+
+/// Measured extents of a laid out text
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct TextMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub num_lines: u32,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
 pub struct Text<'a> {
     contents: &'a str,
     font: &'a str,
@@ -128,6 +321,17 @@ pub struct Text<'a> {
     use_vmetrics: bool,
     dfd_antialiasing: bool,
     super_sample: bool,
+    edge_softness: f32,
+    fake_bold: f32,
+    fake_italic: f32,
+    msdf: bool,
+    wrap_width: Option<f32>,
+    pixel_size: f32,
+    color_runs: &'a [ColorRun],
+    style: FontStyle,
+    line_height: LineHeight,
+    letter_spacing: f32,
+    direction: TextDirection,
 }
 
 #[allow(dead_code)]
@@ -143,9 +347,27 @@ impl <'a> Text<'a> {
             use_vmetrics: false,
             dfd_antialiasing: false,
             super_sample: true,
+            edge_softness: 1.0,
+            fake_bold: 0.0,
+            fake_italic: 0.0,
+            msdf: false,
+            wrap_width: None,
+            pixel_size: DEFAULT_PIXEL_SIZE,
+            color_runs: &[],
+            style: FontStyle::Regular,
+            line_height: LineHeight::Normal,
+            letter_spacing: 0.0,
+            direction: TextDirection::LeftToRight,
         }
     }
 
+    /// Picks a style variant of the font family, see
+    /// `TextRenderer::add_font_variant`
+    pub fn with_style(mut self, style: FontStyle) -> Self {
+        self.style = style;
+        self
+    }
+
     pub fn with_color(mut self, color: &[f32; 4]) -> Self {
         self.color = *color;
         self
@@ -176,22 +398,114 @@ impl <'a> Text<'a> {
         self
     }
 
+    /// Scales the antialiasing width of glyph contours, values below 1.0
+    /// give sharper edges and values above 1.0 softer ones
+    pub fn with_edge_softness(mut self, edge_softness: f32) -> Self {
+        self.edge_softness = edge_softness;
+        self
+    }
+
+    /// Synthesizes a bold face by dilating the glyph distance field.
+    /// The amount is in distance field units, sensible values lie in 0.0..0.2
+    pub fn with_fake_bold(mut self, amount: f32) -> Self {
+        self.fake_bold = amount;
+        self
+    }
+
+    /// Synthesizes an italic face by shearing glyph quads horizontally.
+    /// The shear is the horizontal offset per unit of height above the baseline
+    pub fn with_fake_italic(mut self, shear: f32) -> Self {
+        self.fake_italic = shear;
+        self
+    }
+
+    /// Renders from a multi-channel distance field, keeping glyph corners
+    /// sharp at large sizes at the cost of generating it on first use
+    pub fn with_msdf(mut self, msdf: bool) -> Self {
+        self.msdf = msdf;
+        self
+    }
+
+    /// Wraps lines at word boundaries so that none exceeds `width` screen
+    /// pixels, before the transform gets applied
+    pub fn with_wrap_width(mut self, width: f32) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    /// Sets the on screen font size in pixels, measured from ascent to
+    /// descent as rusttype scales do. Glyphs are still cached at load size.
+    /// The size holds for an identity transform, any scale in the transform
+    /// multiplies it further
+    pub fn with_pixel_size(mut self, px: f32) -> Self {
+        self.pixel_size = px;
+        self
+    }
+
+    /// Colors parts of the text differently, characters outside every run
+    /// keep the base color and later runs win where runs overlap
+    pub fn with_color_runs(mut self, runs: &'a [ColorRun]) -> Self {
+        self.color_runs = runs;
+        self
+    }
+
+    /// Sets the distance between baselines of consecutive lines, in on
+    /// screen pixels when given as `LineHeight::Pixels`
+    pub fn with_line_height(mut self, line_height: LineHeight) -> Self {
+        self.line_height = line_height;
+        self
+    }
+
+    /// Adds `px` on screen pixels (negative to tighten) to the advance
+    /// of every glyph
+    pub fn with_letter_spacing(mut self, px: f32) -> Self {
+        self.letter_spacing = px;
+        self
+    }
+
+    /// Sets the base direction of the text. Right to left and mixed
+    /// direction text gets reordered for display, and the lines of right to
+    /// left text are anchored on the right. Characters are drawn
+    /// as is: scripts that need contextual forms or ligatures (like Arabic)
+    /// aren't shaped
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
     pub fn draw(&self, rndr: &TextRenderer) {
         rndr.draw(self)
     }
+
+    // Color of the character at the given byte offset
+    fn color_at(&self, index: usize) -> [f32; 4] {
+        self.color_runs
+            .iter()
+            .rev()
+            .find(|(range, _)| range.start <= index && index < range.end)
+            .map_or(self.color, |(_, color)| *color)
+    }
 }
 
 impl TextRenderer {
-    pub fn new() -> Self {
+    pub fn new() -> Result<TextRenderer, String> {
+        // Compile shader first, so that failing leaks no GL objects
+        let shdr = try!(Shader::new(
+            VERTEX_SHADER,
+            None,
+            FRAGMENT_SHADER,
+            Some(&["vpos", "vtco", "vcol"]),
+        ));
+
         // Make gpu cache
-        let (cache_width, cache_height) = (512, 512);
+        let (cache_width, cache_height) = (CACHE_SIZE, CACHE_SIZE);
         let cache = Cache::builder()
             .dimensions(cache_width, cache_height)
+            .pad_glyphs(GLYPH_PADDING > 0)
             .build();
 
         // Make font atlas texture (GPU)
         let mut id: GLuint = 0;
-        let null_data = vec![0u8; (cache_width * cache_height) as usize];
         unsafe {
             gl::GenTextures(1, &mut id);
             gl::BindTexture(gl::TEXTURE_2D, id);
@@ -199,26 +513,21 @@ impl TextRenderer {
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::ALPHA as _,
-                cache_width as _,
-                cache_height as _,
-                0,
-                gl::ALPHA,
-                gl::UNSIGNED_BYTE,
-                null_data.as_ptr() as _,
-            );
+            alloc_atlas(gl::ALPHA, gl::ALPHA, 1, cache_width, cache_height);
         }
 
-        // Compile shader
-        let shdr = Shader::new(
-            VERTEX_SHADER,
-            None,
-            FRAGMENT_SHADER,
-            Some(&["vpos", "vnrm", "vuv0"]),
-        );
+        // Make msdf atlas texture (GPU)
+        let mut msdf_id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut msdf_id);
+            gl::BindTexture(gl::TEXTURE_2D, msdf_id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+            alloc_atlas(gl::RGB8, gl::RGB, 3, cache_width, cache_height);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
 
         // Make draw buffers
         let mut vbo: GLuint = 0;
@@ -228,94 +537,348 @@ impl TextRenderer {
             gl::GenBuffers(1, &mut ebo);
         }
 
-        TextRenderer {
+        Ok(TextRenderer {
             font_id_gen: 0,
             font_map: HashMap::new(),
             cache: RefCell::new(cache),
             cache_img_id: id,
+            msdf_img_id: msdf_id,
+            msdf_regions: RefCell::new(HashSet::new()),
+            sdf_memo: RefCell::new(SdfMemo::new()),
             shader: shdr,
             draw_vbo: vbo,
             draw_ebo: ebo,
+            ui_scaling: UiScaling::Logical,
+            dpi_factor: 1.0,
+            target_size: Cell::new(None),
+            derivatives: Capabilities::current().supports_derivatives(),
+        })
+    }
+
+    /// Sets the window's HiDPI factor, to be updated whenever it changes
+    pub fn set_dpi_factor(&mut self, dpi_factor: f64) {
+        self.dpi_factor = dpi_factor as f32;
+    }
+
+    /// Sets the size in physical pixels of the target texts get drawn into,
+    /// sparing a viewport query per draw. None (the default) queries the
+    /// viewport, as needed when drawing into offscreen targets of other sizes
+    pub fn set_target_size(&self, size: Option<(u32, u32)>) {
+        self.target_size.set(size);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_ui_scaling(&mut self, ui_scaling: UiScaling) {
+        self.ui_scaling = ui_scaling;
+    }
+
+    fn ui_scale(&self) -> f32 {
+        match self.ui_scaling {
+            UiScaling::Logical => self.dpi_factor,
+            UiScaling::Physical => 1.0,
         }
     }
 
-    pub fn add_font<B: BufRead>(&mut self, name: &str, reader: &mut B) {
-        // Load font from data
-        let mut font_data = Vec::new();
-        reader.read_to_end(&mut font_data).unwrap();
-        let font = Font::from_bytes(font_data).unwrap();
+    pub fn add_font<B: BufRead>(&mut self, name: &str, reader: &mut B) -> Result<(), String> {
+        self.add_font_variant(name, FontStyle::Regular, reader)
+    }
+
+    /// Registers one style of a font family, texts asking for a style
+    /// the family lacks get its regular font
+    pub fn add_font_variant<B: BufRead>(
+        &mut self,
+        family: &str,
+        style: FontStyle,
+        reader: &mut B,
+    ) -> Result<(), String> {
+        let font = try!(load_font(reader));
         // Add to map
         self.font_map
-            .insert(name.to_string(), (self.font_id_gen, font));
+            .insert((family.to_string(), style), (self.font_id_gen, font));
         self.font_id_gen += 1;
+        Ok(())
     }
 
-    pub fn draw(
-        &self,
-        t: &Text
-    ) {
-        // Find font
-        let (font_id, font) = match self.font_map.get(t.font) {
-            Some(a) => a,
-            None => return,
+    fn resolve_font(&self, t: &Text) -> Option<&(usize, Font<'static>)> {
+        font_for_style(&self.font_map, t.font, t.style)
+    }
+
+    // Glyphs are laid out at load size and scaled to display size afterwards
+    fn display_scale(&self, t: &Text) -> f32 {
+        t.pixel_size / FONT_LOAD_SIZE * self.ui_scale()
+    }
+
+    // Metrics and spacing the text gets laid out with, at load size
+    fn layout_metrics(&self, font: &Font<'static>, t: &Text) -> LayoutMetrics {
+        let v_metrics = font.v_metrics(Scale::uniform(FONT_LOAD_SIZE));
+        let fscale = self.display_scale(t);
+        let line_height = match t.line_height {
+            LineHeight::Pixels(px) => LineHeight::Pixels(px / fscale),
+            lh => lh,
+        };
+        LayoutMetrics {
+            ascent: v_metrics.ascent,
+            descent: v_metrics.descent,
+            line_gap: v_metrics.line_gap,
+            line_height,
+            letter_spacing: t.letter_spacing / fscale,
+            direction: t.direction,
+        }
+    }
+
+    /// Lays the text out for mapping between positions and character
+    /// offsets, e.g. for editable text fields
+    #[allow(dead_code)]
+    pub fn layout(&self, t: &Text) -> Option<TextLayout> {
+        let (_, font) = self.resolve_font(t)?;
+        let (lines, metrics) = self.layout_lines(font, t);
+        Some(TextLayout {
+            lines,
+            metrics,
+            scale: self.display_scale(t),
+        })
+    }
+
+    /// Size the text would occupy on screen when drawn, in pixels before
+    /// the transform gets applied. Only lays the text out, so it is cheap
+    /// and leaves the glyph cache alone
+    #[allow(dead_code)]
+    pub fn measure(&self, t: &Text) -> Option<TextMetrics> {
+        let (_, font) = self.resolve_font(t)?;
+        let (glyphs, _, num_lines) = self.layout_text(font, t);
+        let fscale = self.display_scale(t);
+        let bbox = glyphs
+            .iter()
+            .filter_map(|g| g.pixel_bounding_box())
+            .fold(None, |acc: Option<Rect<i32>>, bb| {
+                Some(match acc {
+                    Some(r) => Rect {
+                        min: point(r.min.x.min(bb.min.x), r.min.y.min(bb.min.y)),
+                        max: point(r.max.x.max(bb.max.x), r.max.y.max(bb.max.y)),
+                    },
+                    None => bb,
+                })
+            });
+        let width = bbox.map_or(0, |r| r.width()) as f32;
+        let height = if t.use_vmetrics {
+            num_lines as f32 * self.layout_metrics(font, t).advance_height()
+        } else {
+            bbox.map_or(0, |r| r.height()) as f32
         };
+        Some(TextMetrics {
+            width: width * fscale,
+            height: height * fscale,
+            num_lines,
+        })
+    }
+
+    pub fn draw(&self, t: &Text) {
+        self.draw_batch(&[t]);
+    }
+
+    /// Draws many texts with a single glyph cache pass and a single buffer
+    /// upload. Texts sharing their transform and shading options are drawn
+    /// with one draw call, so texts with differing options may not keep their
+    /// relative drawing order where they overlap.
+    pub fn draw_batch(&self, texts: &[&Text]) {
+        // Lay out every text and queue the glyphs it needs
+        let mut laid_out = Vec::with_capacity(texts.len());
+        for t in texts {
+            let (font_id, font) = match self.resolve_font(t) {
+                Some(a) => a,
+                None => continue,
+            };
+            let (glyphs, byte_indices, num_lines) = self.layout_text(font, t);
+            laid_out.push((*t, *font_id, font, glyphs, byte_indices, num_lines));
+        }
+        if laid_out.is_empty() {
+            return;
+        }
 
-        // Get gluphs
-        let (glyphs, num_lines) =
-            self.layout_paragraph(font, Scale::uniform(FONT_LOAD_SIZE), 2000, t.contents);
-
-        // Queue some positioned glyphs needed for the next frame
-        for glyph in &glyphs {
-            self.cache.borrow_mut().queue_glyph(*font_id, glyph.clone());
-        }
-
-        // Cache all queued glyphs somewhere in the cache texture.
-        // If new glyph data has been drawn the closure is called to upload
-        // the pixel data to GPU memory.
-        self.cache
-            .borrow_mut()
-            .cache_queued(|region, data| {
-                // Pad data
-                let (rw, rh) = (region.width() as usize, region.height() as usize);
-                let pad = 0; // TODO: make padding 1
-                let (nw, nh) = (rw + pad, rh + pad);
-                let mut padded_data = vec![0u8; nw * nh];
-                for i in 0..(nh - pad) {
-                    let src = &data[(i * rw)..((i + 1) * rw)];
-                    let dst = &mut padded_data[(i * nw)..((i + 1) * nw - pad)];
-                    dst.copy_from_slice(src);
+        // Cache the glyphs of all texts in a single pass, newly rasterized
+        // glyphs get turned into distance fields afterwards
+        let glyph_sets = laid_out
+            .iter()
+            .map(|&(_, font_id, _, ref glyphs, _, _)| (font_id, &glyphs[..]))
+            .collect::<Vec<_>>();
+        let pass = {
+            let mut cache = self.cache.borrow_mut();
+            cache_glyphs(&mut cache, &glyph_sets, |cache| self.grow_cache(cache))
+        };
+        let rasterized = match pass {
+            Ok(pass) => pass.rasterized,
+            Err(e) => {
+                println!("Could not cache glyphs: {}", e);
+                return;
+            }
+        };
+        {
+            // Whatever msdf data the regions held belongs to other glyphs
+            let mut msdf_regions = self.msdf_regions.borrow_mut();
+            for (region, _) in &rasterized {
+                msdf_regions.remove(&(region.min.x, region.min.y));
+            }
+        }
+        if !rasterized.is_empty() {
+            self.upload_distance_fields(&glyph_sets, rasterized);
+        }
+
+        for &(t, font_id, _, ref glyphs, _, _) in &laid_out {
+            if t.msdf {
+                self.update_msdf(glyphs, font_id);
+            }
+        }
+
+        // Group the texts that can share a draw call, keeping their order
+        let mut groups: Vec<(DrawUniforms, Vec<usize>)> = Vec::new();
+        for (i, l) in laid_out.iter().enumerate() {
+            let uniforms = self.draw_uniforms(l.0);
+            match groups.iter().position(|g| g.0 == uniforms) {
+                Some(g) => groups[g].1.push(i),
+                None => groups.push((uniforms, vec![i])),
+            }
+        }
+
+        // Build vertex and indice data of every group, back to back
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut draws = Vec::with_capacity(groups.len());
+        for (uniforms, members) in groups {
+            let first_index = indices.len();
+            for i in members {
+                let (t, font_id, font, ref glyphs, ref byte_indices, num_lines) = laid_out[i];
+                let (mut text_vertices, text_indices) =
+                    self.text_vertices(t, font_id, font, glyphs, byte_indices, num_lines);
+                let base = vertices.len() as u32;
+                indices.extend(text_indices.iter().map(|i| base + i));
+                vertices.append(&mut text_vertices);
+            }
+            draws.push((uniforms, first_index..indices.len()));
+        }
+
+        unsafe {
+            // Upload data
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.draw_vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (vertices.len() * size_of::<Vertex>()) as GLsizeiptr,
+                vertices.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            stats::record_buffer_upload();
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.draw_ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (indices.len() * size_of::<u32>()) as GLsizeiptr,
+                indices.as_ptr() as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            stats::record_buffer_upload();
+
+            // Setup attribute bindings
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                0,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                size_of::<Vertex>() as GLint,
+                0 as *const GLvoid,
+            );
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                size_of::<Vertex>() as GLint,
+                (2 * size_of::<f32>()) as *const GLvoid,
+            );
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(
+                2,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                size_of::<Vertex>() as GLint,
+                (4 * size_of::<f32>()) as *const GLvoid,
+            );
+
+            // Draw
+            let prev_state = RenderState::overlay().apply();
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.cache_img_id);
+            stats::record_texture_bind();
+            self.shader.activate();
+            self.shader.set_uniform("tex", 0);
+            for (uniforms, range) in draws {
+                if range.start == range.end {
+                    continue;
                 }
-                // Make Signed Distance Field
-                let dist_map = sdf::make_distance_mapb(&mut padded_data, nw, nh);
-                // Update GPU texture
-                unsafe {
-                    // Update part of gpu texture with new glyph alpha values
-                    gl::BindTexture(gl::TEXTURE_2D, self.cache_img_id);
-                    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
-                    gl::TexSubImage2D(
-                        gl::TEXTURE_2D,
-                        0,
-                        region.min.x as _,
-                        region.min.y as _,
-                        nw as _,
-                        nh as _,
-                        gl::ALPHA,
-                        gl::UNSIGNED_BYTE,
-                        dist_map.as_ptr() as _,
-                    );
-                    gl::BindTexture(gl::TEXTURE_2D, 0);
+                uniforms.apply(&self.shader);
+                if uniforms.msdf {
+                    gl::ActiveTexture(gl::TEXTURE1);
+                    gl::BindTexture(gl::TEXTURE_2D, self.msdf_img_id);
+                    stats::record_texture_bind();
+                    self.shader.set_uniform("mtex", 1);
+                    gl::ActiveTexture(gl::TEXTURE0);
                 }
-            }).unwrap();
+                gl::DrawElements(
+                    gl::TRIANGLES,
+                    range.len() as GLint,
+                    gl::UNSIGNED_INT,
+                    (range.start * size_of::<u32>()) as *const GLvoid,
+                );
+                stats::record_draw((range.len() / 3) as u32);
+            }
+            prev_state.apply();
+        }
+    }
+
+    // Per text shader state, texts with equal state can share a draw call
+    fn draw_uniforms(&self, t: &Text) -> DrawUniforms {
+        // Compute scale factor
+        let m = t.transform;
+        let scl = (m[1][1] * m[1][1] + m[1][2] * m[1][2] + m[1][3] * m[1][3]).sqrt();
+        DrawUniforms {
+            transform: *t.transform,
+            super_sample: t.super_sample,
+            dfd: t.dfd_antialiasing && self.derivatives,
+            scale: scl,
+            softness: t.edge_softness,
+            bold: t.fake_bold,
+            msdf: t.msdf,
+        }
+    }
+
+    // Builds the aligned quads of a laid out text, in the text's own space
+    fn text_vertices(
+        &self,
+        t: &Text,
+        font_id: usize,
+        font: &Font<'static>,
+        glyphs: &[PositionedGlyph],
+        byte_indices: &[usize],
+        num_lines: u32,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        let fscale = self.display_scale(t);
 
         // Build vertex and indice data
-        let (mut vertices, indices) = self.build_vertex_and_indice_data(&glyphs, *font_id);
+        let colors: Vec<_> = byte_indices
+            .iter()
+            .map(|i| output_color(&t.color_at(*i)))
+            .collect();
+        let (mut vertices, indices) =
+            self.build_vertex_and_indice_data(glyphs, &colors, font_id, t.fake_italic);
 
-        // Get viewport size
-        let vp: [GLint; 4] = [0; 4];
-        unsafe {
-            gl::GetIntegerv(gl::VIEWPORT, vp.as_ptr() as *mut GLint);
-        }
-        let (scr_w, scr_h) = ((vp[2] - vp[0]) as f32, (vp[3] - vp[1]) as f32);
+        // Get target size
+        let (scr_w, scr_h) = match self.target_size.get() {
+            Some((w, h)) => (w as f32, h as f32),
+            None => {
+                let vp = viewport();
+                ((vp[2] - vp[0]) as f32, (vp[3] - vp[1]) as f32)
+            }
+        };
 
         // Get phrase bounding box
         let bbox = vertices.iter().fold(
@@ -334,7 +897,8 @@ impl TextRenderer {
         );
 
         // Alignment
-        let v_metrics = font.v_metrics(Scale::uniform(FONT_LOAD_SIZE));
+        let metrics = self.layout_metrics(font, t);
+        let advance_height = metrics.advance_height();
         for v in vertices.iter_mut() {
             // Center in bbox horizontally
             v.0[0] -= bbox.min.x + bbox.width() / 2.0;
@@ -356,7 +920,6 @@ impl TextRenderer {
                     VAlignment::Bottom => v.0[1] -= bbox.height() / 2.0,
                 }
             } else {
-                let advance_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
                 match t.valign {
                     VAlignment::Top => {
                         v.0[1] += num_lines as f32 * advance_height;
@@ -365,92 +928,192 @@ impl TextRenderer {
                         v.0[1] += bbox.min.y + bbox.height() / 2.0;
                     }
                     VAlignment::Bottom => {
-                        v.0[1] -= v_metrics.descent;
+                        v.0[1] -= metrics.descent;
                     }
                 }
             }
             // Normalize
             v.0[0] = (v.0[0] / scr_w) * 2.0;
             v.0[1] = (v.0[1] / scr_h) * 2.0;
-            // Scale (convert to em)
-            let fscale = 16.0 / FONT_LOAD_SIZE;
+            // Scale to display size
             v.0[0] *= fscale;
             v.0[1] *= fscale;
         }
 
+        (vertices, indices)
+    }
+
+    // Doubles the glyph cache dimensions, dropping every cached glyph.
+    // Returns false when the atlas is already as large as GL allows
+    fn grow_cache(&self, cache: &mut Cache<'static>) -> bool {
+        let (w, h) = cache.dimensions();
+        let mut max_size: GLint = 0;
         unsafe {
-            // Upload data
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.draw_vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertices.len() * size_of::<Vertex>()) as GLsizeiptr,
-                vertices.as_ptr() as *const GLvoid,
-                gl::DYNAMIC_DRAW,
-            );
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.draw_ebo);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (indices.len() * size_of::<u32>()) as GLsizeiptr,
-                indices.as_ptr() as *const GLvoid,
-                gl::DYNAMIC_DRAW,
-            );
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+        }
+        if w.max(h) * 2 > max_size as u32 {
+            return false;
+        }
+        let (w, h) = (w * 2, h * 2);
+        // Keeps the queue, so the glyphs can be cached again right away
+        cache.to_builder().dimensions(w, h).rebuild(cache);
+        self.msdf_regions.borrow_mut().clear();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.cache_img_id);
+            alloc_atlas(gl::ALPHA, gl::ALPHA, 1, w, h);
+            gl::BindTexture(gl::TEXTURE_2D, self.msdf_img_id);
+            alloc_atlas(gl::RGB8, gl::RGB, 3, w, h);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        true
+    }
 
-            // Setup attribute bindings
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                0,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                size_of::<Vertex>() as GLint,
-                0 as *const GLvoid,
-            );
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                size_of::<Vertex>() as GLint,
-                (2 * size_of::<f32>()) as *const GLvoid,
-            );
+    // Turns freshly rasterized cache regions into distance fields and uploads
+    // them, reusing the memoized field of glyphs that were cached before
+    fn upload_distance_fields(
+        &self,
+        glyph_sets: &[(usize, &[PositionedGlyph])],
+        rasterized: Vec<(Rect<u32>, Vec<u8>)>,
+    ) {
+        // Identify the glyphs by the top left corner of their padded region
+        let mut region_glyphs = HashMap::new();
+        {
+            let cache = self.cache.borrow();
+            let (cache_w, cache_h) = cache.dimensions();
+            for &(font_id, glyphs) in glyph_sets {
+                for g in glyphs {
+                    if let Ok(Some((uv_rect, _))) = cache.rect_for(font_id, g) {
+                        let x0 = (uv_rect.min.x * cache_w as f32).round() as u32 - GLYPH_PADDING;
+                        let y0 = (uv_rect.min.y * cache_h as f32).round() as u32 - GLYPH_PADDING;
+                        let scale = g.scale();
+                        region_glyphs.insert(
+                            (x0, y0),
+                            (font_id, g.id().0, scale.x.to_bits(), scale.y.to_bits()),
+                        );
+                    }
+                }
+            }
+        }
 
-            // Compute scale factor
-            let m = &t.transform;
-            let scl = (m[1][1] * m[1][1] + m[1][2] * m[1][2] + m[1][3] * m[1][3]).sqrt();
+        let mut memo = self.sdf_memo.borrow_mut();
+        for (region, data) in rasterized {
+            let (rw, rh) = (region.width() as usize, region.height() as usize);
+            let key = region_glyphs
+                .get(&(region.min.x, region.min.y))
+                .map(|&(font_id, glyph_id, sx, sy)| (font_id, glyph_id, sx, sy, rw, rh));
+            let memoized = key.and_then(|k| memo.get(&k).cloned());
+            let dist_map = match memoized {
+                Some(dist_map) => dist_map,
+                None => {
+                    let dist_map = padded_distance_field(&data, rw, rh);
+                    if let Some(k) = key {
+                        memo.insert(k, dist_map.clone());
+                    }
+                    dist_map
+                }
+            };
+            unsafe { self.upload_glyph_region(&region, &dist_map) };
+        }
+    }
 
-            // Draw
-            gl::Disable(gl::DEPTH_TEST);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, self.cache_img_id);
-            self.shader.activate();
-            self.shader.set_uniform("col", &t.color);
-            self.shader.set_uniform("mvp", t.transform);
-            self.shader.set_uniform("ssp", t.super_sample);
-            self.shader.set_uniform("dfd", t.dfd_antialiasing);
-            self.shader.set_uniform("scl", scl);
-            self.shader.set_uniform("tex", 0);
-            gl::DrawElements(
-                gl::TRIANGLES,
-                indices.len() as GLint,
-                gl::UNSIGNED_INT,
-                0 as *const GLvoid,
-            );
-            gl::Disable(gl::BLEND);
+    // Update part of gpu texture with new glyph alpha values
+    unsafe fn upload_glyph_region(&self, region: &Rect<u32>, dist_map: &[u8]) {
+        gl::BindTexture(gl::TEXTURE_2D, self.cache_img_id);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexSubImage2D(
+            gl::TEXTURE_2D,
+            0,
+            region.min.x as _,
+            region.min.y as _,
+            region.width() as _,
+            region.height() as _,
+            gl::ALPHA,
+            gl::UNSIGNED_BYTE,
+            dist_map.as_ptr() as _,
+        );
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+
+    // Generates msdf data for the cached glyphs that don't have it yet
+    fn update_msdf(&self, glyphs: &[PositionedGlyph], font_id: usize) {
+        let cache = self.cache.borrow();
+        let (cache_w, cache_h) = cache.dimensions();
+        let mut msdf_regions = self.msdf_regions.borrow_mut();
+        for g in glyphs {
+            let (uv_rect, bb) = match (cache.rect_for(font_id, g), g.pixel_bounding_box()) {
+                (Ok(Some((uv_rect, _))), Some(bb)) => (uv_rect, bb),
+                _ => continue,
+            };
+            // The cache pads glyphs on every side
+            let x0 = (uv_rect.min.x * cache_w as f32).round() as u32 - GLYPH_PADDING;
+            let y0 = (uv_rect.min.y * cache_h as f32).round() as u32 - GLYPH_PADDING;
+            if msdf_regions.contains(&(x0, y0)) {
+                continue;
+            }
+            let border = 2 * GLYPH_PADDING as usize;
+            let (w, h) = (bb.width() as usize + border, bb.height() as usize + border);
+            let contours = match g.unpositioned().shape() {
+                Some(contours) => contours,
+                None => continue,
+            };
+            // Glyph space (y up, origin at the pen position) to region pixels
+            let pos = g.position();
+            let pad = GLYPH_PADDING as f32;
+            let to_region = |p: Point<f32>| {
+                [
+                    (pos.x + p.x - bb.min.x as f32 + pad) as f64,
+                    (pos.y - p.y - bb.min.y as f32 + pad) as f64,
+                ]
+            };
+            let segments = contours
+                .iter()
+                .map(|c| {
+                    c.segments
+                        .iter()
+                        .map(|s| match s {
+                            Segment::Line(l) => {
+                                sdf::MsdfSegment::Line(to_region(l.p[0]), to_region(l.p[1]))
+                            }
+                            Segment::Curve(c) => sdf::MsdfSegment::Quad(
+                                to_region(c.p[0]),
+                                to_region(c.p[1]),
+                                to_region(c.p[2]),
+                            ),
+                        }).collect::<Vec<_>>()
+                }).collect::<Vec<_>>();
+            let msdf = sdf::make_msdf(&segments, w, h, MSDF_RANGE);
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D, self.msdf_img_id);
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                gl::TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    x0 as _,
+                    y0 as _,
+                    w as _,
+                    h as _,
+                    gl::RGB,
+                    gl::UNSIGNED_BYTE,
+                    msdf.as_ptr() as _,
+                );
+                gl::BindTexture(gl::TEXTURE_2D, 0);
+            }
+            msdf_regions.insert((x0, y0));
         }
     }
 
     fn build_vertex_and_indice_data(
         &self,
         glyphs: &[PositionedGlyph],
+        colors: &[[f32; 4]],
         font_id: usize,
+        shear: f32,
     ) -> (Vec<Vertex>, Vec<u32>) {
         let mut nglyphs = 0;
         let vertices: Vec<_> = glyphs
             .iter()
-            .flat_map(|g| {
+            .zip(colors)
+            .flat_map(|(g, col)| {
                 // Lookup a positioned glyph's texture location
                 if let Ok(Some((uv_rect, scr_rect))) = self.cache.borrow().rect_for(font_id, g) {
                     nglyphs += 1;
@@ -458,22 +1121,32 @@ impl TextRenderer {
                         min: point(scr_rect.min.x as f32, scr_rect.min.y as f32),
                         max: point(scr_rect.max.x as f32, scr_rect.max.y as f32),
                     };
+                    // Shear relative to the glyph's baseline (y grows downwards)
+                    let baseline = g.position().y;
+                    let (top_shift, bottom_shift) = (
+                        shear * (baseline - sc_rect.min.y),
+                        shear * (baseline - sc_rect.max.y),
+                    );
                     let verts = vec![
                         Vertex(
-                            [sc_rect.min.x, sc_rect.min.y],
+                            [sc_rect.min.x + top_shift, sc_rect.min.y],
                             [uv_rect.min.x, uv_rect.min.y],
+                            *col,
                         ),
                         Vertex(
-                            [sc_rect.min.x, sc_rect.max.y],
+                            [sc_rect.min.x + bottom_shift, sc_rect.max.y],
                             [uv_rect.min.x, uv_rect.max.y],
+                            *col,
                         ),
                         Vertex(
-                            [sc_rect.max.x, sc_rect.max.y],
+                            [sc_rect.max.x + bottom_shift, sc_rect.max.y],
                             [uv_rect.max.x, uv_rect.max.y],
+                            *col,
                         ),
                         Vertex(
-                            [sc_rect.max.x, sc_rect.min.y],
+                            [sc_rect.max.x + top_shift, sc_rect.min.y],
                             [uv_rect.max.x, uv_rect.min.y],
+                            *col,
                         ),
                     ];
                     verts
@@ -487,57 +1160,300 @@ impl TextRenderer {
         (vertices, indices)
     }
 
-    fn layout_paragraph(
+    // Breaks the text into lines, at load size
+    fn layout_lines(&self, font: &Font<'static>, t: &Text) -> (Vec<Line>, LayoutMetrics) {
+        let wrap_width = match t.wrap_width {
+            Some(w) => w / self.display_scale(t),
+            None => std::f32::INFINITY,
+        };
+        let metrics = self.layout_metrics(font, t);
+        let scale = Scale::uniform(FONT_LOAD_SIZE);
+        let lines = layout::layout_paragraph(
+            t.contents,
+            &metrics,
+            wrap_width,
+            |c| font.glyph(c).scaled(scale).h_metrics().advance_width,
+            |a, b| font.pair_kerning(scale, a, b),
+        );
+        (lines, metrics)
+    }
+
+    // Positioned glyphs of the text at load size, along with the byte offset
+    // of each glyph's character and the number of lines
+    fn layout_text(
         &self,
         font: &Font<'static>,
-        scale: Scale,
-        width: u32,
-        text: &str,
-    ) -> (Vec<PositionedGlyph<'static>>, u32) {
-        let mut result = Vec::new();
-        let v_metrics = font.v_metrics(scale);
-        let advance_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
-        let mut caret = point(0.0, v_metrics.ascent);
-        let mut num_lines = 1;
-        let mut last_glyph_id = None;
-        for c in text.chars() {
-            if c.is_control() {
-                match c {
-                    '\r' => {
-                        caret = point(0.0, caret.y + advance_height);
-                        num_lines += 1;
-                    }
-                    '\n' => {}
-                    _ => {}
+        t: &Text,
+    ) -> (Vec<PositionedGlyph<'static>>, Vec<usize>, u32) {
+        let (lines, _) = self.layout_lines(font, t);
+        let scale = Scale::uniform(FONT_LOAD_SIZE);
+        let glyphs = lines
+            .iter()
+            .flat_map(|line| {
+                line.glyphs.iter().map(move |g| {
+                    font.glyph(g.chr)
+                        .scaled(scale)
+                        .positioned(point(g.x, line.baseline_y))
+                })
+            }).collect();
+        let byte_indices = lines
+            .iter()
+            .flat_map(|line| line.glyphs.iter().map(|g| g.index))
+            .collect();
+        (glyphs, byte_indices, lines.len() as u32)
+    }
+}
+
+// Glyph cache work done for a batch of texts
+struct CachePass {
+    // Regions rasterized during the pass, along with their coverage data
+    rasterized: Vec<(Rect<u32>, Vec<u8>)>,
+    // Times the queue went through the cache, more than once when the
+    // cache had to grow to fit it
+    cache_calls: u32,
+}
+
+// Queues the glyphs of every (font id, glyphs) set and caches them all at
+// once, calling `grow` to make room when they don't fit
+fn cache_glyphs<G>(
+    cache: &mut Cache<'static>,
+    glyph_sets: &[(usize, &[PositionedGlyph<'static>])],
+    mut grow: G,
+) -> Result<CachePass, String>
+where
+    G: FnMut(&mut Cache<'static>) -> bool,
+{
+    for &(font_id, glyphs) in glyph_sets {
+        for glyph in glyphs {
+            cache.queue_glyph(font_id, glyph.clone());
+        }
+    }
+    let mut pass = CachePass {
+        rasterized: Vec::new(),
+        cache_calls: 0,
+    };
+    loop {
+        pass.cache_calls += 1;
+        let result = {
+            let rasterized = &mut pass.rasterized;
+            cache.cache_queued(|region, data| rasterized.push((region, data.to_vec())))
+        };
+        match result {
+            Ok(_) => return Ok(pass),
+            Err(e) => {
+                // A grown cache starts out empty and rasterizes everything
+                pass.rasterized.clear();
+                if !grow(cache) {
+                    cache.clear_queue();
+                    return Err(e.to_string());
                 }
-                continue;
-            }
-            let base_glyph = font.glyph(c);
-            if let Some(id) = last_glyph_id.take() {
-                caret.x += font.pair_kerning(scale, id, base_glyph.id());
             }
-            last_glyph_id = Some(base_glyph.id());
-            let mut glyph = base_glyph.scaled(scale).positioned(caret);
-            if let Some(bb) = glyph.pixel_bounding_box() {
-                if bb.max.x > width as i32 {
-                    caret = point(0.0, caret.y + advance_height);
-                    glyph = glyph.into_unpositioned().positioned(caret);
-                    last_glyph_id = None;
-                }
+        }
+    }
+}
+
+// Entry of the requested style of a family, or of its regular style when
+// the family lacks it
+fn font_for_style<'m, V>(
+    fonts: &'m HashMap<(String, FontStyle), V>,
+    family: &str,
+    style: FontStyle,
+) -> Option<&'m V> {
+    let family = family.to_string();
+    fonts
+        .get(&(family.clone(), style))
+        .or_else(|| fonts.get(&(family, FontStyle::Regular)))
+}
+
+fn load_font<B: BufRead>(reader: &mut B) -> Result<Font<'static>, String> {
+    let mut font_data = Vec::new();
+    try!(reader.read_to_end(&mut font_data).map_err(|e| e.to_string()));
+    // The parser indexes into the data without bounds checks and panics on
+    // truncated files instead of reporting them
+    match std::panic::catch_unwind(move || Font::from_bytes(font_data)) {
+        Ok(font) => font.map_err(|e| e.to_string()),
+        Err(_) => Err("Malformed font data".to_string()),
+    }
+}
+
+// Signed distance field of a cached glyph region. The region includes the
+// padding border, which is cleared so that linear filtering never picks up
+// the field of the neighbouring glyph
+fn padded_distance_field(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut dist_map = sdf::make_distance_mapb(data, width, height);
+    let pad = GLYPH_PADDING as usize;
+    for y in 0..height {
+        for x in 0..width {
+            if x < pad || y < pad || x + pad >= width || y + pad >= height {
+                dist_map[y * width + x] = 0;
             }
-            caret.x += glyph.unpositioned().h_metrics().advance_width;
-            result.push(glyph);
         }
-        (result, num_lines)
     }
+    dist_map
+}
+
+// (Re)allocates the storage of the bound atlas texture, cleared to zero
+unsafe fn alloc_atlas(internal_format: GLenum, format: GLenum, channels: u32, w: u32, h: u32) {
+    let null_data = vec![0u8; (w * h * channels) as usize];
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        internal_format as _,
+        w as _,
+        h as _,
+        0,
+        format,
+        gl::UNSIGNED_BYTE,
+        null_data.as_ptr() as _,
+    );
 }
 
 impl Drop for TextRenderer {
     fn drop(&mut self) {
         unsafe {
             gl::DeleteTextures(1, &self.cache_img_id);
+            gl::DeleteTextures(1, &self.msdf_img_id);
             gl::DeleteBuffers(1, &self.draw_ebo);
             gl::DeleteBuffers(1, &self.draw_vbo);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memo_key(glyph_id: u32) -> SdfKey {
+        (0, glyph_id, 48, 48, 32, 32)
+    }
+
+    #[test]
+    fn memo_evicts_oldest_past_bound() {
+        let mut memo = SdfMemo::new();
+        let entry_bytes = SDF_MEMO_BYTES / 4;
+        for id in 0..5 {
+            memo.insert(memo_key(id), vec![0; entry_bytes]);
+        }
+        assert!(memo.bytes <= SDF_MEMO_BYTES);
+        assert!(memo.get(&memo_key(0)).is_none());
+        for id in 1..5 {
+            assert!(memo.get(&memo_key(id)).is_some());
+        }
+    }
+
+    #[test]
+    fn memo_reinsert_replaces_entry() {
+        let mut memo = SdfMemo::new();
+        memo.insert(memo_key(0), vec![0; 100]);
+        memo.insert(memo_key(0), vec![1; 40]);
+        assert_eq!(memo.bytes, 40);
+        assert_eq!(memo.order.len(), 1);
+        assert_eq!(memo.get(&memo_key(0)), Some(&vec![1; 40]));
+    }
+
+    #[test]
+    fn invalid_font_data_fails_to_load() {
+        assert!(load_font(&mut &b"not a font"[..]).is_err());
+        // Truncated data, which makes the parser panic
+        assert!(load_font(&mut &b""[..]).is_err());
+        assert!(load_font(&mut &b"\0\x01\0\0"[..]).is_err());
+        assert!(load_font(&mut &b"ttcf"[..]).is_err());
+    }
+
+    fn font_ids() -> HashMap<(String, FontStyle), usize> {
+        let mut fonts = HashMap::new();
+        fonts.insert(("sans".to_string(), FontStyle::Regular), 0);
+        fonts.insert(("sans".to_string(), FontStyle::Bold), 1);
+        fonts.insert(("serif".to_string(), FontStyle::Bold), 2);
+        fonts
+    }
+
+    #[test]
+    fn bold_picks_bold_font() {
+        let fonts = font_ids();
+        assert_eq!(font_for_style(&fonts, "sans", FontStyle::Bold), Some(&1));
+        assert_eq!(font_for_style(&fonts, "sans", FontStyle::Regular), Some(&0));
+    }
+
+    #[test]
+    fn missing_style_falls_back_to_regular() {
+        let fonts = font_ids();
+        assert_eq!(font_for_style(&fonts, "sans", FontStyle::Italic), Some(&0));
+        assert_eq!(font_for_style(&fonts, "serif", FontStyle::Italic), None);
+        assert_eq!(font_for_style(&fonts, "mono", FontStyle::Regular), None);
+    }
+
+    // The bundled font, unless the checkout only has its git lfs pointer
+    fn hack_font() -> Option<Font<'static>> {
+        let data = std::fs::read("assets/Hack-Regular.ttf").unwrap_or_default();
+        let font = load_font(&mut &data[..]).ok();
+        if font.is_none() {
+            println!("assets/Hack-Regular.ttf is not checked out, skipping");
+        }
+        font
+    }
+
+    fn laid_out_glyphs(font: &Font<'static>, text: &str) -> Vec<PositionedGlyph<'static>> {
+        let scale = Scale::uniform(FONT_LOAD_SIZE);
+        text.chars()
+            .enumerate()
+            .map(|(i, c)| {
+                font.glyph(c)
+                    .scaled(scale)
+                    .positioned(point(i as f32 * 30.0, 0.0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_caches_glyphs_in_one_pass() {
+        let font = match hack_font() {
+            Some(font) => font,
+            None => return,
+        };
+        let texts = ["Hello", "world", "in one batch"]
+            .iter()
+            .map(|t| laid_out_glyphs(&font, t))
+            .collect::<Vec<_>>();
+        let glyph_sets = texts.iter().map(|g| (0, &g[..])).collect::<Vec<_>>();
+        let mut cache = Cache::builder()
+            .dimensions(CACHE_SIZE, CACHE_SIZE)
+            .pad_glyphs(GLYPH_PADDING > 0)
+            .build();
+        let pass = cache_glyphs(&mut cache, &glyph_sets, |_| false).unwrap();
+        assert_eq!(pass.cache_calls, 1);
+        assert!(!pass.rasterized.is_empty());
+        for g in texts.iter().flat_map(|g| g.iter()) {
+            assert!(cache.rect_for(0, g).is_ok());
+        }
+        // Everything is cached already the second time around
+        let pass = cache_glyphs(&mut cache, &glyph_sets, |_| false).unwrap();
+        assert_eq!(pass.cache_calls, 1);
+        assert!(pass.rasterized.is_empty());
+    }
+
+    #[test]
+    fn padded_distance_field_has_zero_border() {
+        // Glyph covering the whole interior of a 6x5 padded region
+        let (w, h) = (6, 5);
+        let pad = GLYPH_PADDING as usize;
+        let mut data = vec![0u8; w * h];
+        for y in pad..h - pad {
+            for x in pad..w - pad {
+                data[y * w + x] = 255;
+            }
+        }
+        let dist_map = padded_distance_field(&data, w, h);
+        for y in 0..h {
+            for x in 0..w {
+                let border = x < pad || y < pad || x + pad >= w || y + pad >= h;
+                if border {
+                    assert_eq!(dist_map[y * w + x], 0, "border pixel {},{}", x, y);
+                } else {
+                    assert!(dist_map[y * w + x] > 0, "interior pixel {},{}", x, y);
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,332 @@
-use assets::image::Image;
+use super::capabilities::Capabilities;
+use super::stats;
+use assets::image::{Image, NativeImage};
+use assets::ktx::CompressedImage;
 use gl;
 use gl::types::*;
+use image::{imageops, GenericImageView};
+use std;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Monotonic counter stamped on textures every time they get bound,
+// used to find the least recently used ones
+static BIND_CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// Pixel storage of textures allocated without an image, e.g. render targets
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFormat {
+    R8,
+    Rgb8,
+    Rgba8,
+    Srgb8,
+    Srgb8Alpha8,
+    Rgba16F,
+    Rgba32F,
+    Depth24,
+}
+
+impl TextureFormat {
+    // (internal format, base format, component type, bytes per pixel)
+    fn gl_format(&self) -> (GLenum, GLenum, GLenum, usize) {
+        match self {
+            TextureFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE, 1),
+            TextureFormat::Rgb8 => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE, 3),
+            TextureFormat::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, 4),
+            TextureFormat::Srgb8 => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE, 3),
+            TextureFormat::Srgb8Alpha8 => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE, 4),
+            TextureFormat::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT, 8),
+            TextureFormat::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT, 16),
+            TextureFormat::Depth24 => {
+                (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_INT, 4)
+            }
+        }
+    }
+
+    /// Sized internal format, e.g. for renderbuffer storage
+    pub fn internal_format(&self) -> GLenum {
+        self.gl_format().0
+    }
+}
+
+/// Block compressed formats that can be uploaded as is with
+/// `Texture::from_compressed`. ETC2 is core in GLES 3.0, S3TC (BC1/BC3) is
+/// mostly found on desktop and ASTC on recent mobile GPUs.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressedFormat {
+    Etc2Rgb8,
+    Etc2Srgb8,
+    Etc2Rgba8,
+    Etc2Srgb8Alpha8,
+    /// DXT1
+    Bc1Rgba,
+    /// DXT5
+    Bc3Rgba,
+    Astc4x4,
+    Astc8x8,
+}
+
+#[allow(dead_code)]
+impl CompressedFormat {
+    pub fn gl_internal_format(&self) -> GLenum {
+        match self {
+            CompressedFormat::Etc2Rgb8 => gl::COMPRESSED_RGB8_ETC2,
+            CompressedFormat::Etc2Srgb8 => gl::COMPRESSED_SRGB8_ETC2,
+            CompressedFormat::Etc2Rgba8 => gl::COMPRESSED_RGBA8_ETC2_EAC,
+            CompressedFormat::Etc2Srgb8Alpha8 => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            CompressedFormat::Bc1Rgba => gl::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+            CompressedFormat::Bc3Rgba => gl::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+            CompressedFormat::Astc4x4 => gl::COMPRESSED_RGBA_ASTC_4x4_KHR,
+            CompressedFormat::Astc8x8 => gl::COMPRESSED_RGBA_ASTC_8x8_KHR,
+        }
+    }
+
+    /// The format matching a GL internal format, as stored in KTX files
+    pub fn from_gl(internal_format: GLenum) -> Option<CompressedFormat> {
+        let formats = [
+            CompressedFormat::Etc2Rgb8,
+            CompressedFormat::Etc2Srgb8,
+            CompressedFormat::Etc2Rgba8,
+            CompressedFormat::Etc2Srgb8Alpha8,
+            CompressedFormat::Bc1Rgba,
+            CompressedFormat::Bc3Rgba,
+            CompressedFormat::Astc4x4,
+            CompressedFormat::Astc8x8,
+        ];
+        formats
+            .iter()
+            .find(|f| f.gl_internal_format() == internal_format)
+            .cloned()
+    }
+
+    // Width and height in pixels of a compressed block
+    fn block_dimensions(&self) -> (u32, u32) {
+        match self {
+            CompressedFormat::Astc8x8 => (8, 8),
+            _ => (4, 4),
+        }
+    }
+
+    fn block_bytes(&self) -> usize {
+        match self {
+            CompressedFormat::Etc2Rgb8
+            | CompressedFormat::Etc2Srgb8
+            | CompressedFormat::Bc1Rgba => 8,
+            _ => 16,
+        }
+    }
+
+    /// Bytes taken by a single image of the given dimensions, partial
+    /// blocks at the edges count as whole ones
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let (bw, bh) = self.block_dimensions();
+        let blocks = ((width + bw - 1) / bw) * ((height + bh - 1) / bh);
+        blocks as usize * self.block_bytes()
+    }
+
+    /// Whether the context can sample textures of this format
+    pub fn is_supported(&self, caps: &Capabilities) -> bool {
+        match self {
+            CompressedFormat::Etc2Rgb8
+            | CompressedFormat::Etc2Srgb8
+            | CompressedFormat::Etc2Rgba8
+            | CompressedFormat::Etc2Srgb8Alpha8 => {
+                let core = if caps.es {
+                    caps.major >= 3
+                } else {
+                    (caps.major, caps.minor) >= (4, 3)
+                };
+                core || caps.has_extension("GL_ARB_ES3_compatibility")
+            }
+            CompressedFormat::Bc1Rgba | CompressedFormat::Bc3Rgba => {
+                caps.has_extension("GL_EXT_texture_compression_s3tc")
+            }
+            CompressedFormat::Astc4x4 | CompressedFormat::Astc8x8 => {
+                (caps.es && (caps.major, caps.minor) >= (3, 2))
+                    || caps.has_extension("GL_KHR_texture_compression_astc_ldr")
+            }
+        }
+    }
+}
+
+/// Sampling state of a texture. Mipmapped minification filters need
+/// `generate_mipmaps`, otherwise the texture is incomplete and samples black.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureParams {
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    pub generate_mipmaps: bool,
+    /// Treat the pixels as sRGB encoded, so sampling returns linear values.
+    /// Set it for color textures (albedo, UI art, photos) rendered into an
+    /// sRGB framebuffer. Data textures (normal maps, heightmaps, distance
+    /// fields, lookup tables) must stay linear. Single channel images are
+    /// always linear.
+    pub srgb: bool,
+    /// Anisotropic filtering level, 1 to disable. Clamped to what the
+    /// context supports, and ignored when it lacks the extension
+    pub anisotropy: f32,
+}
+
+#[allow(dead_code)]
+impl TextureParams {
+    /// Nearest filtering without mipmaps, clamped, for pixel art and UI
+    pub fn pixel_art() -> Self {
+        TextureParams {
+            min_filter: gl::NEAREST,
+            mag_filter: gl::NEAREST,
+            wrap_s: gl::CLAMP_TO_EDGE,
+            wrap_t: gl::CLAMP_TO_EDGE,
+            generate_mipmaps: false,
+            srgb: false,
+            anisotropy: 1.0,
+        }
+    }
+
+    /// The default params for sRGB encoded color textures
+    pub fn srgb() -> Self {
+        TextureParams {
+            srgb: true,
+            ..TextureParams::default()
+        }
+    }
+
+    pub fn with_anisotropy(mut self, level: f32) -> Self {
+        self.anisotropy = level;
+        self
+    }
+
+    unsafe fn apply(&self, target: GLenum) {
+        gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, self.min_filter as GLint);
+        gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, self.mag_filter as GLint);
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s as GLint);
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t as GLint);
+        if self.anisotropy > 1.0 {
+            if let Some(level) = Capabilities::current().clamp_anisotropy(self.anisotropy) {
+                gl::TexParameterf(target, gl::TEXTURE_MAX_ANISOTROPY_EXT, level);
+            }
+        }
+    }
+}
+
+impl Default for TextureParams {
+    fn default() -> Self {
+        TextureParams {
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::REPEAT,
+            generate_mipmaps: true,
+            srgb: false,
+            anisotropy: 1.0,
+        }
+    }
+}
 
 pub struct Texture {
     id: GLuint,
+    // TEXTURE_2D or TEXTURE_CUBE_MAP
+    target: GLenum,
+    byte_size: usize,
+    last_bind: Cell<usize>,
 }
 
 impl Texture {
     pub fn from_image(image: &Image) -> Texture {
+        Self::from_image_with_params(image, &TextureParams::default())
+    }
+
+    pub fn from_image_with_params(image: &Image, params: &TextureParams) -> Texture {
         let (width, height) = image.dimensions();
-        let data = image.as_ptr();
+        Self::from_pixels(width, height, TextureFormat::Rgba8, image, params)
+    }
+
+    /// Uploads an image keeping its channel layout, so that grayscale and
+    /// RGB sources don't pay for unused channels. Single channel textures
+    /// sample as gray (r, r, r, 1). Layouts without a GL counterpart are
+    /// converted to RGBA.
+    #[allow(dead_code)]
+    pub fn from_native_image(image: &NativeImage, params: &TextureParams) -> Texture {
+        let (width, height) = image.dimensions();
+        match image {
+            NativeImage::ImageLuma8(img) => {
+                Self::from_pixels(width, height, TextureFormat::R8, img, params)
+            }
+            NativeImage::ImageRgb8(img) => {
+                Self::from_pixels(width, height, TextureFormat::Rgb8, img, params)
+            }
+            NativeImage::ImageRgba8(img) => {
+                Self::from_pixels(width, height, TextureFormat::Rgba8, img, params)
+            }
+            _ => {
+                let img = image.to_rgba();
+                Self::from_pixels(width, height, TextureFormat::Rgba8, &img, params)
+            }
+        }
+    }
+
+    fn from_pixels(
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        data: &[u8],
+        params: &TextureParams,
+    ) -> Texture {
+        let format = match format {
+            TextureFormat::Rgb8 if params.srgb => TextureFormat::Srgb8,
+            TextureFormat::Rgba8 if params.srgb => TextureFormat::Srgb8Alpha8,
+            _ => format,
+        };
+        let (internal_format, base_format, component_type, pixel_size) = format.gl_format();
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            // Rows of 1 and 3 channel images aren't 4 byte aligned
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as GLint,
+                width as GLint,
+                height as GLint,
+                0,
+                base_format,
+                component_type,
+                data.as_ptr() as *const GLvoid,
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            if format == TextureFormat::R8 {
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_G, gl::RED as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_B, gl::RED as GLint);
+            }
+            params.apply(gl::TEXTURE_2D);
+            if params.generate_mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        let mut byte_size = (width * height) as usize * pixel_size;
+        if params.generate_mipmaps {
+            // Full mip chain adds roughly a third on top of the base level
+            byte_size = byte_size * 4 / 3;
+        }
+        Texture {
+            id,
+            byte_size,
+            target: gl::TEXTURE_2D,
+            last_bind: Cell::new(0),
+        }
+    }
+
+    /// Allocates an uninitialized single level texture, clamped and
+    /// linearly filtered, suitable as a render target
+    #[allow(dead_code)]
+    pub fn empty(width: u32, height: u32, format: TextureFormat) -> Texture {
+        let (internal_format, base_format, component_type, pixel_size) = format.gl_format();
         let mut id: GLuint = 0;
         unsafe {
             gl::GenTextures(1, &mut id);
@@ -17,34 +334,285 @@ impl Texture {
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                gl::RGBA8 as GLint,
+                internal_format as GLint,
                 width as GLint,
                 height as GLint,
                 0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                data as *const GLvoid,
+                base_format,
+                component_type,
+                std::ptr::null(),
             );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        Texture {
+            id,
+            byte_size: (width * height) as usize * pixel_size,
+            target: gl::TEXTURE_2D,
+            last_bind: Cell::new(0),
+        }
+    }
+
+    /// Creates a texture from a precomputed mip chain, base level first.
+    /// Each level must be half the size of the previous one (rounded down,
+    /// clamped to 1), the chain may stop before reaching 1x1.
+    #[allow(dead_code)]
+    pub fn from_mip_levels(levels: &[Image]) -> Result<Texture, String> {
+        if levels.is_empty() {
+            return Err("No mip levels given".to_string());
+        }
+        let (mut expected_w, mut expected_h) = levels[0].dimensions();
+        for (level, image) in levels.iter().enumerate() {
+            if image.dimensions() != (expected_w, expected_h) {
+                return Err(format!(
+                    "Mip level {} is {:?}, expected {:?}",
+                    level,
+                    image.dimensions(),
+                    (expected_w, expected_h)
+                ));
+            }
+            expected_w = (expected_w / 2).max(1);
+            expected_h = (expected_h / 2).max(1);
+        }
+
+        let mut id: GLuint = 0;
+        let mut byte_size = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            for (level, image) in levels.iter().enumerate() {
+                let (width, height) = image.dimensions();
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    level as GLint,
+                    gl::RGBA8 as GLint,
+                    width as GLint,
+                    height as GLint,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    image.as_ptr() as *const GLvoid,
+                );
+                byte_size += (width * height * 4) as usize;
+            }
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
             gl::TexParameteri(
                 gl::TEXTURE_2D,
-                gl::TEXTURE_MIN_FILTER,
-                gl::LINEAR_MIPMAP_LINEAR as GLint,
+                gl::TEXTURE_MAX_LEVEL,
+                (levels.len() - 1) as GLint,
             );
+            let min_filter = if levels.len() > 1 {
+                gl::LINEAR_MIPMAP_LINEAR
+            } else {
+                gl::LINEAR
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
-            gl::GenerateMipmap(gl::TEXTURE_2D);
             gl::BindTexture(gl::TEXTURE_2D, 0);
         }
-        Texture { id }
+        Ok(Texture {
+            id,
+            byte_size,
+            target: gl::TEXTURE_2D,
+            last_bind: Cell::new(0),
+        })
+    }
+
+    /// Uploads block compressed data as is, without decoding it. `data`
+    /// holds `mip_levels` images, base level first, each half the size of
+    /// the previous one and sized as given by `CompressedFormat::level_size`.
+    #[allow(dead_code)]
+    pub fn from_compressed(
+        data: &[u8],
+        format: CompressedFormat,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+    ) -> Result<Texture, String> {
+        let mut levels = Vec::with_capacity(mip_levels as usize);
+        let mut offset = 0;
+        for level in 0..mip_levels.max(1) {
+            let size = format.level_size((width >> level).max(1), (height >> level).max(1));
+            match data.get(offset..offset + size) {
+                Some(bytes) => levels.push(bytes),
+                None => {
+                    return Err(format!(
+                        "Compressed data too short for mip level {} ({} bytes given)",
+                        level,
+                        data.len()
+                    ))
+                }
+            }
+            offset += size;
+        }
+        Self::upload_compressed(&levels, format, width, height)
+    }
+
+    /// Uploads the mip levels of a compressed image read from a KTX file
+    #[allow(dead_code)]
+    pub fn from_compressed_image(image: &CompressedImage) -> Result<Texture, String> {
+        let format = try!(
+            CompressedFormat::from_gl(image.gl_internal_format).ok_or(format!(
+                "Unknown compressed format 0x{:X}",
+                image.gl_internal_format
+            ))
+        );
+        let levels = image.levels.iter().map(|l| &l[..]).collect::<Vec<_>>();
+        Self::upload_compressed(&levels, format, image.width, image.height)
     }
 
+    fn upload_compressed(
+        levels: &[&[u8]],
+        format: CompressedFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Texture, String> {
+        if !format.is_supported(&Capabilities::current()) {
+            return Err(format!("Compressed format {:?} is not supported", format));
+        }
+        if levels.is_empty() {
+            return Err("No mip levels given".to_string());
+        }
+        let mut id: GLuint = 0;
+        let mut byte_size = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            for (level, data) in levels.iter().enumerate() {
+                let (w, h) = ((width >> level).max(1), (height >> level).max(1));
+                let expected = format.level_size(w, h);
+                if data.len() < expected {
+                    gl::BindTexture(gl::TEXTURE_2D, 0);
+                    gl::DeleteTextures(1, &id);
+                    return Err(format!(
+                        "Mip level {} has {} bytes, expected {}",
+                        level,
+                        data.len(),
+                        expected
+                    ));
+                }
+                gl::CompressedTexImage2D(
+                    gl::TEXTURE_2D,
+                    level as GLint,
+                    format.gl_internal_format(),
+                    w as GLsizei,
+                    h as GLsizei,
+                    0,
+                    expected as GLsizei,
+                    data.as_ptr() as *const GLvoid,
+                );
+                byte_size += expected;
+            }
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAX_LEVEL,
+                (levels.len() - 1) as GLint,
+            );
+            let min_filter = if levels.len() > 1 {
+                gl::LINEAR_MIPMAP_LINEAR
+            } else {
+                gl::LINEAR
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        Ok(Texture {
+            id,
+            byte_size,
+            target: gl::TEXTURE_2D,
+            last_bind: Cell::new(0),
+        })
+    }
+
+    /// Creates a cubemap from its faces given in +X, -X, +Y, -Y, +Z, -Z
+    /// order. Faces must be square and all of the same size.
+    #[allow(dead_code)]
+    pub fn cubemap_from_images(faces: [&Image; 6]) -> Result<Texture, String> {
+        let (size, _) = faces[0].dimensions();
+        for (i, face) in faces.iter().enumerate() {
+            if face.dimensions() != (size, size) {
+                return Err(format!(
+                    "Cubemap face {} is {:?}, expected {}x{}",
+                    i,
+                    face.dimensions(),
+                    size,
+                    size
+                ));
+            }
+        }
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, id);
+            for (i, face) in faces.iter().enumerate() {
+                // Images get flipped on load for the bottom left uv origin,
+                // while cubemap faces are specified with a top left origin
+                let face = imageops::flip_vertical(*face);
+                gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum,
+                    0,
+                    gl::RGBA8 as GLint,
+                    size as GLint,
+                    size as GLint,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    face.as_ptr() as *const GLvoid,
+                );
+            }
+            let target = gl::TEXTURE_CUBE_MAP;
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        }
+        Ok(Texture {
+            id,
+            byte_size: (size * size * 4) as usize * 6,
+            target: gl::TEXTURE_CUBE_MAP,
+            last_bind: Cell::new(0),
+        })
+    }
+
+    /// Binds to the given texture unit, on the 2D or cubemap target
+    /// depending on the texture kind
     pub fn bind(&self, bindpoint: u32) {
+        self.last_bind
+            .set(BIND_CLOCK.fetch_add(1, Ordering::Relaxed) + 1);
+        stats::record_texture_bind();
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0 + bindpoint);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::BindTexture(self.target, self.id);
         }
     }
+
+    /// Underlying GL texture name
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Approximate GPU memory used by the texture, including its mip chain
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    /// Bind clock value of the last `bind` call, 0 if never bound
+    pub fn last_bind(&self) -> usize {
+        self.last_bind.get()
+    }
 }
 
 impl Drop for Texture {
@@ -0,0 +1,107 @@
+use super::texture::Texture;
+use assets::{load, Image, Load};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+struct CacheEntry {
+    // Encoded source retained so the texture can be recreated after eviction
+    source: Vec<u8>,
+    texture: Option<Texture>,
+}
+
+//
+// Path keyed texture cache with a GPU memory budget. When the resident
+// textures exceed the budget, the least recently bound ones are evicted.
+// Evicted textures keep their encoded source bytes around and get decoded
+// and uploaded again on their next request.
+//
+pub struct TextureCache {
+    budget: usize,
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+#[allow(dead_code)]
+impl TextureCache {
+    pub fn new(budget: usize) -> Self {
+        TextureCache {
+            budget,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(None);
+    }
+
+    /// Total GPU bytes of all resident textures
+    pub fn resident_bytes(&self) -> usize {
+        self.entries
+            .values()
+            .filter_map(|e| e.texture.as_ref())
+            .map(|t| t.byte_size())
+            .sum()
+    }
+
+    pub fn is_resident<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.entries.get(path.as_ref()) {
+            Some(e) => e.texture.is_some(),
+            None => false,
+        }
+    }
+
+    pub fn get<P: AsRef<Path>>(&mut self, path: P) -> Result<&Texture, String> {
+        let path = path.as_ref();
+        if !self.entries.contains_key(path) {
+            let mut source = Vec::new();
+            let mut reader = try!(load(path));
+            try!(reader.read_to_end(&mut source).map_err(|e| e.to_string()));
+            self.entries.insert(
+                path.to_path_buf(),
+                CacheEntry {
+                    source,
+                    texture: None,
+                },
+            );
+        }
+        {
+            let entry = self.entries.get_mut(path).unwrap();
+            if entry.texture.is_none() {
+                let img = try!(Image::from_buf(&entry.source[..]));
+                entry.texture = Some(Texture::from_image(&img));
+            }
+        }
+        self.evict(Some(path));
+        Ok(self.entries[path].texture.as_ref().unwrap())
+    }
+
+    /// Drops a texture along with its retained source
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) {
+        self.entries.remove(path.as_ref());
+    }
+
+    fn evict(&mut self, keep: Option<&Path>) {
+        let mut resident = self.resident_bytes();
+        while resident > self.budget {
+            let lru = self
+                .entries
+                .iter()
+                .filter(|(p, _)| Some(p.as_path()) != keep)
+                .filter_map(|(p, e)| e.texture.as_ref().map(|t| (p, t.last_bind())))
+                .min_by_key(|(_, t)| *t)
+                .map(|(p, _)| p.clone());
+            match lru {
+                Some(p) => {
+                    let entry = self.entries.get_mut(&p).unwrap();
+                    resident -= entry.texture.as_ref().unwrap().byte_size();
+                    entry.texture = None;
+                }
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use gl;
+use gl::types::*;
+use std;
+use std::marker::PhantomData;
+
+//
+// Uniform buffer holding a block of shader state shared by several programs,
+// such as per frame camera data. Programs pick it up by binding their uniform
+// block to the same binding point with `Shader::bind_uniform_block`, so a
+// single `update` reaches all of them.
+//
+// `T` is copied to the buffer byte for byte, so it has to be `#[repr(C)]`
+// and match the std140 layout of the block. In std140 vec3 and vec4 members
+// are aligned to 16 bytes, array elements and matrix columns take 16 bytes
+// each, and the block size is rounded up to 16 bytes. Sticking to vec4,
+// mat4 and explicit padding is the simplest way to get there:
+//
+//     layout(std140) uniform Frame {
+//         mat4 view;
+//         mat4 projection;
+//         vec3 camera_pos;
+//         float time;
+//     };
+//
+// maps to `FrameUniforms` below.
+//
+pub struct UniformBuffer<T> {
+    id: GLuint,
+    binding: u32,
+    _data: PhantomData<T>,
+}
+
+/// std140 counterpart of the `Frame` block shown above
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FrameUniforms {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    /// A vec3 followed by a float packs into a single 16 byte slot
+    pub camera_pos: [f32; 3],
+    pub time: f32,
+}
+
+#[allow(dead_code)]
+impl<T: Copy> UniformBuffer<T> {
+    /// Creates the buffer holding `data` and attaches it to `binding`
+    pub fn new(binding: u32, data: &T) -> Result<UniformBuffer<T>, String> {
+        let size = std::mem::size_of::<T>();
+        let mut max_bindings: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_UNIFORM_BUFFER_BINDINGS, &mut max_bindings);
+        }
+        try!(check_block(size, binding, max_bindings as u32));
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, id);
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                size as GLsizeiptr,
+                data as *const T as *const GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+        let ubo = UniformBuffer {
+            id,
+            binding,
+            _data: PhantomData,
+        };
+        ubo.bind();
+        Ok(ubo)
+    }
+
+    /// Uploads new contents, visible to every program using the binding
+    pub fn update(&self, data: &T) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                std::mem::size_of::<T>() as GLsizeiptr,
+                data as *const T as *const GLvoid,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+
+    /// Attaches the buffer to its binding point again, in case another
+    /// buffer took it over
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.id);
+        }
+    }
+
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    /// Underlying GL buffer name
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+// Checks a block of `size` bytes against std140 and `binding` against the
+// binding points the context offers
+fn check_block(size: usize, binding: u32, max_bindings: u32) -> Result<(), String> {
+    if size % 16 != 0 {
+        return Err(format!(
+            "Uniform block size {} is not a multiple of 16 as std140 requires",
+            size
+        ));
+    }
+    if binding >= max_bindings {
+        return Err(format!(
+            "Uniform buffer binding {} out of range (max {})",
+            binding, max_bindings
+        ));
+    }
+    Ok(())
+}
+
+impl<T> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_checks_size_and_binding() {
+        assert!(check_block(144, 0, 36).is_ok());
+        assert!(check_block(144, 35, 36).is_ok());
+        assert!(check_block(144, 36, 36).is_err());
+        assert!(check_block(140, 0, 36).is_err());
+    }
+
+    #[test]
+    fn frame_uniforms_match_std140() {
+        let frame = FrameUniforms {
+            view: [[0.0; 4]; 4],
+            projection: [[0.0; 4]; 4],
+            camera_pos: [0.0; 3],
+            time: 0.0,
+        };
+        let base = &frame as *const _ as usize;
+        let offset = |field: *const f32| field as usize - base;
+        assert_eq!(std::mem::size_of::<FrameUniforms>(), 144);
+        assert_eq!(offset(&frame.view[0][0]), 0);
+        assert_eq!(offset(&frame.projection[0][0]), 64);
+        assert_eq!(offset(&frame.camera_pos[0]), 128);
+        assert_eq!(offset(&frame.time), 140);
+        assert!(check_block(std::mem::size_of::<FrameUniforms>(), 0, 36).is_ok());
+    }
+}
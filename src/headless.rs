@@ -0,0 +1,84 @@
+use assets::Image;
+use gl;
+use glutin::{Api, GlContext, GlRequest, HeadlessContext, HeadlessRendererBuilder};
+use graphics::capabilities::Capabilities;
+use graphics::framebuffer::Framebuffer;
+use graphics::readback;
+use graphics::TextureFormat;
+
+//
+// Offscreen rendering without a window, for automated rendering tests and
+// screenshot generation. Frames are drawn into a framebuffer of a fixed
+// size and read back into images.
+//
+#[allow(dead_code)]
+pub struct Headless {
+    // Only kept alive, the context stays current on this thread
+    context: HeadlessContext,
+    framebuffer: Framebuffer,
+    width: u32,
+    height: u32,
+}
+
+#[allow(dead_code)]
+impl Headless {
+    /// Creates a context not tied to any window and makes it current, along
+    /// with a `width` x `height` render target
+    pub fn new(width: u32, height: u32) -> Result<Headless, String> {
+        let context = try!(
+            HeadlessRendererBuilder::new(width, height)
+                .with_gl(GlRequest::Specific(Api::OpenGlEs, (3, 0)))
+                .build()
+                .map_err(|e| e.to_string())
+        );
+        unsafe {
+            try!(context.make_current().map_err(|e| e.to_string()));
+        }
+        gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+        Capabilities::query();
+        let framebuffer = try!(Framebuffer::new(width, height, &[TextureFormat::Rgba8], true));
+        Ok(Headless {
+            context,
+            framebuffer,
+            width,
+            height,
+        })
+    }
+
+    /// Redirects rendering to the offscreen target, call before drawing
+    pub fn begin_frame(&self) {
+        self.framebuffer.bind();
+    }
+
+    /// Reads back what got drawn since `begin_frame`. The rows keep GL's
+    /// bottom-left origin
+    pub fn capture_frame(&self) -> Image {
+        self.framebuffer.bind();
+        let frame = readback::read_color(0, 0, self.width, self.height);
+        self.framebuffer.unbind();
+        frame
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore] // Needs a GL ES 3.0 headless driver (OsMesa on Linux)
+    fn clear_color_is_captured() {
+        let headless = Headless::new(4, 2).unwrap();
+        headless.begin_frame();
+        unsafe {
+            gl::ClearColor(1.0, 0.0, 1.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        let frame = headless.capture_frame();
+        assert_eq!(frame.dimensions(), (4, 2));
+        assert!(frame.pixels().all(|p| p.data == [255, 0, 255, 255]));
+    }
+}
@@ -0,0 +1,203 @@
+use glutin::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+use std::collections::HashSet;
+
+const PIXELS_PER_LINE: f32 = 20.0;
+
+//
+// Keyboard and mouse state accumulated from window events. Call `begin_frame` before
+// feeding the events of a new frame so that the pressed/released edges only
+// describe what happened since the previous frame.
+//
+pub struct InputState {
+    held: HashSet<VirtualKeyCode>,
+    pressed: HashSet<VirtualKeyCode>,
+    released: HashSet<VirtualKeyCode>,
+    modifiers: ModifiersState,
+    focused: bool,
+    scroll: f32,
+    scroll_pixels: f32,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    // None until the cursor enters the window
+    cursor: Option<(f64, f64)>,
+    cursor_delta: (f64, f64),
+    hidpi_factor: f64,
+}
+
+#[allow(dead_code)]
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            modifiers: ModifiersState::default(),
+            focused: true,
+            scroll: 0.0,
+            scroll_pixels: 0.0,
+            buttons_held: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            cursor: None,
+            cursor_delta: (0.0, 0.0),
+            hidpi_factor: 1.0,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.scroll = 0.0;
+        self.scroll_pixels = 0.0;
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.cursor_delta = (0.0, 0.0);
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => self.handle_keyboard(input),
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (lines, pixels) = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => (*y, *y * PIXELS_PER_LINE),
+                    // Touchpads report pixels, roughly map them to lines
+                    MouseScrollDelta::PixelDelta(pos) => {
+                        (pos.y as f32 / PIXELS_PER_LINE, pos.y as f32)
+                    }
+                };
+                self.scroll += lines;
+                self.scroll_pixels += pixels;
+            }
+            WindowEvent::HiDpiFactorChanged(factor) => self.hidpi_factor = *factor,
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    if self.buttons_held.insert(*button) {
+                        self.buttons_pressed.insert(*button);
+                    }
+                }
+                ElementState::Released => {
+                    self.buttons_held.remove(button);
+                    self.buttons_released.insert(*button);
+                }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor {
+                    self.cursor_delta.0 += position.x - x;
+                    self.cursor_delta.1 += position.y - y;
+                }
+                self.cursor = Some((position.x, position.y));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                // Don't report the jump to wherever the cursor comes back in
+                self.cursor = None;
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                if !focused {
+                    // Releases happening elsewhere are never delivered to us
+                    self.held.clear();
+                    self.buttons_held.clear();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_keyboard(&mut self, input: &KeyboardInput) {
+        self.modifiers = input.modifiers;
+        if let Some(key) = input.virtual_keycode {
+            match input.state {
+                ElementState::Pressed => {
+                    // OS auto-repeat keeps sending presses while the key
+                    // is held, only the first one counts as an edge
+                    if self.held.insert(key) {
+                        self.pressed.insert(key);
+                    }
+                }
+                ElementState::Released => {
+                    self.held.remove(&key);
+                    self.released.insert(key);
+                }
+            }
+        }
+    }
+
+    /// True while the key is held down
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// True only on the frame the key went down, ignoring auto-repeat
+    pub fn was_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// True only on the frame the key went up
+    pub fn was_released(&self, key: VirtualKeyCode) -> bool {
+        self.released.contains(&key)
+    }
+
+    /// Whether the window currently has keyboard focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Vertical wheel movement in lines since the last `begin_frame`,
+    /// positive when scrolling away from the user
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll
+    }
+
+    /// True while the mouse button is held down
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    /// True only on the frame the mouse button went down
+    pub fn was_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// True only on the frame the mouse button went up
+    pub fn was_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Cursor position in logical pixels from the top left corner of the
+    /// window, None while it is outside
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        self.cursor
+    }
+
+    /// Cursor movement in logical pixels since the last `begin_frame`
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    /// Vertical wheel movement in logical pixels since the last
+    /// `begin_frame`, for smooth scrolling
+    pub fn scroll_pixel_delta(&self) -> f32 {
+        self.scroll_pixels
+    }
+
+    /// Factor between logical and physical pixels, kept up to date from
+    /// window events after the initial value is set
+    pub fn set_hidpi_factor(&mut self, factor: f64) {
+        self.hidpi_factor = factor;
+    }
+
+    /// Cursor position in framebuffer pixels, None while it is outside
+    pub fn cursor_physical_position(&self) -> Option<(f64, f64)> {
+        let f = self.hidpi_factor;
+        self.cursor.map(|(x, y)| (x * f, y * f))
+    }
+
+    /// Shift/Ctrl/Alt/Logo state as of the last keyboard event
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+}
@@ -1,9 +1,15 @@
 #[cfg(target_os = "android")]
 extern crate android_glue;
+extern crate base64;
 extern crate gl;
+extern crate gltf;
 extern crate glutin;
 pub extern crate image;
+#[macro_use]
+extern crate lazy_static;
 extern crate nalgebra_glm;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 extern crate rusttype;
 extern crate time;
 extern crate tobj;
@@ -11,20 +17,46 @@ extern crate tobj;
 mod assets;
 mod game;
 mod graphics;
+mod headless;
+mod input;
 mod mainloop;
 mod math;
+mod scene;
 
 use game::Game;
 use mainloop::MainLoop;
 
+const UNFOCUSED_FPS: u32 = 10;
+
 fn main() {
     println!("Hello, world!");
-    let mut game = Game::new();
+    let mut game = match Game::new() {
+        Ok(game) => game,
+        Err(e) => {
+            println!("Could not start: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // Keep polling for focus, slowly, while paused in the background
+    let unfocused_frame_cap = if game.pauses_when_unfocused() {
+        Some(UNFOCUSED_FPS)
+    } else {
+        None
+    };
     let mut mainloop = MainLoop::new(
         Box::new(|mut game, dt| Game::update(&mut game, dt)),
-        Box::new(|game, interpolation| Game::render(&game, interpolation)),
+        Box::new(|game, interpolation| match Game::render(&game, interpolation) {
+            Ok(()) => false,
+            Err(e) => {
+                println!("{}", e);
+                true
+            }
+        }),
         Some(Box::new(|mut game, t, u, r| Game::perf(&mut game, t, u, r))),
         &mut game,
-    );
+    ).with_event_source(Box::new(|game| game.drain_events()))
+    .on_resize(Box::new(|game, w, h| game.resize(w, h)))
+    .with_unfocused_frame_cap(unfocused_frame_cap)
+    .with_gpu_timing();
     mainloop.run();
 }
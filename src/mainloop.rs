@@ -1,5 +1,13 @@
+use graphics::gpu_timer::GpuTimer;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 use time::PreciseTime;
 
+// Shortest perf averaging window (in seconds)
+const MIN_PERF_REFRESH_RATE: f32 = 0.001;
+
 fn timeit<T, F: FnOnce() -> T>(f: F) -> (f32, T) {
     let start = PreciseTime::now();
     let r = f();
@@ -7,12 +15,91 @@ fn timeit<T, F: FnOnce() -> T>(f: F) -> (f32, T) {
     (elapsed, r)
 }
 
+/// How the update callback is stepped
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timestep {
+    /// Fixed number of updates per second of elapsed time, each one
+    /// receiving the same dt, decoupled from the render rate
+    Fixed(u32),
+    /// A single update per frame receiving the real elapsed seconds
+    Variable,
+}
+
+//
+// Shared handle for pausing and single stepping the simulation. Clones refer
+// to the same state, so the handle can be captured by the callbacks or kept
+// in the userdata while the loop owns another one.
+//
+#[derive(Clone, Default)]
+pub struct LoopControl {
+    paused: Rc<Cell<bool>>,
+    pending_steps: Rc<Cell<u32>>,
+}
+
+#[allow(dead_code)]
+impl LoopControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops calling update, rendering keeps going with an interpolation
+    /// of 0. Userdata that polls window events in its update callback must
+    /// keep doing so elsewhere while paused (e.g. in the event source).
+    pub fn pause(&self) {
+        self.paused.set(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.set(false);
+        self.pending_steps.set(0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    /// Runs exactly one update on the next frame while paused
+    pub fn step(&self) {
+        if self.is_paused() {
+            self.pending_steps.set(self.pending_steps.get() + 1);
+        }
+    }
+
+    fn take_step(&self) -> bool {
+        let steps = self.pending_steps.get();
+        if steps > 0 {
+            self.pending_steps.set(steps - 1);
+        }
+        steps > 0
+    }
+}
+
+/// Window events the loop can dispatch to its hooks
+#[derive(Clone, Copy, Debug)]
+pub enum LoopEvent {
+    /// New framebuffer size in physical pixels
+    Resized(u32, u32),
+    Focused(bool),
+}
+
 pub struct MainLoop<'a, T: 'a> {
     update_cb: Box<Fn(&mut T, f32) -> bool>,
-    render_cb: Box<Fn(&T, f32)>,
+    render_cb: Box<Fn(&T, f32) -> bool>,
     perf_cb: Option<Box<Fn(&mut T, f32, f32, f32)>>,
+    event_source_cb: Option<Box<Fn(&mut T) -> Vec<LoopEvent>>>,
+    resize_cb: Option<Box<Fn(&mut T, u32, u32)>>,
+    focus_cb: Option<Box<Fn(&mut T, bool)>>,
     should_terminate: bool,
-    updates_per_second: u32,
+    timestep: Timestep,
+    max_frame_time: f32,
+    max_updates_per_frame: u32,
+    frame_cap: Option<u32>,
+    unfocused_frame_cap: Option<u32>,
+    // Last focus state reported by the event source
+    focused: bool,
+    gpu_timer: Option<GpuTimer>,
+    control: LoopControl,
     perf_refr_rate: f32,
     perf_samples_acc: [f32; 3], // Total, Update, Render
     perf_samples_cnt: u32,
@@ -20,9 +107,10 @@ pub struct MainLoop<'a, T: 'a> {
 }
 
 impl<'a, T> MainLoop<'a, T> {
+    /// Both `update_cb` and `render_cb` return true to end the loop
     pub fn new(
         update_cb: Box<Fn(&mut T, f32) -> bool>,
-        render_cb: Box<Fn(&T, f32)>,
+        render_cb: Box<Fn(&T, f32) -> bool>,
         perf_cb: Option<Box<Fn(&mut T, f32, f32, f32)>>,
         userdata: &'a mut T,
     ) -> MainLoop<'a, T> {
@@ -30,8 +118,18 @@ impl<'a, T> MainLoop<'a, T> {
             update_cb: update_cb,
             render_cb: render_cb,
             perf_cb: perf_cb,
+            event_source_cb: None,
+            resize_cb: None,
+            focus_cb: None,
             should_terminate: false,
-            updates_per_second: 60,
+            timestep: Timestep::Fixed(60),
+            max_frame_time: 250.0,
+            max_updates_per_frame: 5,
+            frame_cap: None,
+            unfocused_frame_cap: None,
+            focused: true,
+            gpu_timer: None,
+            control: LoopControl::new(),
             perf_refr_rate: 0.5,
             perf_samples_acc: [0.0; 3],
             perf_samples_cnt: 0,
@@ -39,24 +137,203 @@ impl<'a, T> MainLoop<'a, T> {
         }
     }
 
-    pub fn run(&mut self) {
-        let ms_per_update: f32 = 1000.0 / (self.updates_per_second as f32);
+    #[allow(dead_code)]
+    pub fn with_timestep(mut self, timestep: Timestep) -> Self {
+        self.timestep = timestep;
+        self
+    }
 
-        let mut lag = 0.0;
-        let mut elapsed = 0.0;
-        while !self.should_terminate {
-            elapsed = timeit(|| {
+    /// Shares an existing control handle, e.g. one captured by the callbacks
+    #[allow(dead_code)]
+    pub fn with_control(mut self, control: LoopControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    /// Handle to the loop's pause state
+    #[allow(dead_code)]
+    pub fn control(&self) -> LoopControl {
+        self.control.clone()
+    }
+
+    #[allow(dead_code)]
+    pub fn pause(&self) {
+        self.control.pause();
+    }
+
+    #[allow(dead_code)]
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    #[allow(dead_code)]
+    pub fn step(&self) {
+        self.control.step();
+    }
+
+    /// Longest frame duration (in msec) accounted for, longer frames (window
+    /// drags, breakpoints) are treated as if they took this long
+    #[allow(dead_code)]
+    pub fn set_max_frame_time(&mut self, max_frame_time: f32) {
+        self.max_frame_time = max_frame_time;
+    }
+
+    /// Switches to a fixed timestep of `updates_per_second` updates, at
+    /// least one, starting with the next frame
+    #[allow(dead_code)]
+    pub fn set_updates_per_second(&mut self, updates_per_second: u32) {
+        self.timestep = Timestep::Fixed(updates_per_second.max(1));
+    }
+
+    /// Seconds over which perf samples are averaged before each perf
+    /// callback invocation
+    #[allow(dead_code)]
+    pub fn set_perf_refresh_rate(&mut self, secs: f32) {
+        self.perf_refr_rate = secs.max(MIN_PERF_REFRESH_RATE);
+    }
+
+    /// Caps the number of fixed updates run to catch up in a single frame
+    #[allow(dead_code)]
+    pub fn set_max_updates_per_frame(&mut self, max_updates_per_frame: u32) {
+        self.max_updates_per_frame = max_updates_per_frame.max(1);
+    }
+
+    /// Limits the frame rate by sleeping away the rest of each frame,
+    /// None runs as fast as the swap interval allows
+    #[allow(dead_code)]
+    pub fn with_frame_cap(mut self, max_fps: Option<u32>) -> Self {
+        self.frame_cap = max_fps;
+        self
+    }
+
+    /// Frame rate limit applied instead of the frame cap while the event
+    /// source reports the window as unfocused, to save power in the
+    /// background
+    #[allow(dead_code)]
+    pub fn with_unfocused_frame_cap(mut self, max_fps: Option<u32>) -> Self {
+        self.unfocused_frame_cap = max_fps;
+        self
+    }
+
+    /// Reports the GPU time of the render callback to the perf callback
+    /// instead of its CPU time, where timer queries are available. Needs
+    /// the GL context to be current
+    pub fn with_gpu_timing(mut self) -> Self {
+        self.gpu_timer = GpuTimer::new();
+        self
+    }
+
+    /// Sets the callback draining the window events gathered by the
+    /// userdata (typically while polling its event loop in update)
+    pub fn with_event_source(mut self, event_source_cb: Box<Fn(&mut T) -> Vec<LoopEvent>>) -> Self {
+        self.event_source_cb = Some(event_source_cb);
+        self
+    }
+
+    pub fn on_resize(mut self, resize_cb: Box<Fn(&mut T, u32, u32)>) -> Self {
+        self.resize_cb = Some(resize_cb);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn on_focus(mut self, focus_cb: Box<Fn(&mut T, bool)>) -> Self {
+        self.focus_cb = Some(focus_cb);
+        self
+    }
+
+    fn dispatch_events(&mut self) {
+        let events = match &self.event_source_cb {
+            Some(event_source_cb) => event_source_cb(&mut self.userdata),
+            None => return,
+        };
+        for ev in events {
+            match ev {
+                LoopEvent::Resized(w, h) => {
+                    if let Some(resize_cb) = &self.resize_cb {
+                        resize_cb(&mut self.userdata, w, h);
+                    }
+                }
+                LoopEvent::Focused(focused) => {
+                    self.focused = focused;
+                    if let Some(focus_cb) = &self.focus_cb {
+                        focus_cb(&mut self.userdata, focused);
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs the updates due this frame given the time elapsed since the last
+    // one, returning the time spent updating and the render interpolation
+    fn advance(&mut self, elapsed: f32, lag: &mut f32) -> (f32, f32) {
+        let elapsed = elapsed.min(self.max_frame_time);
+        if self.control.is_paused() {
+            // The accumulator is frozen, only explicit steps get through
+            *lag = 0.0;
+            if !self.control.take_step() {
+                return (0.0, 0.0);
+            }
+            let dt = match self.timestep {
+                Timestep::Fixed(updates_per_second) => 1.0 / updates_per_second as f32,
+                Timestep::Variable => elapsed / 1000.0,
+            };
+            let rt = timeit(|| (self.update_cb)(&mut self.userdata, dt));
+            self.should_terminate = rt.1;
+            return (rt.0, 0.0);
+        }
+        match self.timestep {
+            Timestep::Fixed(updates_per_second) => {
+                let ms_per_update: f32 = 1000.0 / (updates_per_second as f32);
+                // Spiral of death clamp: when updates take longer than the
+                // time they simulate the lag would grow without bounds, so
+                // at most max_updates_per_frame get run and the remaining
+                // time is dropped, slowing the simulation down instead
+                let max_lag = ms_per_update * self.max_updates_per_frame as f32;
+                *lag = (*lag + elapsed).min(max_lag);
                 let mut update_time = 0.0;
-                while lag > ms_per_update {
+                while *lag >= ms_per_update && !self.should_terminate {
                     let dt = ms_per_update / 1000.0;
                     let rt = timeit(|| (self.update_cb)(&mut self.userdata, dt));
-                    update_time = rt.0;
+                    update_time += rt.0;
                     self.should_terminate = rt.1;
-                    lag -= ms_per_update;
+                    *lag -= ms_per_update;
                 }
+                (update_time, *lag / ms_per_update)
+            }
+            Timestep::Variable => {
+                let dt = elapsed / 1000.0;
+                let rt = timeit(|| (self.update_cb)(&mut self.userdata, dt));
+                self.should_terminate = rt.1;
+                // State is always up to date with the frame
+                (rt.0, 0.0)
+            }
+        }
+    }
 
-                let interpolation = lag / ms_per_update;
-                let (render_time, _) = timeit(|| (self.render_cb)(&self.userdata, interpolation));
+    pub fn run(&mut self) {
+        let mut lag = 0.0;
+        let mut elapsed = 0.0;
+        loop {
+            elapsed = timeit(|| {
+                let (update_time, interpolation) = self.advance(elapsed, &mut lag);
+                self.dispatch_events();
+
+                if let Some(timer) = self.gpu_timer.as_mut() {
+                    timer.begin();
+                }
+                let (mut render_time, render_failed) =
+                    timeit(|| (self.render_cb)(&self.userdata, interpolation));
+                self.should_terminate |= render_failed;
+                if let Some(timer) = self.gpu_timer.as_mut() {
+                    timer.end();
+                    // Until the first query resolves, CPU time is all we have
+                    render_time = timer.last_elapsed().unwrap_or(render_time);
+                }
 
                 if let Some(perf_cb) = &self.perf_cb {
                     self.perf_samples_acc
@@ -76,7 +353,101 @@ impl<'a, T> MainLoop<'a, T> {
                     }
                 }
             }).0;
-            lag += elapsed;
+            // Set by the callbacks above
+            if self.should_terminate {
+                break;
+            }
+            elapsed += self.wait_for_frame_cap(elapsed);
+        }
+    }
+
+    // Sleeps the remainder of a frame that took `frame_time` msec when
+    // a frame cap is set, returning the time actually slept
+    fn wait_for_frame_cap(&self, frame_time: f32) -> f32 {
+        let frame_cap = if self.focused {
+            self.frame_cap
+        } else {
+            self.unfocused_frame_cap.or(self.frame_cap)
+        };
+        let max_fps = match frame_cap {
+            Some(max_fps) if max_fps > 0 => max_fps,
+            _ => return 0.0,
+        };
+        let remaining = 1000.0 / max_fps as f32 - frame_time;
+        if remaining <= 0.0 {
+            return 0.0;
+        }
+        timeit(|| thread::sleep(Duration::from_micros((remaining * 1000.0) as u64))).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loop over an update counter, rendering nothing
+    fn counting_loop<'a>(updates: &'a mut u32) -> MainLoop<'a, u32> {
+        MainLoop::new(
+            Box::new(|n, _dt| {
+                *n += 1;
+                false
+            }),
+            Box::new(|_, _| false),
+            None,
+            updates,
+        )
+    }
+
+    #[test]
+    fn huge_elapsed_runs_at_most_max_updates() {
+        let mut updates = 0;
+        {
+            let mut mainloop = counting_loop(&mut updates);
+            mainloop.set_max_frame_time(std::f32::MAX);
+            mainloop.set_max_updates_per_frame(3);
+            let mut lag = 0.0;
+            mainloop.advance(1.0e9, &mut lag);
+        }
+        assert_eq!(updates, 3);
+    }
+
+    #[test]
+    fn elapsed_time_is_consumed_in_fixed_steps() {
+        let mut updates = 0;
+        {
+            let mut mainloop = counting_loop(&mut updates).with_timestep(Timestep::Fixed(100));
+            let mut lag = 0.0;
+            // 3.5 steps worth of msec, the half step is carried over
+            let (_, interpolation) = mainloop.advance(35.0, &mut lag);
+            assert!((interpolation - 0.5).abs() < 1e-4);
+        }
+        assert_eq!(updates, 3);
+    }
+
+    #[test]
+    fn thirty_updates_per_second_cadence() {
+        let mut dts: Vec<f32> = Vec::new();
+        let mut lag = 0.0;
+        {
+            let mut mainloop = MainLoop::new(
+                Box::new(|dts: &mut Vec<f32>, dt| {
+                    dts.push(dt);
+                    false
+                }),
+                Box::new(|_, _| false),
+                None,
+                &mut dts,
+            );
+            mainloop.set_updates_per_second(30);
+            // 110 msec in 10 msec frames
+            for _ in 0..11 {
+                mainloop.advance(10.0, &mut lag);
+            }
+        }
+        assert_eq!(dts.len(), 3);
+        for dt in &dts {
+            assert!((dt - 1.0 / 30.0).abs() < 1e-6);
         }
+        assert!((lag - (110.0 - 3.0 * 1000.0 / 30.0)).abs() < 1e-3);
     }
 }
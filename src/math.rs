@@ -1 +0,0 @@
-pub use nalgebra_glm::*;
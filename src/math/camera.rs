@@ -0,0 +1,105 @@
+use super::*;
+
+const MIN_FOV: f32 = 10.0;
+const MAX_FOV: f32 = 90.0;
+// Rate (per second) at which the fov eases towards its target
+const FOV_SMOOTHING: f32 = 12.0;
+// Keeps orbiting cameras off the poles, where the view direction meets `up`
+const MAX_ORBIT_PITCH: f32 = 1.55;
+
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub near: f32,
+    pub far: f32,
+    pub reversed_z: bool,
+    /// Vertical field of view in degrees, eased towards `target_fov`
+    fov: f32,
+    target_fov: f32,
+}
+
+#[allow(dead_code)]
+impl Camera {
+    pub fn new(eye: Vec3, target: Vec3) -> Self {
+        Camera {
+            eye,
+            target,
+            up: vec3(0.0, 1.0, 0.0),
+            near: 0.1,
+            far: 100.0,
+            reversed_z: false,
+            fov: 60.0,
+            target_fov: 60.0,
+        }
+    }
+
+    pub fn with_reversed_z(mut self, reversed_z: bool) -> Self {
+        self.reversed_z = reversed_z;
+        self
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Sets the vertical field of view in degrees immediately
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov.max(MIN_FOV).min(MAX_FOV);
+        self.target_fov = self.fov;
+    }
+
+    /// Narrows (positive delta) or widens the field of view by `delta`
+    /// degrees, the change is eased in over the next updates
+    pub fn zoom(&mut self, delta: f32) {
+        self.target_fov = (self.target_fov - delta).max(MIN_FOV).min(MAX_FOV);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let t = (FOV_SMOOTHING * dt).min(1.0);
+        self.fov += (self.target_fov - self.fov) * t;
+    }
+
+    /// Places the eye `distance` away from the target, `yaw` radians around
+    /// the Y axis (from +Z towards +X) and `pitch` radians above the XZ plane
+    pub fn orbit(&mut self, yaw: f32, pitch: f32, distance: f32) {
+        let pitch = pitch.max(-MAX_ORBIT_PITCH).min(MAX_ORBIT_PITCH);
+        let dir = vec3(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        self.eye = self.target + dir * distance;
+    }
+
+    /// Current (yaw, pitch, distance) of the eye around the target,
+    /// see `orbit`
+    pub fn orbit_angles(&self) -> (f32, f32, f32) {
+        let d = self.eye - self.target;
+        let distance = length(&d);
+        if distance == 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let pitch = (d.y / distance).max(-1.0).min(1.0).asin();
+        (d.x.atan2(d.z), pitch, distance)
+    }
+
+    /// Rotates the eye around the target keeping its distance
+    pub fn orbit_by(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        let (yaw, pitch, distance) = self.orbit_angles();
+        self.orbit(yaw + delta_yaw, pitch + delta_pitch, distance);
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        look_at(&self.eye, &self.target, &self.up)
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        let fovy = self.fov.to_radians();
+        if self.reversed_z {
+            perspective_reversed_z(aspect, fovy, self.near, self.far)
+        } else {
+            perspective(aspect, fovy, self.near, self.far)
+        }
+    }
+
+    pub fn view_projection(&self, aspect: f32) -> Mat4 {
+        self.projection_matrix(aspect) * self.view_matrix()
+    }
+}
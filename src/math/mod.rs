@@ -0,0 +1,234 @@
+pub mod camera;
+
+pub use self::camera::*;
+pub use nalgebra_glm::*;
+use std;
+
+fn srgb_channel_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_channel_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear interpolation between `a` and `b`, `t` is not clamped. See
+/// `slerp` and `quat_slerp` for rotations
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Decodes an sRGB encoded color (as authored in most tools) to linear space
+pub fn srgb_to_linear(c: [f32; 3]) -> [f32; 3] {
+    [
+        srgb_channel_to_linear(c[0]),
+        srgb_channel_to_linear(c[1]),
+        srgb_channel_to_linear(c[2]),
+    ]
+}
+
+/// Encodes a linear color to sRGB
+#[allow(dead_code)]
+pub fn linear_to_srgb(c: [f32; 3]) -> [f32; 3] {
+    [
+        linear_channel_to_srgb(c[0]),
+        linear_channel_to_srgb(c[1]),
+        linear_channel_to_srgb(c[2]),
+    ]
+}
+
+/// Right handed perspective projection with reversed depth, mapping the near
+/// plane to depth 1 and the far plane to depth 0. Combined with a [0, 1] clip
+/// space depth range (glClipControl), a GREATER depth test and a depth clear
+/// value of 0, this spreads floating point depth precision evenly over the
+/// view distance instead of bunching it up near the camera.
+pub fn perspective_reversed_z(aspect: f32, fovy: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fovy / 2.0).tan();
+    let mut m = Mat4::zeros();
+    m[(0, 0)] = f / aspect;
+    m[(1, 1)] = f;
+    m[(2, 2)] = near / (far - near);
+    m[(2, 3)] = far * near / (far - near);
+    m[(3, 2)] = -1.0;
+    m
+}
+
+/// Orthographic projection mapping pixel coordinates with a top-left origin
+/// over a `width` x `height` area to NDC, for 2D drawing. Built by hand since
+/// glm's `ortho` refuses a flipped (top < bottom) y range.
+pub fn screen_ortho(width: f32, height: f32) -> Mat4 {
+    let mut m = Mat4::identity();
+    m[(0, 0)] = 2.0 / width;
+    m[(1, 1)] = -2.0 / height;
+    m[(0, 3)] = -1.0;
+    m[(1, 3)] = 1.0;
+    m
+}
+
+/// Maps a window position (pixels, top-left origin) and its depth buffer
+/// value back to world space, using the inverse of `view_proj`.
+/// Assumes the default [0, 1] depth range mapped from [-1, 1] NDC.
+#[allow(dead_code)]
+pub fn unproject_screen(
+    x: f32,
+    y: f32,
+    depth: f32,
+    view_proj: &Mat4,
+    viewport_size: (f32, f32),
+) -> Vec3 {
+    let ndc = vec4(
+        2.0 * x / viewport_size.0 - 1.0,
+        1.0 - 2.0 * y / viewport_size.1,
+        2.0 * depth - 1.0,
+        1.0,
+    );
+    let p = inverse(view_proj) * ndc;
+    vec3(p.x / p.w, p.y / p.w, p.z / p.w)
+}
+
+/// Rotation of `angle` radians around `axis`, which needs not be normalized
+#[allow(dead_code)]
+pub fn quat_from_axis_angle(axis: &Vec3, angle: f32) -> Quat {
+    quat_angle_axis(angle, axis)
+}
+
+/// Rotation from Euler angles in radians: `roll` around Z is applied first,
+/// then `pitch` around X and finally `yaw` around Y
+#[allow(dead_code)]
+pub fn quat_from_euler(pitch: f32, yaw: f32, roll: f32) -> Quat {
+    let qx = quat_angle_axis(pitch, &vec3(1.0, 0.0, 0.0));
+    let qy = quat_angle_axis(yaw, &vec3(0.0, 1.0, 0.0));
+    let qz = quat_angle_axis(roll, &vec3(0.0, 0.0, 1.0));
+    qy * qx * qz
+}
+
+/// Rotation applying `first` and then `second`
+#[allow(dead_code)]
+pub fn quat_compose(first: &Quat, second: &Quat) -> Quat {
+    quat_normalize(&(second * first))
+}
+
+/// Spherical interpolation between two orientations along the shorter arc.
+/// Unlike `quat_slerp` it doesn't fail on orientations 180 degrees apart
+#[allow(dead_code)]
+pub fn quat_slerp_shortest(from: &Quat, to: &Quat, t: f32) -> Quat {
+    // q and -q are the same orientation, pick the one closer to `from`
+    let to = if quat_dot(from, to) < 0.0 { -to } else { *to };
+    quat_slerp(from, &to, t)
+}
+
+/// Homogeneous rotation matrix of a unit quaternion
+#[allow(dead_code)]
+pub fn quat_to_rotation_matrix(q: &Quat) -> Mat4 {
+    quat_to_mat4(&quat_normalize(q))
+}
+
+/// Axis aligned bounding box
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+#[allow(dead_code)]
+impl Aabb {
+    /// Box containing nothing, growing it with any point yields that point
+    pub fn empty() -> Aabb {
+        let inf = std::f32::INFINITY;
+        Aabb {
+            min: vec3(inf, inf, inf),
+            max: vec3(-inf, -inf, -inf),
+        }
+    }
+
+    /// Bounds of packed (x, y, z) positions
+    pub fn from_positions(positions: &[f32]) -> Aabb {
+        positions.chunks(3).fold(Aabb::empty(), |aabb, p| {
+            aabb.extended(&vec3(p[0], p[1], p[2]))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn extended(&self, p: &Vec3) -> Aabb {
+        Aabb {
+            min: vec3(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z)),
+            max: vec3(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z)),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let (a, b) = (self, other);
+        Aabb {
+            min: vec3(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+            max: vec3(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z)),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!(distance(a, b) < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn yaw_turns_x_onto_negative_z() {
+        let q = quat_from_euler(0.0, 90.0_f32.to_radians(), 0.0);
+        let v = quat_rotate_vec3(&q, &vec3(1.0, 0.0, 0.0));
+        assert_near(&v, &vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn quat_matrix_matches_rotate() {
+        let axis = normalize(&vec3(1.0, 2.0, 3.0));
+        let angle = 0.7;
+        let from_quat = quat_to_rotation_matrix(&quat_from_axis_angle(&axis, angle));
+        let expected = rotate(&Mat4::identity(), angle, &axis);
+        for i in 0..16 {
+            assert!((from_quat[i] - expected[i]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn compose_applies_first_then_second() {
+        let yaw = quat_from_axis_angle(&vec3(0.0, 1.0, 0.0), 90.0_f32.to_radians());
+        let roll = quat_from_axis_angle(&vec3(0.0, 0.0, 1.0), 90.0_f32.to_radians());
+        // +X yaws onto -Z, which rolling leaves in place
+        let v = quat_rotate_vec3(&quat_compose(&yaw, &roll), &vec3(1.0, 0.0, 0.0));
+        assert_near(&v, &vec3(0.0, 0.0, -1.0));
+        // +X rolls onto +Y, which yawing leaves in place
+        let v = quat_rotate_vec3(&quat_compose(&roll, &yaw), &vec3(1.0, 0.0, 0.0));
+        assert_near(&v, &vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn screen_ortho_maps_corners() {
+        let m = screen_ortho(800.0, 600.0);
+        let top_left = m * vec4(0.0, 0.0, 0.0, 1.0);
+        let bottom_right = m * vec4(800.0, 600.0, 0.0, 1.0);
+        assert_near(&top_left.xyz(), &vec3(-1.0, 1.0, 0.0));
+        assert_near(&bottom_right.xyz(), &vec3(1.0, -1.0, 0.0));
+    }
+}
@@ -0,0 +1,219 @@
+use math::*;
+
+//
+// Node hierarchy. Nodes live in a flat list and refer to each other by
+// index, parents always coming before their children, so that world
+// matrices can be computed in a single pass down the list.
+//
+
+/// Translation, rotation and scale of a node relative to its parent
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: vec3(0.0, 0.0, 0.0),
+            rotation: quat_identity(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub fn new() -> Self {
+        Transform::default()
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Scales first, then rotates and finally translates
+    pub fn local_matrix(&self) -> Mat4 {
+        let t = translation(&self.translation);
+        let r = quat_to_rotation_matrix(&self.rotation);
+        let s = scaling(&self.scale);
+        t * r * s
+    }
+}
+
+/// Index of a node in its scene
+pub type NodeId = usize;
+
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+#[allow(dead_code)]
+impl Node {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+pub struct Scene {
+    nodes: Vec<Node>,
+}
+
+#[allow(dead_code)]
+impl Scene {
+    pub fn new() -> Self {
+        Scene { nodes: Vec::new() }
+    }
+
+    /// Adds a node without a parent
+    pub fn add_root(&mut self, name: &str, transform: Transform) -> NodeId {
+        self.push(name, transform, None)
+    }
+
+    /// Adds a node whose transform is relative to `parent`
+    pub fn add_child(&mut self, parent: NodeId, name: &str, transform: Transform) -> NodeId {
+        let id = self.push(name, transform, Some(parent));
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    fn push(&mut self, name: &str, transform: Transform, parent: Option<NodeId>) -> NodeId {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            transform,
+            parent,
+            children: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|n| n.name == name)
+    }
+
+    pub fn roots<'a>(&'a self) -> impl Iterator<Item = NodeId> + 'a {
+        (0..self.nodes.len()).filter(move |&id| self.nodes[id].parent.is_none())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// World matrix of every node, indexed by node id
+    pub fn world_matrices(&self) -> Vec<Mat4> {
+        let mut world: Vec<Mat4> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let local = node.transform.local_matrix();
+            let matrix = match node.parent {
+                Some(parent) => world[parent] * local,
+                None => local,
+            };
+            world.push(matrix);
+        }
+        world
+    }
+
+    /// World matrix of a single node, walking up its parents
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let mut matrix = self.nodes[id].transform.local_matrix();
+        let mut parent = self.nodes[id].parent;
+        while let Some(p) = parent {
+            matrix = self.nodes[p].transform.local_matrix() * matrix;
+            parent = self.nodes[p].parent;
+        }
+        matrix
+    }
+
+    /// Every node along with its world matrix, parents before children
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node, Mat4)> {
+        self.nodes
+            .iter()
+            .zip(self.world_matrices())
+            .enumerate()
+            .map(|(id, (node, world))| (id, node, world))
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!(distance(a, b) < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn origin_of(m: &Mat4) -> Vec3 {
+        vec3(m[(0, 3)], m[(1, 3)], m[(2, 3)])
+    }
+
+    #[test]
+    fn child_world_matrix_is_parent_times_child() {
+        let mut scene = Scene::new();
+        let parent_transform = Transform::new()
+            .with_translation(vec3(10.0, 0.0, 0.0))
+            .with_rotation(quat_from_euler(0.0, 90.0_f32.to_radians(), 0.0))
+            .with_scale(vec3(2.0, 2.0, 2.0));
+        let child_transform = Transform::new().with_translation(vec3(1.0, 0.0, 0.0));
+        let parent = scene.add_root("parent", parent_transform);
+        let child = scene.add_child(parent, "child", child_transform);
+
+        let expected = parent_transform.local_matrix() * child_transform.local_matrix();
+        let world = scene.world_matrices();
+        assert_eq!(world[parent], parent_transform.local_matrix());
+        assert_near(&origin_of(&world[child]), &origin_of(&expected));
+        // Scaled by 2 and turned from +X onto -Z by the parent
+        let expected_origin = vec3(10.0, 0.0, -2.0);
+        assert_near(&origin_of(&world[child]), &expected_origin);
+        assert_near(&origin_of(&scene.world_matrix(child)), &expected_origin);
+    }
+
+    #[test]
+    fn hierarchy_links_and_lookup() {
+        let mut scene = Scene::new();
+        let root = scene.add_root("root", Transform::new());
+        let child = scene.add_child(root, "child", Transform::new());
+        assert_eq!(scene.node(child).parent(), Some(root));
+        assert_eq!(scene.node(root).children(), &[child]);
+        assert_eq!(scene.find("child"), Some(child));
+        assert_eq!(scene.roots().collect::<Vec<_>>(), vec![root]);
+    }
+}
@@ -0,0 +1,17 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 fcolor;
+
+in vec2 texcoord;
+
+uniform sampler2D tex;
+uniform sampler2D bloom;
+uniform float intensity;
+
+void main()
+{
+    vec4 color = texture(tex, texcoord);
+    fcolor = vec4(color.rgb + texture(bloom, texcoord).rgb * intensity, color.a);
+}
@@ -0,0 +1,21 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 fcolor;
+
+in vec2 texcoord;
+
+uniform sampler2D tex;
+uniform float threshold;
+
+void main()
+{
+    // Keep only what is brighter than the threshold, with a soft knee to
+    // avoid a hard edge where the bloom starts
+    vec3 color = texture(tex, texcoord).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float knee = clamp(brightness - threshold * 0.5, 0.0, threshold);
+    float contrib = max(brightness - threshold, knee * knee / (2.0 * threshold + 1e-4));
+    fcolor = vec4(color * contrib / max(brightness, 1e-4), 1.0);
+}
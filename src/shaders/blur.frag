@@ -0,0 +1,26 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 fcolor;
+
+in vec2 texcoord;
+
+uniform sampler2D tex;
+// One texel along the blur axis
+uniform vec2 direction;
+
+// 9 tap gaussian folded into 5 fetches by sampling between texels
+const float offsets[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float weights[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main()
+{
+    vec3 sum = texture(tex, texcoord).rgb * weights[0];
+    for (int i = 1; i < 3; ++i) {
+        vec2 offset = direction * offsets[i];
+        sum += texture(tex, texcoord + offset).rgb * weights[i];
+        sum += texture(tex, texcoord - offset).rgb * weights[i];
+    }
+    fcolor = vec4(sum, 1.0);
+}
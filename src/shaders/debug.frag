@@ -0,0 +1,27 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 fcolor;
+
+in vec2 texcoord;
+in vec3 normal;
+in vec3 ws_pos;
+
+// 0: world space normals, 1: texture coordinates, 2: flat shading
+uniform int mode;
+uniform vec3 light_pos;
+
+void main()
+{
+    if (mode == 0) {
+        fcolor = vec4(normalize(normal) * 0.5 + 0.5, 1.0);
+    } else if (mode == 1) {
+        fcolor = vec4(fract(texcoord), 0.0, 1.0);
+    } else {
+        // Face normal from the screen space derivatives of the position
+        vec3 n = normalize(cross(dFdx(ws_pos), dFdy(ws_pos)));
+        float diffuse = max(dot(n, normalize(light_pos - ws_pos)), 0.0);
+        fcolor = vec4(vec3(0.1 + 0.9 * diffuse), 1.0);
+    }
+}
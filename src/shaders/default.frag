@@ -10,12 +10,16 @@ in vec3 ws_pos;
 
 uniform sampler2D tex;
 uniform vec3 light_pos;
+uniform float alpha_cutoff;
 
 const vec3 light_color = vec3(1.0);
 
 void main()
 {
-    vec3 base_color = texture(tex, texcoord).rgb;
+    vec4 base = texture(tex, texcoord);
+    if (base.a < alpha_cutoff)
+        discard;
+    vec3 base_color = base.rgb;
     vec3 N = normalize(normal);
     vec3 L = normalize(light_pos - ws_pos);
     float kD = max(dot(N, L), 0.0);
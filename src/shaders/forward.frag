@@ -0,0 +1,73 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+// Forward shading with lists of point and spot lights, see graphics::light.
+// The array sizes must match MAX_POINT_LIGHTS and MAX_SPOT_LIGHTS there
+#define MAX_POINT_LIGHTS 8
+#define MAX_SPOT_LIGHTS 4
+
+out vec4 fcolor;
+
+in vec2 texcoord;
+in vec3 normal;
+in vec3 ws_pos;
+
+struct PointLight {
+    vec3 position;
+    vec3 color;
+    float radius;
+};
+
+struct SpotLight {
+    vec3 position;
+    vec3 direction;
+    vec3 color;
+    float cos_inner;
+    float cos_outer;
+};
+
+uniform sampler2D tex;
+uniform float alpha_cutoff;
+uniform PointLight point_lights[MAX_POINT_LIGHTS];
+uniform int num_point_lights;
+uniform SpotLight spot_lights[MAX_SPOT_LIGHTS];
+uniform int num_spot_lights;
+
+// Inverse square falloff, windowed to reach 0 at the light radius
+float point_falloff(float dist, float radius)
+{
+    float window = clamp(1.0 - pow(dist / radius, 4.0), 0.0, 1.0);
+    return window * window / (dist * dist + 1.0);
+}
+
+void main()
+{
+    vec4 base = texture(tex, texcoord);
+    if (base.a < alpha_cutoff)
+        discard;
+    vec3 N = normalize(normal);
+    vec3 light = vec3(0.0);
+    for (int i = 0; i < MAX_POINT_LIGHTS; ++i) {
+        if (i >= num_point_lights)
+            break;
+        vec3 to_light = point_lights[i].position - ws_pos;
+        float dist = length(to_light);
+        float kD = max(dot(N, to_light / dist), 0.0);
+        light += kD * point_falloff(dist, point_lights[i].radius) * point_lights[i].color;
+    }
+    for (int i = 0; i < MAX_SPOT_LIGHTS; ++i) {
+        if (i >= num_spot_lights)
+            break;
+        vec3 to_light = spot_lights[i].position - ws_pos;
+        float dist = length(to_light);
+        vec3 L = to_light / dist;
+        float cone = smoothstep(
+            spot_lights[i].cos_outer,
+            spot_lights[i].cos_inner,
+            dot(-L, spot_lights[i].direction));
+        float kD = max(dot(N, L), 0.0);
+        light += kD * cone / (dist * dist + 1.0) * spot_lights[i].color;
+    }
+    fcolor = vec4(light * base.rgb, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 300 es
+// Covers the screen with a single triangle, see Mesh::fullscreen_triangle
+in vec3 vpos;
+
+out vec2 texcoord;
+
+void main()
+{
+    texcoord = vpos.xy * 0.5 + 0.5;
+    gl_Position = vec4(vpos.xy, 0.0, 1.0);
+}
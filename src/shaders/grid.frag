@@ -0,0 +1,26 @@
+#version 300 es
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 fcolor;
+
+in vec3 ws_pos;
+
+uniform vec3 eye_pos;
+uniform vec4 line_color;
+uniform float cell_size;
+uniform float fade_dist;
+
+void main()
+{
+    // Distance to the nearest grid line in screen space pixels
+    vec2 coord = ws_pos.xz / cell_size;
+    vec2 grid = abs(fract(coord - 0.5) - 0.5) / fwidth(coord);
+    float line = 1.0 - min(min(grid.x, grid.y), 1.0);
+    // Fade out with distance from the viewer
+    float fade = 1.0 - clamp(length(ws_pos - eye_pos) / fade_dist, 0.0, 1.0);
+    float alpha = line_color.a * line * fade;
+    if (alpha <= 0.0)
+        discard;
+    fcolor = vec4(line_color.rgb, alpha);
+}
@@ -0,0 +1,13 @@
+#version 300 es
+in vec3 vpos;
+
+out vec3 ws_pos;
+
+uniform mat4 model;
+uniform mat4 vp;
+
+void main()
+{
+    ws_pos = (model * vec4(vpos, 1.0)).xyz;
+    gl_Position = vp * vec4(ws_pos, 1.0);
+}
@@ -0,0 +1,33 @@
+#version 300 es
+// Linear blend skinning on top of default.vert. The attribute locations
+// follow the Vattr order, so bind them with the attribute list
+// ["vpos", "vnrm", "vuv0", "vuv1", "vtan", "vcol", "vjnt", "vwgt"]
+in vec3 vpos;
+in vec3 vnrm;
+in vec2 vuv0;
+in vec4 vjnt;
+in vec4 vwgt;
+
+out vec2 texcoord;
+out vec3 normal;
+out vec3 ws_pos;
+
+const int MAX_JOINTS = 64;
+
+uniform mat4 model;
+uniform mat4 mvp;
+uniform mat3 nmm;
+uniform mat4 joints[MAX_JOINTS];
+
+void main()
+{
+    mat4 skin = vwgt.x * joints[int(vjnt.x)]
+              + vwgt.y * joints[int(vjnt.y)]
+              + vwgt.z * joints[int(vjnt.z)]
+              + vwgt.w * joints[int(vjnt.w)];
+    vec4 pos = skin * vec4(vpos, 1.0);
+    texcoord = vuv0;
+    ws_pos = (model * pos).xyz;
+    normal = nmm * mat3(skin) * vnrm;
+    gl_Position = mvp * pos;
+}